@@ -0,0 +1,392 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::{Int, SymbolTable};
+use crate::hash::HashMap;
+use crate::intcode::Opcodes;
+
+// Writes programs from Rust, with labels and the crate's standard calling
+// convention, so generated code and hand-built routines can call each other.
+//
+// The convention uses the relative base as the stack pointer: it points at
+// the first free cell, and the stack grows upwards from the end of the
+// program (`init_stack` sets this up). A call goes like this:
+//
+//     push 0          reserve the result slot, for functions with a result
+//     push arg0       arguments, in order
+//     ...
+//     call f          pushes the return address and jumps
+//     drop n          discards the arguments
+//     pop dest        takes the result
+//
+// Inside the function, its frame is, from the bottom of the stack up: the
+// result slot, the arguments, the return address, and then the locals,
+// which `function` reserves on entry. `ret` discards the locals and any
+// temporaries, and jumps back through the return address, popping it.
+//
+// The writer keeps track of how much has been pushed since the function
+// started, so `arg`, `local` and `result` give the right relative
+// parameter at any point. That only holds for straight-line pushes and
+// pops: every path to an instruction must leave the same amount on the
+// stack.
+//
+// A writer can also be turned into a relocatable `Object` instead of a
+// program: its code as if loaded at address 0, along with every word that
+// holds an address. Labels used but not defined become imports, to be found
+// in the other objects when linking. Objects can then be loaded at any base
+// address, so libraries of routines can be assembled once and shared.
+// Addresses given as plain numbers (e.g. `Param::Pos`) are left alone.
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Param {
+    Imm(Int),
+    Pos(Int),
+    Rel(Int),
+    // Address of a label, as an immediate value.
+    Label(String),
+    // The memory cell at a label.
+    Var(String),
+}
+
+impl Param {
+    pub fn label(name: &str) -> Self {
+        Self::Label(name.to_owned())
+    }
+
+    pub fn var(name: &str) -> Self {
+        Self::Var(name.to_owned())
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum LabelError {
+    Undefined(String),
+    Duplicate(String),
+}
+
+impl fmt::Display for LabelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Undefined(name) => write!(f, "Undefined label: {name}"),
+            Self::Duplicate(name) => write!(f, "Label defined twice: {name}"),
+        }
+    }
+}
+
+impl std::error::Error for LabelError {}
+
+// Word to fill in once every label is known.
+#[derive(Clone, Debug)]
+enum Fixup {
+    Label(String),
+    // Address right after the program.
+    End,
+    // Address in the program, already known.
+    Addr(Int),
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Frame {
+    args: Int,
+    locals: Int,
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct ProgramWriter {
+    code: Vec<Int>,
+    labels: HashMap<String, Int>,
+    fixups: Vec<(usize, Fixup)>,
+    duplicate: Option<String>,
+    frame: Option<Frame>,
+    // Cells pushed since the start of the current function.
+    depth: Int,
+}
+
+impl ProgramWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Address of the next word.
+    pub fn here(&self) -> Int {
+        self.code.len() as Int
+    }
+
+    pub fn label(&mut self, name: &str) {
+        self.label_at(name, self.here());
+    }
+
+    // Names an address other than the next one, such as the parameter of an
+    // instruction that gets patched while running.
+    pub fn label_at(&mut self, name: &str, addr: Int) {
+        if self.labels.insert(name.to_owned(), addr).is_some() {
+            self.duplicate.get_or_insert_with(|| name.to_owned());
+        }
+    }
+
+    pub fn address_of(&self, name: &str) -> Option<Int> {
+        self.labels.get(name).copied()
+    }
+
+    // Raw words, such as initialized variables.
+    pub fn data(&mut self, name: &str, values: &[Int]) {
+        self.label(name);
+        self.code.extend(values);
+    }
+
+    pub fn instr(&mut self, opcode: u8, params: &[Param]) {
+        assert_eq!(Opcodes::n_params(opcode), Some(params.len()), "Wrong number of parameters for opcode {opcode}");
+        let mut word = opcode as Int;
+        for (i, param) in params.iter().enumerate() {
+            let mode = match param {
+                Param::Pos(_) | Param::Var(_) => 0,
+                Param::Imm(_) | Param::Label(_) => 1,
+                Param::Rel(_) => 2,
+            };
+            word += mode * 10_i128.pow(i as u32 + 2);
+        }
+        self.code.push(word);
+
+        for param in params {
+            match param {
+                Param::Imm(val) | Param::Pos(val) | Param::Rel(val) => self.code.push(*val),
+                Param::Label(name) | Param::Var(name) => {
+                    self.fixups.push((self.code.len(), Fixup::Label(name.clone())));
+                    self.code.push(0);
+                },
+            }
+        }
+    }
+
+    pub fn add(&mut self, a: Param, b: Param, dest: Param) {
+        self.instr(Opcodes::ADD, &[a, b, dest]);
+    }
+
+    pub fn mul(&mut self, a: Param, b: Param, dest: Param) {
+        self.instr(Opcodes::MUL, &[a, b, dest]);
+    }
+
+    pub fn input(&mut self, dest: Param) {
+        self.instr(Opcodes::IN, &[dest]);
+    }
+
+    pub fn output(&mut self, val: Param) {
+        self.instr(Opcodes::OUT, &[val]);
+    }
+
+    pub fn jump_if_true(&mut self, cond: Param, target: Param) {
+        self.instr(Opcodes::JMP, &[cond, target]);
+    }
+
+    pub fn jump_if_false(&mut self, cond: Param, target: Param) {
+        self.instr(Opcodes::JMN, &[cond, target]);
+    }
+
+    pub fn jump(&mut self, target: Param) {
+        self.jump_if_true(Param::Imm(1), target);
+    }
+
+    pub fn less_than(&mut self, a: Param, b: Param, dest: Param) {
+        self.instr(Opcodes::LT, &[a, b, dest]);
+    }
+
+    pub fn equals(&mut self, a: Param, b: Param, dest: Param) {
+        self.instr(Opcodes::EQ, &[a, b, dest]);
+    }
+
+    pub fn adjust_base(&mut self, delta: Param) {
+        self.instr(Opcodes::RLB, &[delta]);
+    }
+
+    pub fn halt(&mut self) {
+        self.instr(Opcodes::END, &[]);
+    }
+
+    //////////////////////////////////////////////////////////////////////////////////////////////////
+
+    // Points the stack at the end of the program. Goes before anything else
+    // uses the stack.
+    pub fn init_stack(&mut self) {
+        self.code.push(109);
+        self.fixups.push((self.code.len(), Fixup::End));
+        self.code.push(0);
+        self.depth = 0;
+    }
+
+    pub fn push(&mut self, val: Param) {
+        self.add(val, Param::Imm(0), Param::Rel(0));
+        self.adjust_base(Param::Imm(1));
+        self.depth += 1;
+    }
+
+    // Copies the top of the stack first, so a relative `dest` means the same
+    // as before the pop.
+    pub fn pop(&mut self, dest: Param) {
+        self.add(Param::Rel(-1), Param::Imm(0), dest);
+        self.drop(1);
+    }
+
+    pub fn drop(&mut self, n: Int) {
+        self.adjust_base(Param::Imm(-n));
+        self.depth -= n;
+    }
+
+    pub fn call(&mut self, name: &str) {
+        // Return address: past this push and the jump
+        let start = self.here();
+        self.push(Param::Imm(start + 9));
+        self.fixups.push((start as usize + 1, Fixup::Addr(start + 9)));
+        self.jump(Param::label(name));
+        // The callee pops the return address
+        self.depth -= 1;
+    }
+
+    // Starts a function: its label, and room for its locals.
+    pub fn function(&mut self, name: &str, args: Int, locals: Int) {
+        self.label(name);
+        self.frame = Some(Frame { args, locals });
+        self.depth = 0;
+        if locals > 0 {
+            self.adjust_base(Param::Imm(locals));
+        }
+    }
+
+    pub fn ret(&mut self) {
+        let frame = self.current_frame();
+        let depth = self.depth;
+        self.adjust_base(Param::Imm(-(frame.locals + depth + 1)));
+        self.jump(Param::Rel(0));
+        // Code after a return is reached from elsewhere, with the same frame
+        self.depth = depth;
+    }
+
+    pub fn local(&self, i: Int) -> Param {
+        let frame = self.current_frame();
+        assert!((0..frame.locals).contains(&i), "No local {i}");
+        Param::Rel(i - frame.locals - self.depth)
+    }
+
+    pub fn arg(&self, i: Int) -> Param {
+        let frame = self.current_frame();
+        assert!((0..frame.args).contains(&i), "No argument {i}");
+        Param::Rel(i - frame.args - 1 - frame.locals - self.depth)
+    }
+
+    // Only meaningful if the caller reserved the slot.
+    pub fn result(&self) -> Param {
+        let frame = self.current_frame();
+        Param::Rel(-frame.args - 2 - frame.locals - self.depth)
+    }
+
+    fn current_frame(&self) -> Frame {
+        self.frame.expect("Not inside a function")
+    }
+
+    //////////////////////////////////////////////////////////////////////////////////////////////////
+
+    // Every label, for debugging the program.
+    pub fn symbols(&self) -> SymbolTable {
+        // Sorted, so addresses with several labels always get the same one
+        let mut labels: Vec<_> = self.labels.iter().collect();
+        labels.sort();
+        let mut symbols = SymbolTable::new();
+        for (name, &addr) in labels {
+            symbols.insert(addr, name);
+        }
+        symbols
+    }
+
+    pub fn finish(self) -> Result<Vec<Int>, LabelError> {
+        link(&[self.finish_object()?])
+    }
+
+    pub fn finish_object(mut self) -> Result<Object, LabelError> {
+        if let Some(name) = self.duplicate {
+            return Err(LabelError::Duplicate(name));
+        }
+        let mut object = Object { exports: self.labels.iter().map(|(name, &addr)| (name.clone(), addr)).collect(), ..Default::default() };
+        for (pos, fixup) in self.fixups {
+            match fixup {
+                Fixup::Label(name) => match self.labels.get(&name) {
+                    Some(&addr) => {
+                        self.code[pos] = addr;
+                        object.relocations.push(pos);
+                    },
+                    None => object.imports.push((pos, name)),
+                },
+                Fixup::End => object.ends.push(pos),
+                Fixup::Addr(addr) => {
+                    self.code[pos] = addr;
+                    object.relocations.push(pos);
+                },
+            }
+        }
+        object.code = self.code;
+        Ok(object)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Object {
+    // Assembled at address 0.
+    pub code: Vec<Int>,
+    // Words holding an address in the object, which moves along with it.
+    pub relocations: Vec<usize>,
+    // Words holding the address of a label defined elsewhere.
+    pub imports: Vec<(usize, String)>,
+    // Words holding the address right after the whole program.
+    pub ends: Vec<usize>,
+    // Every label, relative to the start of the object.
+    pub exports: BTreeMap<String, Int>,
+}
+
+impl Object {
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+
+    // The code placed at `base`, given where the whole program ends and the
+    // address of every imported label.
+    pub fn load_at(&self, base: Int, end: Int, resolve: impl Fn(&str) -> Option<Int>) -> Result<Vec<Int>, LabelError> {
+        let mut code = self.code.clone();
+        for &pos in &self.relocations {
+            code[pos] += base;
+        }
+        for (pos, name) in &self.imports {
+            code[*pos] = resolve(name).ok_or_else(|| LabelError::Undefined(name.clone()))?;
+        }
+        for &pos in &self.ends {
+            code[pos] = end;
+        }
+        Ok(code)
+    }
+}
+
+// Places the objects one after the other, resolving each one's imports with
+// the labels of the others. A label defined by more than one is an error.
+pub fn link(objects: &[Object]) -> Result<Vec<Int>, LabelError> {
+    let mut bases = vec![];
+    let mut labels: HashMap<&str, Int> = HashMap::default();
+    let mut end = 0;
+    for object in objects {
+        for (name, &addr) in &object.exports {
+            if labels.insert(name, end + addr).is_some() {
+                return Err(LabelError::Duplicate(name.clone()));
+            }
+        }
+        bases.push(end);
+        end += object.len() as Int;
+    }
+
+    let mut program = vec![];
+    for (object, base) in objects.iter().zip(bases) {
+        program.extend(object.load_at(base, end, |name| labels.get(name).copied())?);
+    }
+    Ok(program)
+}
@@ -0,0 +1,408 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+
+use crate::{IntcodeComputer, IntcodeError, Int, RunResult};
+use crate::intcode::Opcodes;
+use crate::hash::HashMap;
+
+// Static value-range analysis (abstract interpretation over intervals).
+//
+// Starting from the initial memory, every reachable instruction is given a
+// conservative approximation of the memory at that point: a range for every
+// cell the program may have written, while untouched cells keep their
+// initial value. Inputs are unknown, so they can take any value. Loops are
+// handled by widening ranges that keep growing to unbounded.
+//
+// Instructions are decoded from the approximated memory, so self-modifying
+// code is followed as long as the modified words are known exactly. Whenever
+// an instruction or a jump target can't be pinned down, the analysis stops
+// following that path and reports it in `Analysis::unknown`.
+
+// Number of times an instruction is revisited before its ranges are widened.
+const WIDEN_AFTER: u32 = 3;
+
+// Passes recomputing every state from its predecessors after widening, to
+// win back the precision lost by it. Every pass is sound on its own, so this
+// only bounds how far the precision travels.
+const NARROWING_PASSES: usize = 100;
+
+// Hard limit on the work done, as a safety net.
+const MAX_VISITS: usize = 1_000_000;
+
+// Inclusive range of values. `Int::MIN` and `Int::MAX` stand for unbounded.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Range {
+    pub lo: Int,
+    pub hi: Int,
+}
+
+impl Range {
+    pub const ANY: Range = Range { lo: Int::MIN, hi: Int::MAX };
+
+    pub fn exact(val: Int) -> Self {
+        Self { lo: val, hi: val }
+    }
+
+    pub fn as_exact(&self) -> Option<Int> {
+        (self.lo == self.hi).then_some(self.lo)
+    }
+
+    pub fn contains(&self, val: Int) -> bool {
+        self.lo <= val && val <= self.hi
+    }
+
+    pub fn join(self, other: Self) -> Self {
+        Self { lo: self.lo.min(other.lo), hi: self.hi.max(other.hi) }
+    }
+
+    // Like `join`, but bounds that moved become unbounded.
+    fn widen(self, other: Self) -> Self {
+        Self {
+            lo: if other.lo < self.lo { Int::MIN } else { self.lo },
+            hi: if other.hi > self.hi { Int::MAX } else { self.hi },
+        }
+    }
+
+    fn add(self, other: Self) -> Self {
+        Self { lo: self.lo.saturating_add(other.lo), hi: self.hi.saturating_add(other.hi) }
+    }
+
+    fn mul(self, other: Self) -> Self {
+        let corners = [
+            self.lo.saturating_mul(other.lo), self.lo.saturating_mul(other.hi),
+            self.hi.saturating_mul(other.lo), self.hi.saturating_mul(other.hi),
+        ];
+        Self { lo: *corners.iter().min().unwrap(), hi: *corners.iter().max().unwrap() }
+    }
+
+    fn less_than(self, other: Self) -> Self {
+        if self.hi < other.lo {
+            Self::exact(1)
+        } else if self.lo >= other.hi {
+            Self::exact(0)
+        } else {
+            Self { lo: 0, hi: 1 }
+        }
+    }
+
+    fn equals(self, other: Self) -> Self {
+        match (self.as_exact(), other.as_exact()) {
+            (Some(a), Some(b)) if a == b => Self::exact(1),
+            _ if self.hi < other.lo || other.hi < self.lo => Self::exact(0),
+            _ => Self { lo: 0, hi: 1 },
+        }
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bound = |val: Int| match val {
+            Int::MIN => "-inf".to_owned(),
+            Int::MAX => "inf".to_owned(),
+            _ => val.to_string(),
+        };
+        write!(f, "[{}, {}]", bound(self.lo), bound(self.hi))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Branch {
+    AlwaysTaken,
+    NeverTaken,
+    Both,
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Analysis {
+    // Addresses of the instructions that may be executed.
+    pub reachable: BTreeSet<Int>,
+    // Values every cell written by the program may hold, at any point.
+    pub ranges: BTreeMap<Int, Range>,
+    // Outcome of every reachable conditional jump.
+    pub branches: BTreeMap<Int, Branch>,
+    // Relative base at every reachable instruction where it's always the same.
+    pub rel_bases: BTreeMap<Int, Int>,
+    // Instructions the analysis couldn't decode or follow.
+    pub unknown: BTreeSet<Int>,
+}
+
+impl Analysis {
+    // Whether the results cover every possible execution.
+    pub fn is_complete(&self) -> bool {
+        self.unknown.is_empty()
+    }
+}
+
+// Approximated machine state at one instruction.
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct State {
+    // Cells that may differ from their initial value.
+    written: BTreeMap<Int, Range>,
+    // Set after a write to an unknown address: any cell may hold anything.
+    clobbered: bool,
+    rel_base: Option<Int>,
+}
+
+impl State {
+    fn read(&self, code: &[Int], addr: Int) -> Range {
+        match self.written.get(&addr) {
+            Some(&range) => range,
+            None if self.clobbered => Range::ANY,
+            None => Range::exact(usize::try_from(addr).ok().and_then(|a| code.get(a)).copied().unwrap_or_default()),
+        }
+    }
+
+    fn write(&mut self, addr: Option<Int>, value: Range) {
+        match addr {
+            Some(addr) => {
+                self.written.insert(addr, value);
+            },
+            None => {
+                self.written.clear();
+                self.clobbered = true;
+            },
+        }
+    }
+
+    fn merge(&self, other: &Self, code: &[Int], widen: bool) -> Self {
+        let clobbered = self.clobbered || other.clobbered;
+        let keys: BTreeSet<Int> = self.written.keys().chain(other.written.keys()).copied().collect();
+        let written = keys.into_iter().map(|addr| {
+            let (old, new) = (self.read(code, addr), other.read(code, addr));
+            (addr, if widen { old.widen(new) } else { old.join(new) })
+        }).filter(|&(_, range)| !clobbered || range != Range::ANY).collect();
+        let rel_base = if self.rel_base == other.rel_base { self.rel_base } else { None };
+        Self { written, clobbered, rel_base }
+    }
+}
+
+// Decoded instruction: opcode, and for every parameter its address (`None`
+// for immediate ones, or relative ones with an unknown base) and raw word.
+struct Decoded {
+    opcode: u8,
+    params: Vec<(Option<Int>, Int, u8)>,
+}
+
+fn decode(state: &State, code: &[Int], ip: Int) -> Option<Decoded> {
+    let instr = state.read(code, ip).as_exact()?;
+    let opcode = u8::try_from(instr % 100).ok()?;
+    let n_params = match opcode {
+        Opcodes::ADD | Opcodes::MUL | Opcodes::LT | Opcodes::EQ => 3,
+        Opcodes::JMP | Opcodes::JMN => 2,
+        Opcodes::IN | Opcodes::OUT | Opcodes::RLB => 1,
+        Opcodes::END => 0,
+        _ => return None,
+    };
+
+    let mut params = vec![];
+    for i in 0..n_params {
+        let raw = state.read(code, ip.checked_add(1 + i as Int)?).as_exact()?;
+        let mode = (instr / 10_i128.pow(i as u32 + 2) % 10) as u8;
+        // Overflowing addresses make the instruction fail, so it can't be followed
+        let addr = match (mode, state.rel_base) {
+            (0, _) => Some(raw),
+            (1, _) => None,
+            (2, Some(base)) => Some(base.checked_add(raw)?),
+            (2, None) => None,
+            _ => return None,
+        };
+        params.push((addr, raw, mode));
+    }
+    Some(Decoded { opcode, params })
+}
+
+fn value(state: &State, code: &[Int], (addr, raw, mode): (Option<Int>, Int, u8)) -> Range {
+    match (mode, addr) {
+        (1, _) => Range::exact(raw),
+        (_, Some(addr)) => state.read(code, addr),
+        _ => Range::ANY,
+    }
+}
+
+// Successors of an instruction, with the state they start with. `None` when
+// the instruction can't be analyzed.
+fn step(state: &State, code: &[Int], ip: Int) -> Option<Vec<(Int, State)>> {
+    let Decoded { opcode, params } = decode(state, code, ip)?;
+    let next_ip = ip.checked_add(1 + params.len() as Int)?;
+    let mut next = state.clone();
+
+    match opcode {
+        Opcodes::ADD | Opcodes::MUL | Opcodes::LT | Opcodes::EQ => {
+            let (a, b) = (value(state, code, params[0]), value(state, code, params[1]));
+            let result = match opcode {
+                Opcodes::ADD => a.add(b),
+                Opcodes::MUL => a.mul(b),
+                Opcodes::LT => a.less_than(b),
+                _ => a.equals(b),
+            };
+            next.write(params[2].0, result);
+        },
+        Opcodes::IN => next.write(params[0].0, Range::ANY),
+        Opcodes::OUT => {},
+        Opcodes::JMP | Opcodes::JMN => {
+            let (taken, not_taken) = branch_outcomes(opcode, value(state, code, params[0]));
+            let mut succ = vec![];
+            if not_taken {
+                succ.push((next_ip, next.clone()));
+            }
+            if taken {
+                succ.push((value(state, code, params[1]).as_exact()?, next));
+            }
+            return Some(succ);
+        },
+        Opcodes::RLB => {
+            let delta = value(state, code, params[0]).as_exact();
+            next.rel_base = match state.rel_base.zip(delta) {
+                Some((base, delta)) => Some(base.checked_add(delta)?),
+                None => None,
+            };
+        },
+        _ => return Some(vec![]),
+    }
+
+    Some(vec![(next_ip, next)])
+}
+
+// Whether a jump may be taken, and whether it may fall through.
+fn branch_outcomes(opcode: u8, cond: Range) -> (bool, bool) {
+    let may_be_zero = cond.contains(0);
+    let may_be_nonzero = cond != Range::exact(0);
+    if opcode == Opcodes::JMP { (may_be_nonzero, may_be_zero) } else { (may_be_zero, may_be_nonzero) }
+}
+
+pub fn analyze(code: &[Int]) -> Analysis {
+    let mut states: HashMap<Int, State> = HashMap::default();
+    let mut visits: HashMap<Int, u32> = HashMap::default();
+    let mut queue = VecDeque::from([0]);
+    let mut analysis = Analysis::default();
+    let initial = State { written: BTreeMap::new(), clobbered: false, rel_base: Some(0) };
+    states.insert(0, initial.clone());
+
+    let mut budget = MAX_VISITS;
+    while let Some(ip) = queue.pop_front() {
+        budget -= 1;
+        if budget == 0 {
+            // Without a fixpoint the results don't cover every execution
+            analysis.unknown.insert(ip);
+            return analysis;
+        }
+
+        let Some(successors) = step(&states[&ip], code, ip) else {
+            analysis.unknown.insert(ip);
+            continue;
+        };
+
+        for (next_ip, state) in successors {
+            let merged = match states.get(&next_ip) {
+                None => state,
+                Some(old) => {
+                    let count = visits.entry(next_ip).or_default();
+                    *count += 1;
+                    let merged = old.merge(&state, code, *count > WIDEN_AFTER);
+                    if merged == *old {
+                        continue;
+                    }
+                    merged
+                },
+            };
+            states.insert(next_ip, merged);
+            if !queue.contains(&next_ip) {
+                queue.push_back(next_ip);
+            }
+        }
+    }
+
+    for _ in 0..NARROWING_PASSES {
+        let mut narrowed: HashMap<Int, State> = HashMap::default();
+        narrowed.insert(0, initial.clone());
+        for (&ip, state) in &states {
+            for (next_ip, state) in step(state, code, ip).unwrap_or_default() {
+                let merged = match narrowed.get(&next_ip) {
+                    Some(old) => old.merge(&state, code, false),
+                    None => state,
+                };
+                narrowed.insert(next_ip, merged);
+            }
+        }
+        if narrowed == states {
+            break;
+        }
+        states = narrowed;
+    }
+
+    // With the states settled, collect the results
+    for (&ip, state) in &states {
+        if analysis.unknown.contains(&ip) && decode(state, code, ip).is_none() {
+            continue;
+        }
+        analysis.reachable.insert(ip);
+        if let Some(base) = state.rel_base {
+            analysis.rel_bases.insert(ip, base);
+        }
+        for (&addr, &range) in &state.written {
+            let initial = Range::exact(usize::try_from(addr).ok().and_then(|a| code.get(a)).copied().unwrap_or_default());
+            let known = analysis.ranges.get(&addr).copied().unwrap_or(initial);
+            analysis.ranges.insert(addr, known.join(range));
+        }
+
+        if let Some(Decoded { opcode: opcode @ (Opcodes::JMP | Opcodes::JMN), params }) = decode(state, code, ip) {
+            let branch = match branch_outcomes(opcode, value(state, code, params[0])) {
+                (true, false) => Branch::AlwaysTaken,
+                (false, true) => Branch::NeverTaken,
+                _ => Branch::Both,
+            };
+            analysis.branches.insert(ip, branch);
+        }
+    }
+
+    analysis
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Bounded halting analysis, for screening generated or fuzzed programs. The
+// program is run with the inputs for a number of steps, detecting repeated
+// states along the way. If that's not enough to tell, the range analysis
+// above may still prove that END can never be reached.
+
+// Instructions between checks for repeated states.
+const CYCLE_CHECK_INTERVAL: u64 = 64;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum HaltVerdict {
+    // Reached an END instruction.
+    Halts { steps: u64 },
+    // Stopped because of an error, including running out of inputs.
+    Fails(IntcodeError),
+    // Can't ever reach an END instruction or stop for lack of input, so it
+    // runs forever (or until some value overflows).
+    Loops,
+    Unknown,
+}
+
+pub fn will_halt_within(program: &[Int], inputs: &[Int], max_steps: u64) -> HaltVerdict {
+    let mut comp = IntcodeComputer::new(program);
+    comp.input_iter(inputs.iter().copied());
+    comp.set_step_limit(Some(max_steps));
+    comp.set_cycle_detection(Some(CYCLE_CHECK_INTERVAL));
+    loop {
+        match comp.try_run() {
+            Ok(RunResult::Output(_)) => {},
+            Ok(RunResult::Finished) => return HaltVerdict::Halts { steps: comp.steps() },
+            Err(IntcodeError::InfiniteLoop { .. }) => return HaltVerdict::Loops,
+            Err(IntcodeError::StepLimit { .. }) => break,
+            Err(e) => return HaltVerdict::Fails(e),
+        }
+    }
+
+    // Instructions that may have been modified could be anything
+    let analysis = analyze(program);
+    let may_stop = analysis.reachable.iter().any(|&ip| {
+        let opcode = usize::try_from(ip).ok().and_then(|ip| program.get(ip)).map_or(0, |word| word % 100);
+        analysis.ranges.contains_key(&ip) || opcode == Opcodes::END as Int || opcode == Opcodes::IN as Int
+    });
+    match analysis.is_complete() && !may_stop {
+        true => HaltVerdict::Loops,
+        false => HaltVerdict::Unknown,
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::VecDeque;
+
+use crate::{IntcodeComputer, IntcodeError, RunResult};
+use crate::ascii::ascii_char;
+use crate::hash::{HashMap, HashSet};
+
+// Day 25: the text adventure. The ship is mapped depth-first, picking up
+// every item that is safe to carry, and then every combination of items is
+// tried on the pressure-sensitive floor next to the security checkpoint
+// until the droid is let through and the password is revealed.
+//
+// The explorer works over any `TextGame`, so it can be driven by something
+// other than an Intcode program.
+
+// Items that end or block the game in the known puzzle inputs.
+pub const DEFAULT_BLACKLIST: [&str; 5] = ["escape pod", "giant electromagnet", "infinite loop", "molten lava", "photons"];
+
+// Instructions an Intcode game may execute per command before it's
+// considered stuck (e.g. after picking up the infinite loop).
+pub const COMMAND_STEPS: u64 = 1_000_000;
+
+const CHECKPOINT: &str = "Security Checkpoint";
+const PROMPT: &str = "Command?";
+
+pub trait TextGame: Clone {
+    // Output before the first command.
+    fn start(&mut self) -> Option<String>;
+
+    // Sends a command, returning the reply, or `None` if the game stopped
+    // responding.
+    fn send(&mut self, command: &str) -> Option<String>;
+}
+
+impl TextGame for IntcodeComputer {
+    fn start(&mut self) -> Option<String> {
+        read_reply(self)
+    }
+
+    fn send(&mut self, command: &str) -> Option<String> {
+        self.input_line(command);
+        read_reply(self)
+    }
+}
+
+fn read_reply(comp: &mut IntcodeComputer) -> Option<String> {
+    let limit = comp.step_limit();
+    comp.set_step_limit(Some(comp.steps() + COMMAND_STEPS));
+
+    let mut text = String::new();
+    let reply = loop {
+        match comp.try_run() {
+            Ok(RunResult::Output(val)) => match ascii_char(val) {
+                Some(c) => text.push(c),
+                None => text.push_str(&val.to_string()),
+            },
+            Ok(RunResult::Finished) | Err(IntcodeError::NoInput { .. }) => break Some(text),
+            Err(_) => break None,
+        }
+    };
+
+    comp.set_step_limit(limit);
+    reply
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Room {
+    pub name: String,
+    pub description: String,
+    pub doors: Vec<String>,
+    pub items: Vec<String>,
+}
+
+// Parses the last room described in the text, since being sent back by the
+// pressure-sensitive floor describes two rooms in a single reply.
+pub fn parse_room(text: &str) -> Option<Room> {
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    let start = lines.iter().rposition(|l| l.len() > 6 && l.starts_with("== ") && l.ends_with(" =="))?;
+
+    let mut room = Room { name: lines[start][3..lines[start].len() - 3].to_owned(), ..Default::default() };
+    let mut section = None;
+
+    for line in &lines[start + 1..] {
+        match *line {
+            "Doors here lead:" => section = Some(&mut room.doors),
+            "Items here:" => section = Some(&mut room.items),
+            "" => section = None,
+            _ => match (line.strip_prefix("- "), &mut section) {
+                (Some(entry), Some(list)) => list.push(entry.to_owned()),
+                (None, _) if room.description.is_empty() => room.description = line.to_string(),
+                _ => {},
+            },
+        }
+    }
+
+    Some(room)
+}
+
+// Whether the game is still waiting for a command after this reply.
+fn is_alive(reply: &str) -> bool {
+    reply.trim_end().ends_with(PROMPT)
+}
+
+fn opposite(door: &str) -> &str {
+    match door {
+        "north" => "south",
+        "south" => "north",
+        "east" => "west",
+        "west" => "east",
+        other => other,
+    }
+}
+
+pub struct AutoExplorer<G: TextGame> {
+    game: G,
+    blacklist: HashSet<String>,
+    // Doors between rooms, by room name.
+    map: HashMap<String, Vec<(String, String)>>,
+    inventory: Vec<String>,
+    // Room and door leading to the pressure-sensitive floor.
+    plate: Option<(String, String)>,
+}
+
+impl<G: TextGame> AutoExplorer<G> {
+    pub fn new(game: G) -> Self {
+        Self {
+            game,
+            blacklist: DEFAULT_BLACKLIST.iter().map(|&s| s.to_owned()).collect(),
+            map: HashMap::default(),
+            inventory: vec![],
+            plate: None,
+        }
+    }
+
+    // Replaces the items that are never picked up.
+    pub fn with_blacklist<S: AsRef<str>>(mut self, items: &[S]) -> Self {
+        self.blacklist = items.iter().map(|s| s.as_ref().to_owned()).collect();
+        self
+    }
+
+    // Items picked up while exploring.
+    pub fn inventory(&self) -> &[String] {
+        &self.inventory
+    }
+
+    // Explores the ship and brute-forces the checkpoint, returning the
+    // password for the main airlock.
+    pub fn solve(&mut self) -> Option<String> {
+        let start = parse_room(&self.game.start()?)?;
+        let start_name = start.name.clone();
+        self.visit(start, None)?;
+
+        let (checkpoint, door) = self.plate.clone()?;
+        for command in self.route(&start_name, &checkpoint)? {
+            self.send(&command)?;
+        }
+        self.brute_force(&checkpoint, &door)
+    }
+
+    fn send(&mut self, command: &str) -> Option<String> {
+        self.game.send(command).filter(|reply| is_alive(reply))
+    }
+
+    fn visit(&mut self, room: Room, from: Option<&str>) -> Option<()> {
+        self.map.insert(room.name.clone(), vec![]);
+
+        for item in &room.items {
+            if !self.blacklist.contains(item) && self.is_safe(item) {
+                self.send(&format!("take {item}"))?;
+                self.inventory.push(item.clone());
+            }
+        }
+
+        for door in &room.doors {
+            if from.is_some_and(|d| opposite(d) == door) {
+                continue;
+            }
+
+            let reply = self.send(door)?;
+            let next = parse_room(&reply)?;
+            if next.name == room.name {
+                // Sent back by the pressure-sensitive floor
+                if room.name == CHECKPOINT {
+                    self.plate = Some((room.name.clone(), door.clone()));
+                }
+                continue;
+            }
+
+            self.map.get_mut(&room.name)?.push((door.clone(), next.name.clone()));
+            if !self.map.contains_key(&next.name) {
+                self.visit(next, Some(door))?;
+            }
+            self.send(opposite(door))?;
+        }
+
+        Some(())
+    }
+
+    // Picks the item up in a copy of the game, to check that it's still
+    // playable afterwards.
+    fn is_safe(&self, item: &str) -> bool {
+        let mut game = self.game.clone();
+        game.send(&format!("take {item}")).is_some_and(|r| is_alive(&r))
+            && game.send("inv").is_some_and(|r| is_alive(&r))
+    }
+
+    // Commands leading between two explored rooms.
+    fn route(&self, from: &str, to: &str) -> Option<Vec<String>> {
+        let mut prev: HashMap<&str, (&str, &str)> = HashMap::default();
+        let mut queue = VecDeque::from([from]);
+
+        while let Some(room) = queue.pop_front() {
+            if room == to {
+                let mut path = vec![];
+                let mut cur = to;
+                while cur != from {
+                    let (before, door) = prev[cur];
+                    path.push(door.to_owned());
+                    cur = before;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for (door, next) in self.map.get(room)? {
+                if next != from && !prev.contains_key(next.as_str()) {
+                    prev.insert(next, (room, door));
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        None
+    }
+
+    // Tries every subset of the inventory in Gray code order, so that only
+    // one item has to be taken or dropped between attempts.
+    fn brute_force(&mut self, checkpoint: &str, door: &str) -> Option<String> {
+        let items = self.inventory.clone();
+        if items.len() >= usize::BITS as usize {
+            return None;
+        }
+        let mut held = (1usize << items.len()) - 1;
+
+        for i in 0..1usize << items.len() {
+            let wanted = i ^ (i >> 1);
+            for (bit, item) in items.iter().enumerate() {
+                let mask = 1 << bit;
+                if (held ^ wanted) & mask != 0 {
+                    let verb = if wanted & mask != 0 { "take" } else { "drop" };
+                    self.send(&format!("{verb} {item}"))?;
+                }
+            }
+            held = wanted;
+
+            let reply = self.game.send(door)?;
+            if parse_room(&reply).is_some_and(|r| r.name == checkpoint) && is_alive(&reply) {
+                continue;
+            }
+            return password(&reply);
+        }
+
+        None
+    }
+}
+
+// The password is the number the droid is told to type on the keypad.
+fn password(reply: &str) -> Option<String> {
+    let after = &reply[reply.find("typing")?..];
+    let digits: String = after.chars().skip_while(|c| !c.is_ascii_digit()).take_while(char::is_ascii_digit).collect();
+    (!digits.is_empty()).then_some(digits)
+}
@@ -0,0 +1,67 @@
+use crate::{IntcodeComputer, Int, RunResult};
+use crate::search::{best_permutation, par_best_permutation};
+
+// Day 7: amplifier controller software. Every amplifier runs its own copy of
+// the program, receiving its phase setting first and then an input signal.
+
+// Runs one amplifier per phase setting in series, feeding each output
+// signal into the next amplifier. Returns the last amplifier's output.
+pub fn run_amplifier_chain(program: &IntcodeComputer, phases: &[Int], initial: Int) -> Int {
+    phases.iter().fold(initial, |signal, &phase| {
+        let mut amp = program.clone();
+        amp.input(phase);
+        amp.input(signal);
+        match amp.run() {
+            RunResult::Output(val) => val,
+            RunResult::Finished => panic!("Amplifier with phase {phase} produced no output"),
+        }
+    })
+}
+
+// Tries every ordering of the phase settings, returning the highest signal
+// sent to the thrusters and the phases that produce it (part 1), or `None`
+// without any phase settings.
+pub fn max_chain_signal(program: &IntcodeComputer, phases: &[Int]) -> Option<(Int, Vec<Int>)> {
+    if phases.is_empty() {
+        return None;
+    }
+    best_permutation(phases, |perm| run_amplifier_chain(program, perm, 0))
+}
+
+// Feedback loop mode: the amplifiers stay alive, and the last one's output
+// goes back into the first until they halt. Returns the last signal sent
+// to the thrusters by the last amplifier. Without any amplifiers, the loop
+// would never end.
+pub fn run_feedback_loop(program: &IntcodeComputer, phases: &[Int]) -> Int {
+    assert!(!phases.is_empty(), "At least one phase setting is needed");
+    let mut amps: Vec<IntcodeComputer> = phases.iter().map(|&phase| {
+        let mut amp = program.clone();
+        amp.input(phase);
+        amp
+    }).collect();
+
+    let mut signal = 0;
+    let mut thrusters = None;
+    'outer: loop {
+        for (i, amp) in amps.iter_mut().enumerate() {
+            amp.input(signal);
+            match amp.run() {
+                RunResult::Output(val) => signal = val,
+                RunResult::Finished => break 'outer,
+            }
+            if i == phases.len() - 1 {
+                thrusters = Some(signal);
+            }
+        }
+    }
+
+    thrusters.expect("The amplifiers halted before sending any signal to the thrusters")
+}
+
+// Same as `max_chain_signal`, in feedback loop mode (part 2).
+pub fn max_feedback_signal(program: &IntcodeComputer, phases: &[Int]) -> Option<(Int, Vec<Int>)> {
+    if phases.is_empty() {
+        return None;
+    }
+    par_best_permutation(phases, |perm| run_feedback_loop(program, perm))
+}
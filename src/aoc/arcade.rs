@@ -0,0 +1,137 @@
+use crate::{IntcodeComputer, IntcodeError, Int};
+use crate::hash::HashMap;
+
+// Day 13: the arcade cabinet. Outputs come in `(x, y, tile)` triplets, except
+// for `(-1, 0, score)`, and the joystick is read whenever input is needed.
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Tile {
+    Empty,
+    Wall,
+    Block,
+    Paddle,
+    Ball,
+}
+
+impl Tile {
+    pub fn from_id(id: Int) -> Option<Self> {
+        match id {
+            0 => Some(Self::Empty),
+            1 => Some(Self::Wall),
+            2 => Some(Self::Block),
+            3 => Some(Self::Paddle),
+            4 => Some(Self::Ball),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct GameState {
+    pub tiles: HashMap<(Int, Int), Tile>,
+    pub score: Int,
+    pub ball: Option<(Int, Int)>,
+    pub paddle: Option<(Int, Int)>,
+}
+
+impl GameState {
+    pub fn blocks(&self) -> usize {
+        self.tiles.values().filter(|&&t| t == Tile::Block).count()
+    }
+}
+
+// Decides the joystick position (-1 left, 0 neutral, 1 right) every frame.
+pub trait Strategy {
+    fn joystick(&mut self, state: &GameState) -> Int;
+}
+
+// Moves the paddle towards the ball. Enough to beat the game.
+#[derive(Default, Copy, Clone, Debug)]
+pub struct FollowBall;
+
+impl Strategy for FollowBall {
+    fn joystick(&mut self, state: &GameState) -> Int {
+        match (state.ball, state.paddle) {
+            (Some((ball, _)), Some((paddle, _))) => (ball - paddle).signum(),
+            _ => 0,
+        }
+    }
+}
+
+impl<F: FnMut(&GameState) -> Int> Strategy for F {
+    fn joystick(&mut self, state: &GameState) -> Int {
+        self(state)
+    }
+}
+
+pub struct Arcade {
+    comp: IntcodeComputer,
+    state: GameState,
+}
+
+impl Arcade {
+    pub fn new(comp: IntcodeComputer) -> Self {
+        Self { comp, state: GameState::default() }
+    }
+
+    // Sets memory address 0 to 2 to play for free (part 2).
+    pub fn insert_quarters(&mut self) {
+        self.comp.write_at(0, 2);
+    }
+
+    pub fn state(&self) -> &GameState {
+        &self.state
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.comp.is_finished()
+    }
+
+    // Runs until the game asks for the joystick or ends, updating the state.
+    // Returns whether the game is still running.
+    pub fn run_frame(&mut self) -> bool {
+        loop {
+            match self.comp.run_until_n_outputs() {
+                Ok(Some([x, y, id])) => self.update(x, y, id),
+                Ok(None) => return false,
+                Err(IntcodeError::NoInput { .. }) => return true,
+                Err(e) => panic!("{e}"),
+            }
+        }
+    }
+
+    pub fn push_joystick(&mut self, position: Int) {
+        self.comp.input(position);
+    }
+
+    // Plays until the game ends, returning the final score.
+    pub fn play(&mut self, strategy: &mut impl Strategy) -> Int {
+        self.play_with(strategy, |_| {})
+    }
+
+    // Same as `play`, calling `on_frame` whenever a frame is complete.
+    pub fn play_with(&mut self, strategy: &mut impl Strategy, mut on_frame: impl FnMut(&GameState)) -> Int {
+        while self.run_frame() {
+            on_frame(&self.state);
+            let position = strategy.joystick(&self.state);
+            self.push_joystick(position);
+        }
+        on_frame(&self.state);
+        self.state.score
+    }
+
+    fn update(&mut self, x: Int, y: Int, id: Int) {
+        if (x, y) == (-1, 0) {
+            self.state.score = id;
+            return;
+        }
+
+        let tile = Tile::from_id(id).unwrap_or_else(|| panic!("Unknown tile id: {id}"));
+        match tile {
+            Tile::Ball => self.state.ball = Some((x, y)),
+            Tile::Paddle => self.state.paddle = Some((x, y)),
+            _ => {},
+        }
+        self.state.tiles.insert((x, y), tile);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::{IntcodeComputer, Int, RunResult};
+use crate::hash::HashMap;
+
+// Day 19: the tractor beam. Every query runs a fresh copy of the drone
+// program with `x, y` as inputs and gets back whether the point is pulled.
+// Queries are cached, and the beam is tracked row by row: assuming it's a
+// cone coming out of the origin, each row is a single span whose ends never
+// move left, so rows can be scanned starting where the previous one began.
+
+pub struct BeamScanner {
+    comp: IntcodeComputer,
+    cache: HashMap<(Int, Int), bool>,
+    spans: HashMap<Int, Option<(Int, Int)>>,
+    scan_limit: Int,
+    queries: usize,
+}
+
+impl BeamScanner {
+    pub fn new(comp: IntcodeComputer) -> Self {
+        Self { comp, cache: HashMap::default(), spans: HashMap::default(), scan_limit: 50, queries: 0 }
+    }
+
+    // How far past the previous row's start to look for the beam before
+    // declaring a row empty. Very thin beams may need a larger value.
+    pub fn with_scan_limit(mut self, limit: Int) -> Self {
+        self.scan_limit = limit;
+        self
+    }
+
+    pub fn query(&mut self, x: Int, y: Int) -> bool {
+        if x < 0 || y < 0 {
+            return false;
+        }
+        if let Some(&pulled) = self.cache.get(&(x, y)) {
+            return pulled;
+        }
+
+        let mut drone = self.comp.clone();
+        drone.input(x);
+        drone.input(y);
+        let pulled = match drone.run() {
+            RunResult::Output(val) => val == 1,
+            RunResult::Finished => panic!("The drone program produced no output"),
+        };
+
+        self.queries += 1;
+        self.cache.insert((x, y), pulled);
+        pulled
+    }
+
+    // Number of times the drone program has actually been run.
+    pub fn queries(&self) -> usize {
+        self.queries
+    }
+
+    // First and last x pulled by the beam in the given row, if any.
+    pub fn row_span(&mut self, y: Int) -> Option<(Int, Int)> {
+        if let Some(&span) = self.spans.get(&y) {
+            return span;
+        }
+
+        // Start from the closest row above that has been tracked
+        let (hint_start, hint_end) = (0..y).rev()
+            .find_map(|prev| self.spans.get(&prev).copied().flatten())
+            .unwrap_or((0, 0));
+
+        let span = (hint_start..=hint_start + self.scan_limit.max(y)).find(|&x| self.query(x, y)).map(|start| {
+            let mut end = hint_end.max(start);
+            while self.query(end + 1, y) {
+                end += 1;
+            }
+            (start, end)
+        });
+
+        self.spans.insert(y, span);
+        span
+    }
+
+    // Points pulled inside the `width` x `height` area at the origin (part 1).
+    pub fn count_in(&mut self, width: Int, height: Int) -> usize {
+        (0..height).map(|y| match self.row_span(y) {
+            Some((start, end)) => (end.min(width - 1) - start + 1).max(0) as usize,
+            None => 0,
+        }).sum()
+    }
+
+    // Top-left corner of the closest `size` x `size` square that fits
+    // entirely inside the beam (part 2). Rows are scanned by the square's
+    // bottom-left corner, which must sit on the start of a row span.
+    pub fn fit_square(&mut self, size: Int) -> (Int, Int) {
+        for y in size - 1.. {
+            // Make sure rows are tracked in order, so hints stay accurate
+            let Some((x, _)) = self.row_span(y) else { continue };
+            let top = y - size + 1;
+            if self.query(x + size - 1, top) {
+                return (x, top);
+            }
+        }
+        unreachable!()
+    }
+}
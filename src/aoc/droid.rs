@@ -0,0 +1,122 @@
+use std::collections::VecDeque;
+
+use crate::{IntcodeComputer, Int, RunResult};
+use crate::hash::HashMap;
+
+// Day 15: the repair droid. It takes movement commands (1 north, 2 south,
+// 3 west, 4 east) and replies with a status (0 hit a wall, 1 moved, 2 moved
+// and found the oxygen system). The area is mapped breadth-first, forking
+// the droid's computer at every open position instead of backtracking.
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Cell {
+    Wall,
+    Open,
+    Oxygen,
+}
+
+// Commands and their offsets, with y growing southwards.
+const MOVES: [(Int, (Int, Int)); 4] = [(1, (0, -1)), (2, (0, 1)), (3, (-1, 0)), (4, (1, 0))];
+
+#[derive(Clone, Debug)]
+pub struct Exploration {
+    // Everything discovered, relative to the starting position (0, 0).
+    pub map: HashMap<(Int, Int), Cell>,
+    pub oxygen: Option<(Int, Int)>,
+}
+
+pub struct DroidExplorer {
+    comp: IntcodeComputer,
+}
+
+impl DroidExplorer {
+    pub fn new(comp: IntcodeComputer) -> Self {
+        Self { comp }
+    }
+
+    pub fn explore(&self) -> Exploration {
+        let mut map = HashMap::default();
+        let mut oxygen = None;
+        let mut queue = VecDeque::from([((0, 0), self.comp.clone())]);
+        map.insert((0, 0), Cell::Open);
+
+        while let Some((pos, comp)) = queue.pop_front() {
+            for (command, (dx, dy)) in MOVES {
+                let next = (pos.0 + dx, pos.1 + dy);
+                if map.contains_key(&next) {
+                    continue;
+                }
+
+                let mut droid = comp.clone();
+                droid.input(command);
+                let cell = match droid.run() {
+                    RunResult::Output(0) => Cell::Wall,
+                    RunResult::Output(1) => Cell::Open,
+                    RunResult::Output(2) => Cell::Oxygen,
+                    other => panic!("Unexpected droid status: {other:?}"),
+                };
+
+                map.insert(next, cell);
+                if cell != Cell::Wall {
+                    if cell == Cell::Oxygen {
+                        oxygen = Some(next);
+                    }
+                    queue.push_back((next, droid));
+                }
+            }
+        }
+
+        Exploration { map, oxygen }
+    }
+}
+
+impl Exploration {
+    // Number of moves to every reachable position from the given one.
+    pub fn distances_from(&self, start: (Int, Int)) -> HashMap<(Int, Int), usize> {
+        let mut dists = HashMap::default();
+        let mut queue = VecDeque::from([(start, 0)]);
+        dists.insert(start, 0);
+
+        while let Some((pos, dist)) = queue.pop_front() {
+            for (_, (dx, dy)) in MOVES {
+                let next = (pos.0 + dx, pos.1 + dy);
+                let open = self.map.get(&next).is_some_and(|&c| c != Cell::Wall);
+                if open && !dists.contains_key(&next) {
+                    dists.insert(next, dist + 1);
+                    queue.push_back((next, dist + 1));
+                }
+            }
+        }
+
+        dists
+    }
+
+    // Fewest moves from the start to the oxygen system (part 1).
+    pub fn shortest_path_to_oxygen(&self) -> Option<usize> {
+        self.oxygen.map(|o| self.distances_from((0, 0))[&o])
+    }
+
+    // Minutes until oxygen fills every open position (part 2).
+    pub fn fill_time(&self) -> Option<usize> {
+        self.oxygen.map(|o| self.distances_from(o).into_values().max().unwrap())
+    }
+
+    // The map as text: `#` walls, `.` open, `O` oxygen, `D` start.
+    pub fn render(&self) -> String {
+        let min_x = self.map.keys().map(|p| p.0).min().unwrap();
+        let max_x = self.map.keys().map(|p| p.0).max().unwrap();
+        let min_y = self.map.keys().map(|p| p.1).min().unwrap();
+        let max_y = self.map.keys().map(|p| p.1).max().unwrap();
+
+        let rows: Vec<String> = (min_y..=max_y).map(|y| {
+            (min_x..=max_x).map(|x| match (x, y, self.map.get(&(x, y))) {
+                (0, 0, _) => 'D',
+                (_, _, Some(Cell::Wall)) => '#',
+                (_, _, Some(Cell::Open)) => '.',
+                (_, _, Some(Cell::Oxygen)) => 'O',
+                (_, _, None) => ' ',
+            }).collect()
+        }).collect();
+        rows.join("\n")
+    }
+}
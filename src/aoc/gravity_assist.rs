@@ -0,0 +1,35 @@
+use crate::{IntcodeComputer, Int};
+use crate::search::par_find;
+
+// Day 2: the gravity assist program. Its inputs are the values at addresses
+// 1 and 2 (the noun and the verb), and its result is left at address 0.
+
+// Both the noun and the verb go from 0 to 99.
+pub const NOUN_VERB_RANGE: Int = 100;
+
+impl IntcodeComputer {
+    // Runs a copy of this computer with the given noun and verb, returning
+    // the value left at address 0.
+    pub fn run_with_noun_verb(&self, noun: Int, verb: Int) -> Int {
+        let mut comp = self.clone();
+        comp.write_at(1, noun);
+        comp.write_at(2, verb);
+        comp.run();
+        comp.read_at(0)
+    }
+
+    // First noun and verb (in that order) producing the target.
+    pub fn find_noun_verb(&self, target: Int) -> Option<(Int, Int)> {
+        (0..NOUN_VERB_RANGE)
+            .flat_map(|noun| (0..NOUN_VERB_RANGE).map(move |verb| (noun, verb)))
+            .find(|&(noun, verb)| self.run_with_noun_verb(noun, verb) == target)
+    }
+
+    // Same as `find_noun_verb`, trying nouns and verbs on all available
+    // threads.
+    pub fn par_find_noun_verb(&self, target: Int) -> Option<(Int, Int)> {
+        let range = 0..=NOUN_VERB_RANGE * NOUN_VERB_RANGE - 1;
+        let accepts = |p| self.run_with_noun_verb(p / NOUN_VERB_RANGE, p % NOUN_VERB_RANGE) == target;
+        par_find(range, &accepts).map(|p| (p / NOUN_VERB_RANGE, p % NOUN_VERB_RANGE))
+    }
+}
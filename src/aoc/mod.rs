@@ -0,0 +1,12 @@
+// Drivers for the Advent of Code 2019 puzzles built on top of the computer.
+pub mod adventure;
+pub mod amplifiers;
+pub mod arcade;
+pub mod beam;
+pub mod droid;
+pub mod gravity_assist;
+pub mod network;
+pub mod ocr;
+pub mod painting;
+pub mod scaffold;
+pub mod springscript;
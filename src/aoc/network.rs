@@ -0,0 +1,232 @@
+use std::io::{self, Write};
+
+use crate::{IntcodeComputer, IntcodeError, Int, RunResult};
+
+// Day 23: a network of computers exchanging `(destination, x, y)` packets.
+// Every computer first receives its address, then reads incoming packets,
+// getting -1 when there's nothing for it. Packets sent to address 255 go to
+// the NAT, which wakes up computer 0 whenever the whole network is idle.
+//
+// Computers never block waiting for each other: the network advances in
+// ticks, where every computer gets its pending packets (or a -1) and runs
+// until it asks for input again.
+
+pub const NAT_ADDRESS: Int = 255;
+
+// Number of consecutive empty reads every computer needs before the network
+// is considered idle. A single -1 isn't enough: a computer may still be busy
+// between reads, or be about to act on a packet it just received.
+pub const DEFAULT_IDLE_THRESHOLD: usize = 2;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Packet {
+    pub src: Int,
+    pub dest: Int,
+    pub x: Int,
+    pub y: Int,
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct TickReport {
+    pub sent: Vec<Packet>,
+    // Whether the network is considered idle after this tick.
+    pub idle: bool,
+}
+
+// Tracks, for every computer, how many times in a row it has read from an
+// empty queue without sending anything. The network is idle once all of
+// them reach the threshold. It can be fed by any scheduler, not just
+// `Network`.
+#[derive(Clone, Debug)]
+pub struct IdleDetector {
+    threshold: usize,
+    streaks: Vec<usize>,
+}
+
+impl IdleDetector {
+    pub fn new(computers: usize, threshold: usize) -> Self {
+        Self { threshold: threshold.max(1), streaks: vec![0; computers] }
+    }
+
+    // Records one read by a computer: whether it got real input (instead of
+    // a -1), and whether it sent anything since its previous read.
+    pub fn record(&mut self, computer: usize, got_input: bool, sent: bool) {
+        let streak = &mut self.streaks[computer];
+        *streak = if got_input || sent { 0 } else { *streak + 1 };
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.streaks.iter().all(|&s| s >= self.threshold)
+    }
+
+    pub fn reset(&mut self) {
+        self.streaks.fill(0);
+    }
+}
+
+// A routed packet together with the tick it was delivered on.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct LoggedPacket {
+    pub tick: u64,
+    pub packet: Packet,
+}
+
+type PacketHook = Box<dyn FnMut(&LoggedPacket)>;
+
+pub struct Network {
+    computers: Vec<IntcodeComputer>,
+    // Outputs of every computer not yet forming a full packet.
+    partial: Vec<Vec<Int>>,
+    nat: Option<(Int, Int)>,
+    idle: IdleDetector,
+    ticks: u64,
+    hooks: Vec<PacketHook>,
+    log: Option<Vec<LoggedPacket>>,
+}
+
+impl Network {
+    pub fn new(program: &IntcodeComputer, size: usize) -> Self {
+        let computers = (0..size).map(|addr| {
+            let mut comp = program.clone();
+            comp.input(addr as Int);
+            comp
+        }).collect();
+        Self {
+            computers,
+            partial: vec![vec![]; size],
+            nat: None,
+            idle: IdleDetector::new(size, DEFAULT_IDLE_THRESHOLD),
+            ticks: 0,
+            hooks: vec![],
+            log: None,
+        }
+    }
+
+    pub fn with_idle_threshold(mut self, threshold: usize) -> Self {
+        self.idle = IdleDetector::new(self.computers.len(), threshold);
+        self
+    }
+
+    // Calls `hook` for every routed packet, including the ones sent to and
+    // from the NAT (whose address is used as the source when it wakes up
+    // computer 0).
+    pub fn on_packet(&mut self, hook: impl FnMut(&LoggedPacket) + 'static) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    // Starts keeping every routed packet in memory.
+    pub fn enable_log(&mut self) {
+        self.log.get_or_insert_with(Vec::new);
+    }
+
+    pub fn packet_log(&self) -> &[LoggedPacket] {
+        self.log.as_deref().unwrap_or(&[])
+    }
+
+    // Writes the packet log, one packet per line: `<tick> <src> -> <dest>: <x>,<y>`
+    pub fn dump_log(&self, mut out: impl Write) -> io::Result<()> {
+        for LoggedPacket { tick, packet } in self.packet_log() {
+            writeln!(out, "{tick} {} -> {}: {},{}", packet.src, packet.dest, packet.x, packet.y)?;
+        }
+        Ok(())
+    }
+
+    // Last packet received by the NAT.
+    pub fn nat(&self) -> Option<(Int, Int)> {
+        self.nat
+    }
+
+    // Number of ticks run so far.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn tick(&mut self) -> TickReport {
+        let mut sent = vec![];
+
+        for src in 0..self.computers.len() {
+            let comp = &mut self.computers[src];
+            if comp.is_finished() {
+                // Halted computers don't keep the network busy
+                self.idle.record(src, false, false);
+                continue;
+            }
+            let got_input = comp.pending_inputs() > 0;
+            if !got_input {
+                comp.input(-1);
+            }
+            let sent_before = sent.len();
+
+            loop {
+                match comp.try_run() {
+                    Ok(RunResult::Output(val)) => {
+                        let partial = &mut self.partial[src];
+                        partial.push(val);
+                        if let [dest, x, y] = partial[..] {
+                            partial.clear();
+                            sent.push(Packet { src: src as Int, dest, x, y });
+                        }
+                    },
+                    Ok(RunResult::Finished) | Err(IntcodeError::NoInput { .. }) => break,
+                    Err(e) => panic!("Computer {src}: {e}"),
+                }
+            }
+
+            let sent_now = sent.len() > sent_before || !self.partial[src].is_empty();
+            self.idle.record(src, got_input, sent_now);
+        }
+
+        for &packet in &sent {
+            self.route(packet);
+        }
+        self.ticks += 1;
+
+        TickReport { idle: self.idle.is_idle(), sent }
+    }
+
+    // Y value of the first packet sent to the NAT (part 1).
+    pub fn first_nat_packet(&mut self) -> Int {
+        loop {
+            let report = self.tick();
+            if let Some(packet) = report.sent.iter().find(|p| p.dest == NAT_ADDRESS) {
+                return packet.y;
+            }
+        }
+    }
+
+    // Runs with the NAT waking up computer 0 whenever the network is idle,
+    // until it delivers the same Y value twice in a row (part 2).
+    pub fn run_nat(&mut self) -> Int {
+        let mut last_y = None;
+        loop {
+            if !self.tick().idle {
+                continue;
+            }
+            let (x, y) = self.nat.expect("The network is idle but the NAT has no packet");
+            if last_y == Some(y) {
+                return y;
+            }
+            last_y = Some(y);
+            self.route(Packet { src: NAT_ADDRESS, dest: 0, x, y });
+            self.idle.reset();
+        }
+    }
+
+    fn route(&mut self, packet: Packet) {
+        let Packet { dest, x, y, .. } = packet;
+        if dest == NAT_ADDRESS {
+            self.nat = Some((x, y));
+        } else if let Some(comp) = usize::try_from(dest).ok().and_then(|d| self.computers.get_mut(d)) {
+            comp.input(x);
+            comp.input(y);
+        }
+
+        let logged = LoggedPacket { tick: self.ticks, packet };
+        for hook in &mut self.hooks {
+            hook(&logged);
+        }
+        if let Some(log) = &mut self.log {
+            log.push(logged);
+        }
+    }
+}
@@ -0,0 +1,84 @@
+// Recognition of the 6 pixel tall block letters used by several puzzles
+// (the day 11 registration identifier, day 8 images...). Lit pixels are
+// `#` or `█`, anything else is blank. Letters are split on blank columns,
+// and anything unrecognized comes out as `?`.
+
+const HEIGHT: usize = 6;
+
+const GLYPHS: &[(char, [&str; HEIGHT])] = &[
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Y', ["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#.."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+type Pixels = Vec<Vec<bool>>;
+
+pub fn ocr(grid: &str) -> String {
+    let mut rows: Pixels = grid.lines().map(|l| l.chars().map(is_lit).collect()).collect();
+    // Blank rows around the text are ignored
+    while rows.first().is_some_and(|r| !r.contains(&true)) {
+        rows.remove(0);
+    }
+    while rows.last().is_some_and(|r| !r.contains(&true)) {
+        rows.pop();
+    }
+    if rows.is_empty() {
+        return String::new();
+    }
+
+    let width = rows.iter().map(Vec::len).max().unwrap();
+    for row in &mut rows {
+        row.resize(width, false);
+    }
+    let lit_column = |x: usize| rows.iter().any(|r| r[x]);
+
+    let mut text = String::new();
+    let mut x = 0;
+    while x < width {
+        if !lit_column(x) {
+            x += 1;
+            continue;
+        }
+        let start = x;
+        while x < width && lit_column(x) {
+            x += 1;
+        }
+        let letter: Pixels = rows.iter().map(|r| r[start..x].to_vec()).collect();
+        text.push(recognize(&letter));
+    }
+
+    text
+}
+
+fn recognize(letter: &Pixels) -> char {
+    GLYPHS.iter()
+        .find(|(_, glyph)| &trim_columns(glyph.iter().map(|r| r.chars().map(is_lit).collect()).collect()) == letter)
+        .map(|(c, _)| *c)
+        .unwrap_or('?')
+}
+
+fn trim_columns(pixels: Pixels) -> Pixels {
+    let width = pixels[0].len();
+    let lit: Vec<usize> = (0..width).filter(|&x| pixels.iter().any(|r| r[x])).collect();
+    let (first, last) = (lit[0], lit[lit.len() - 1]);
+    pixels.into_iter().map(|r| r[first..=last].to_vec()).collect()
+}
+
+fn is_lit(c: char) -> bool {
+    c == '#' || c == '█'
+}
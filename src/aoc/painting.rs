@@ -0,0 +1,102 @@
+use crate::{IntcodeComputer, IntcodeError, Int, RunResult};
+use crate::hash::HashMap;
+
+// Day 11: the hull painting robot. Whenever the program asks for input, it
+// gets the color of the panel below the robot (0 black, 1 white). It then
+// outputs the color to paint and the direction to turn (0 left, 1 right),
+// after which the robot moves forward one panel.
+
+pub const BLACK: Int = 0;
+pub const WHITE: Int = 1;
+
+pub struct PaintingRobot {
+    comp: IntcodeComputer,
+    panels: HashMap<(Int, Int), Int>,
+    // Color of the starting panel until it's painted.
+    start_color: Int,
+    pos: (Int, Int),
+    // Unit vector, with y growing downwards. The robot starts facing up.
+    dir: (Int, Int),
+}
+
+impl PaintingRobot {
+    pub fn new(comp: IntcodeComputer) -> Self {
+        Self { comp, panels: HashMap::default(), start_color: BLACK, pos: (0, 0), dir: (0, -1) }
+    }
+
+    // Color of the starting panel before the robot is started (part 2). It
+    // doesn't count as painted.
+    pub fn with_start_color(mut self, color: Int) -> Self {
+        self.start_color = color;
+        self
+    }
+
+    // Runs the program to completion.
+    pub fn run(&mut self) {
+        let mut outputs = vec![];
+
+        loop {
+            match self.comp.try_run() {
+                Ok(RunResult::Output(val)) => {
+                    outputs.push(val);
+                    if let [color, turn] = outputs[..] {
+                        outputs.clear();
+                        self.paint_and_move(color, turn);
+                    }
+                },
+                Ok(RunResult::Finished) => break,
+                Err(IntcodeError::NoInput { .. }) => self.comp.input(self.color_at(self.pos)),
+                Err(e) => panic!("{e}"),
+            }
+        }
+    }
+
+    pub fn color_at(&self, pos: (Int, Int)) -> Int {
+        let default = if pos == (0, 0) { self.start_color } else { BLACK };
+        self.panels.get(&pos).copied().unwrap_or(default)
+    }
+
+    // Panels painted at least once, with their current color.
+    pub fn panels(&self) -> &HashMap<(Int, Int), Int> {
+        &self.panels
+    }
+
+    pub fn painted_count(&self) -> usize {
+        self.panels.len()
+    }
+
+    pub fn position(&self) -> (Int, Int) {
+        self.pos
+    }
+
+    // The hull as text, one line per row, with `#` for white and `.` for
+    // black panels, cropped to the white ones.
+    pub fn render(&self) -> String {
+        let white: Vec<(Int, Int)> = self.panels.keys().chain([&(0, 0)])
+            .filter(|&&p| self.color_at(p) == WHITE)
+            .copied()
+            .collect();
+        let Some(min_x) = white.iter().map(|p| p.0).min() else {
+            return String::new();
+        };
+        let max_x = white.iter().map(|p| p.0).max().unwrap();
+        let min_y = white.iter().map(|p| p.1).min().unwrap();
+        let max_y = white.iter().map(|p| p.1).max().unwrap();
+
+        let rows: Vec<String> = (min_y..=max_y).map(|y| {
+            (min_x..=max_x).map(|x| if self.color_at((x, y)) == WHITE { '#' } else { '.' }).collect()
+        }).collect();
+        rows.join("\n")
+    }
+
+    fn paint_and_move(&mut self, color: Int, turn: Int) {
+        self.panels.insert(self.pos, color);
+        let (dx, dy) = self.dir;
+        self.dir = match turn {
+            0 => (dy, -dx),
+            1 => (-dy, dx),
+            x => panic!("Unknown turn direction: {x}"),
+        };
+        self.pos = (self.pos.0 + self.dir.0, self.pos.1 + self.dir.1);
+    }
+}
@@ -0,0 +1,201 @@
+use std::fmt;
+
+use crate::{IntcodeComputer, Int};
+
+// Day 17: the scaffolding around the ship, as seen by the ASCII camera, and
+// the movement routines that make the vacuum robot walk all of it.
+
+const MAX_ROUTINE_LEN: usize = 20;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Turn {
+    Left,
+    Right,
+}
+
+// Turn, then walk forward a number of cells.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Move {
+    pub turn: Turn,
+    pub dist: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Scaffold {
+    rows: Vec<Vec<u8>>,
+    // Position and facing direction (unit vector, y grows downwards).
+    robot: Option<((Int, Int), (Int, Int))>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Routines {
+    pub main: String,
+    pub functions: [String; 3],
+}
+
+impl Scaffold {
+    pub fn parse(camera: &str) -> Self {
+        let rows: Vec<Vec<u8>> = camera.lines().filter(|l| !l.is_empty()).map(|l| l.bytes().collect()).collect();
+        let mut robot = None;
+
+        for (y, row) in rows.iter().enumerate() {
+            for (x, &c) in row.iter().enumerate() {
+                let dir = match c {
+                    b'^' => (0, -1),
+                    b'v' => (0, 1),
+                    b'<' => (-1, 0),
+                    b'>' => (1, 0),
+                    _ => continue,
+                };
+                robot = Some(((x as Int, y as Int), dir));
+            }
+        }
+
+        Self { rows, robot }
+    }
+
+    // Runs the camera program and parses what it shows.
+    pub fn from_camera(comp: &mut IntcodeComputer) -> Self {
+        Self::parse(&comp.run_ascii().text)
+    }
+
+    pub fn is_scaffold(&self, (x, y): (Int, Int)) -> bool {
+        if x < 0 || y < 0 {
+            return false;
+        }
+        let cell = self.rows.get(y as usize).and_then(|r| r.get(x as usize));
+        matches!(cell, Some(b'#' | b'^' | b'v' | b'<' | b'>'))
+    }
+
+    pub fn intersections(&self) -> Vec<(Int, Int)> {
+        let mut found = vec![];
+        for (y, row) in self.rows.iter().enumerate() {
+            for x in 0..row.len() {
+                let (x, y) = (x as Int, y as Int);
+                let cross = [(x, y), (x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)];
+                if cross.into_iter().all(|p| self.is_scaffold(p)) {
+                    found.push((x, y));
+                }
+            }
+        }
+        found
+    }
+
+    // Sum of the alignment parameters of all intersections (part 1).
+    pub fn alignment_sum(&self) -> Int {
+        self.intersections().iter().map(|(x, y)| x * y).sum()
+    }
+
+    // Walks straight through intersections, only turning at corners, until
+    // the end of the scaffold.
+    pub fn path(&self) -> Vec<Move> {
+        let Some((mut pos, mut dir)) = self.robot else {
+            return vec![];
+        };
+        let mut moves = vec![];
+
+        loop {
+            let left = (dir.1, -dir.0);
+            let right = (-dir.1, dir.0);
+            let (turn, new_dir) = if self.is_scaffold((pos.0 + left.0, pos.1 + left.1)) {
+                (Turn::Left, left)
+            } else if self.is_scaffold((pos.0 + right.0, pos.1 + right.1)) {
+                (Turn::Right, right)
+            } else {
+                break;
+            };
+
+            dir = new_dir;
+            let mut dist = 0;
+            while self.is_scaffold((pos.0 + dir.0, pos.1 + dir.1)) {
+                pos = (pos.0 + dir.0, pos.1 + dir.1);
+                dist += 1;
+            }
+            moves.push(Move { turn, dist });
+        }
+
+        moves
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let turn = match self.turn {
+            Turn::Left => 'L',
+            Turn::Right => 'R',
+        };
+        write!(f, "{turn},{}", self.dist)
+    }
+}
+
+pub fn path_string(moves: &[Move]) -> String {
+    let parts: Vec<String> = moves.iter().map(Move::to_string).collect();
+    parts.join(",")
+}
+
+// Splits a path into a main routine calling up to three movement functions,
+// with every routine fitting in the robot's memory.
+pub fn compress(path: &[Move]) -> Option<Routines> {
+    let mut functions = vec![];
+    let mut main = vec![];
+    if !search(path, &mut functions, &mut main) {
+        return None;
+    }
+
+    let calls: Vec<&str> = main.iter().map(|&i| ["A", "B", "C"][i]).collect();
+    let mut names = functions.iter().map(|f| path_string(f)).chain(std::iter::repeat(String::new()));
+    let functions = [names.next().unwrap(), names.next().unwrap(), names.next().unwrap()];
+    Some(Routines { main: calls.join(","), functions })
+}
+
+fn search<'a>(rest: &'a [Move], functions: &mut Vec<&'a [Move]>, main: &mut Vec<usize>) -> bool {
+    if rest.is_empty() {
+        return true;
+    }
+    // Every call takes two characters in the main routine, counting the comma
+    if main.len() * 2 + 1 > MAX_ROUTINE_LEN {
+        return false;
+    }
+
+    for i in 0..functions.len() {
+        let func = functions[i];
+        if rest.starts_with(func) {
+            main.push(i);
+            if search(&rest[func.len()..], functions, main) {
+                return true;
+            }
+            main.pop();
+        }
+    }
+
+    // Longer functions first, as they lead to shorter main routines
+    if functions.len() < 3 {
+        for len in (1..=rest.len()).rev() {
+            let func = &rest[..len];
+            if path_string(func).len() > MAX_ROUTINE_LEN {
+                continue;
+            }
+            functions.push(func);
+            main.push(functions.len() - 1);
+            if search(&rest[len..], functions, main) {
+                return true;
+            }
+            main.pop();
+            functions.pop();
+        }
+    }
+
+    false
+}
+
+impl Routines {
+    // Queues the routines as the robot expects them (part 2), answering the
+    // continuous video feed prompt as requested.
+    pub fn feed(&self, comp: &mut IntcodeComputer, video: bool) {
+        comp.input_line(&self.main);
+        for func in &self.functions {
+            comp.input_line(func);
+        }
+        comp.input_line(if video { "y" } else { "n" });
+    }
+}
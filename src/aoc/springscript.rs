@@ -0,0 +1,263 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{AsciiStop, IntcodeComputer, Int};
+use crate::parallel::n_threads;
+
+// Day 21: springscript programs for the springdroid, built from typed
+// instructions and serialized to the ASCII input the droid expects.
+
+pub const MAX_INSTRUCTIONS: usize = 15;
+
+// Registers that can be read. A-D are the sensors available when walking,
+// E-I are only available when running, T and J are the writable ones.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Reg {
+    A, B, C, D, E, F, G, H, I, T, J,
+}
+
+// Registers that can be written.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Writable {
+    T,
+    J,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Op {
+    And,
+    Or,
+    Not,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Mode {
+    Walk,
+    Run,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Instruction {
+    pub op: Op,
+    pub src: Reg,
+    pub dst: Writable,
+}
+
+pub fn and(src: Reg, dst: Writable) -> Instruction {
+    Instruction { op: Op::And, src, dst }
+}
+
+pub fn or(src: Reg, dst: Writable) -> Instruction {
+    Instruction { op: Op::Or, src, dst }
+}
+
+pub fn not(src: Reg, dst: Writable) -> Instruction {
+    Instruction { op: Op::Not, src, dst }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SpringscriptError {
+    TooManyInstructions(usize),
+    // A sensor that is only available in RUN mode was used when walking.
+    NeedsRunMode(Reg),
+}
+
+impl fmt::Display for SpringscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TooManyInstructions(n) => write!(f, "{n} instructions, at most {MAX_INSTRUCTIONS} are allowed"),
+            Self::NeedsRunMode(reg) => write!(f, "Register {reg} can only be read in RUN mode"),
+        }
+    }
+}
+
+impl std::error::Error for SpringscriptError {}
+
+#[derive(Clone, Default, PartialEq, Eq, Hash, Debug)]
+pub struct Springscript {
+    pub instructions: Vec<Instruction>,
+}
+
+impl Springscript {
+    pub fn new(instructions: &[Instruction]) -> Self {
+        Self { instructions: instructions.to_vec() }
+    }
+
+    pub fn then(mut self, instruction: Instruction) -> Self {
+        self.instructions.push(instruction);
+        self
+    }
+
+    pub fn validate(&self, mode: Mode) -> Result<(), SpringscriptError> {
+        if self.instructions.len() > MAX_INSTRUCTIONS {
+            return Err(SpringscriptError::TooManyInstructions(self.instructions.len()));
+        }
+        if mode == Mode::Walk {
+            if let Some(instr) = self.instructions.iter().find(|i| i.src.needs_run()) {
+                return Err(SpringscriptError::NeedsRunMode(instr.src));
+            }
+        }
+        Ok(())
+    }
+
+    // The program as ASCII text, ending with the WALK or RUN command.
+    pub fn to_ascii(&self, mode: Mode) -> Result<String, SpringscriptError> {
+        self.validate(mode)?;
+        let mut text: String = self.instructions.iter().map(|i| format!("{i}\n")).collect();
+        text.push_str(match mode {
+            Mode::Walk => "WALK\n",
+            Mode::Run => "RUN\n",
+        });
+        Ok(text)
+    }
+
+    pub fn feed(&self, comp: &mut IntcodeComputer, mode: Mode) -> Result<(), SpringscriptError> {
+        comp.input_str(&self.to_ascii(mode)?);
+        Ok(())
+    }
+}
+
+impl Reg {
+    pub const ALL: [Reg; 11] = [Reg::A, Reg::B, Reg::C, Reg::D, Reg::E, Reg::F, Reg::G, Reg::H, Reg::I, Reg::T, Reg::J];
+
+    pub fn needs_run(self) -> bool {
+        matches!(self, Reg::E | Reg::F | Reg::G | Reg::H | Reg::I)
+    }
+}
+
+impl From<Writable> for Reg {
+    fn from(reg: Writable) -> Self {
+        match reg {
+            Writable::T => Reg::T,
+            Writable::J => Reg::J,
+        }
+    }
+}
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl fmt::Display for Writable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self.op {
+            Op::And => "AND",
+            Op::Or => "OR",
+            Op::Not => "NOT",
+        };
+        write!(f, "{op} {} {}", self.src, self.dst)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+// Brute-force search
+
+// Every instruction that can be written in the given mode.
+pub fn alphabet(mode: Mode) -> Vec<Instruction> {
+    let mut instructions = vec![];
+    for op in [Op::And, Op::Or, Op::Not] {
+        for src in Reg::ALL.into_iter().filter(|r| mode == Mode::Run || !r.needs_run()) {
+            for dst in [Writable::T, Writable::J] {
+                instructions.push(Instruction { op, src, dst });
+            }
+        }
+    }
+    instructions
+}
+
+// Rejects programs that are equivalent to a shorter one: both registers
+// start as false, so AND into or OR from a register never written is a
+// no-op, repeating an AND or OR changes nothing, and the last instruction
+// must write J for the program to do anything.
+fn is_pruned(program: &[Instruction]) -> bool {
+    let mut written = [false; 2];
+    for (i, instr) in program.iter().enumerate() {
+        let dst = instr.dst as usize;
+        let reads_unwritten = match instr.src {
+            Reg::T => !written[Writable::T as usize],
+            Reg::J => !written[Writable::J as usize],
+            _ => false,
+        };
+        if (instr.op == Op::And && !written[dst]) || (instr.op == Op::Or && reads_unwritten) {
+            return true;
+        }
+        if instr.op != Op::Not && i > 0 && program[i - 1] == *instr {
+            return true;
+        }
+        written[dst] = true;
+    }
+    program.last().is_none_or(|i| i.dst != Writable::J)
+}
+
+// Tries every springscript program up to `max_len` instructions, shortest
+// first, against the droid program. Returns the first one (in enumeration
+// order) whose run ends in a non-ASCII value, along with that value.
+// Candidates are split between all available threads. Lengths with more
+// candidates than fit in a u64 are out of reach anyway and not searched.
+pub fn search(comp: &IntcodeComputer, mode: Mode, max_len: usize) -> Option<(Springscript, Int)> {
+    let alphabet = alphabet(mode);
+    let n_threads = n_threads();
+
+    for len in 1..=max_len.min(MAX_INSTRUCTIONS) {
+        let Some(total) = (alphabet.len() as u64).checked_pow(len as u32) else { break };
+        let best = AtomicU64::new(u64::MAX);
+        let found = Mutex::new(None);
+
+        std::thread::scope(|scope| {
+            for worker in 0..n_threads {
+                let (alphabet, best, found) = (&alphabet, &best, &found);
+                scope.spawn(move || {
+                    let mut index = worker as u64;
+                    // Candidates after one that already worked don't matter
+                    while index < total && index < best.load(Ordering::Relaxed) {
+                        let script = candidate(alphabet, index, len);
+                        if !is_pruned(&script.instructions) {
+                            if let Some(damage) = try_script(comp, &script, mode) {
+                                best.fetch_min(index, Ordering::Relaxed);
+                                let mut found = found.lock().unwrap();
+                                if found.as_ref().is_none_or(|&(i, _, _)| index < i) {
+                                    *found = Some((index, script, damage));
+                                }
+                            }
+                        }
+                        index = index.saturating_add(n_threads as u64);
+                    }
+                });
+            }
+        });
+
+        if let Some((_, script, damage)) = found.into_inner().unwrap() {
+            return Some((script, damage));
+        }
+    }
+
+    None
+}
+
+fn candidate(alphabet: &[Instruction], mut index: u64, len: usize) -> Springscript {
+    let base = alphabet.len() as u64;
+    let mut instructions = vec![alphabet[0]; len];
+    for slot in instructions.iter_mut().rev() {
+        *slot = alphabet[(index % base) as usize];
+        index /= base;
+    }
+    Springscript { instructions }
+}
+
+fn try_script(comp: &IntcodeComputer, script: &Springscript, mode: Mode) -> Option<Int> {
+    let mut droid = comp.clone();
+    script.feed(&mut droid, mode).ok()?;
+    match droid.run_ascii().stop {
+        AsciiStop::Value(damage) => Some(damage),
+        _ => None,
+    }
+}
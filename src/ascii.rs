@@ -0,0 +1,212 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::{IntcodeComputer, IntcodeError, Int, RunResult};
+use crate::hash::HashMap;
+
+// Why `run_ascii` stopped.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AsciiStop {
+    Finished,
+    NeedsInput,
+    // A value outside of the ASCII range, usually the puzzle answer.
+    Value(Int),
+    // The program failed, for any reason other than missing input.
+    Error(IntcodeError),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AsciiOutput {
+    pub text: String,
+    pub stop: AsciiStop,
+}
+
+// Helpers for the ASCII-driven programs (days 17, 21 and 25).
+impl IntcodeComputer {
+    // Queues every byte of the string as an input.
+    pub fn input_str(&mut self, text: &str) {
+        self.input_iter(text.bytes().map(Int::from));
+    }
+
+    // Same as `input_str`, followed by a newline.
+    pub fn input_line(&mut self, line: &str) {
+        self.input_str(line);
+        self.input(b'\n' as Int);
+    }
+
+    // Runs collecting the output as text, until the program finishes, asks
+    // for input that isn't there, outputs something that isn't ASCII, or
+    // fails.
+    pub fn run_ascii(&mut self) -> AsciiOutput {
+        let mut text = String::new();
+
+        let stop = loop {
+            match self.try_run() {
+                Ok(RunResult::Output(val)) => match ascii_char(val) {
+                    Some(c) => text.push(c),
+                    None => break AsciiStop::Value(val),
+                },
+                Ok(RunResult::Finished) => break AsciiStop::Finished,
+                Err(IntcodeError::NoInput { .. }) => break AsciiStop::NeedsInput,
+                Err(e) => break AsciiStop::Error(e),
+            }
+        };
+
+        AsciiOutput { text, stop }
+    }
+
+    // Plays the program from the terminal: output is printed to stdout and
+    // lines are read from stdin whenever input is needed, until it finishes.
+    pub fn run_interactive(&mut self) -> io::Result<()> {
+        self.run_interactive_with(io::stdin().lock(), io::stdout().lock())
+    }
+
+    // Same as `run_interactive`, over any pair of streams. Non-ASCII outputs
+    // are written as numbers on their own line. Running out of input lines
+    // ends the session early, and the program failing ends it with an
+    // `Other` error wrapping the `IntcodeError`.
+    //
+    // Lines starting with `!` are handled by the session instead of being
+    // sent to the program: `!save <name>` and `!restore <name>` keep and go
+    // back to named snapshots of the computer, and `!undo` reverts the last
+    // line sent (or the last restore).
+    pub fn run_interactive_with(&mut self, mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        let mut saves: HashMap<String, IntcodeComputer> = HashMap::default();
+        let mut history: Vec<IntcodeComputer> = vec![];
+
+        'session: loop {
+            let out = self.run_ascii();
+            output.write_all(out.text.as_bytes())?;
+
+            match out.stop {
+                AsciiStop::Finished => break,
+                AsciiStop::Value(val) => writeln!(output, "{val}")?,
+                AsciiStop::Error(e) => {
+                    output.flush()?;
+                    return Err(io::Error::other(e));
+                },
+                AsciiStop::NeedsInput => loop {
+                    output.flush()?;
+                    let mut line = String::new();
+                    if input.read_line(&mut line)? == 0 {
+                        break 'session;
+                    }
+                    let line = line.trim_end_matches(['\r', '\n']);
+
+                    let Some(command) = line.strip_prefix('!') else {
+                        history.push(self.clone());
+                        self.input_line(line);
+                        break;
+                    };
+
+                    match command.split_once(' ').map_or((command, ""), |(cmd, arg)| (cmd, arg.trim())) {
+                        ("save", name) if !name.is_empty() => {
+                            saves.insert(name.to_owned(), self.clone());
+                            writeln!(output, "Saved '{name}'.")?;
+                        },
+                        ("restore", name) => match saves.get(name) {
+                            Some(saved) => {
+                                history.push(std::mem::replace(self, saved.clone()));
+                                self.reset_cycles();
+                                writeln!(output, "Restored '{name}'.")?;
+                            },
+                            None => writeln!(output, "No savepoint named '{name}'.")?,
+                        },
+                        ("undo", "") => match history.pop() {
+                            Some(prev) => {
+                                *self = prev;
+                                self.reset_cycles();
+                                writeln!(output, "Undone.")?;
+                            },
+                            None => writeln!(output, "Nothing to undo.")?,
+                        },
+                        _ => writeln!(output, "Unknown command: {line}")?,
+                    }
+                },
+            }
+        }
+
+        output.flush()
+    }
+
+    // The computer's ASCII output as a `Read` stream.
+    pub fn ascii_reader(&mut self) -> AsciiReader<'_> {
+        AsciiReader { comp: self, pending: None }
+    }
+
+    // The computer's input as a `Write` stream.
+    pub fn ascii_writer(&mut self) -> AsciiWriter<'_> {
+        AsciiWriter { comp: self }
+    }
+}
+
+// Reading runs the computer. The stream ends (for now) when the program
+// finishes or waits for input; reading again after providing more input
+// resumes it. A non-ASCII output is reported as an `InvalidData` error
+// wrapping a `NonAsciiOutput` that holds the value.
+pub struct AsciiReader<'a> {
+    comp: &'a mut IntcodeComputer,
+    pending: Option<Int>,
+}
+
+pub struct AsciiWriter<'a> {
+    comp: &'a mut IntcodeComputer,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct NonAsciiOutput(pub Int);
+
+impl fmt::Display for NonAsciiOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Non-ASCII output: {}", self.0)
+    }
+}
+
+impl std::error::Error for NonAsciiOutput {}
+
+impl io::Read for AsciiReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+
+        while n < buf.len() {
+            // A non-ASCII value seen earlier is reported once the bytes
+            // before it have been returned.
+            if let Some(val) = self.pending {
+                if n > 0 {
+                    break;
+                }
+                self.pending = None;
+                return Err(io::Error::new(io::ErrorKind::InvalidData, NonAsciiOutput(val)));
+            }
+
+            match self.comp.try_run() {
+                Ok(RunResult::Output(val)) => match ascii_char(val) {
+                    Some(c) => {
+                        buf[n] = c as u8;
+                        n += 1;
+                    },
+                    None => self.pending = Some(val),
+                },
+                Ok(RunResult::Finished) | Err(IntcodeError::NoInput { .. }) => break,
+                Err(e) => return Err(io::Error::other(e)),
+            }
+        }
+
+        Ok(n)
+    }
+}
+
+impl io::Write for AsciiWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.comp.input_iter(buf.iter().map(|&b| b as Int));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub(crate) fn ascii_char(val: Int) -> Option<char> {
+    u8::try_from(val).ok().filter(u8::is_ascii).map(char::from)
+}
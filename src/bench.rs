@@ -0,0 +1,131 @@
+use crate::{IntcodeComputer, Int};
+
+// Synthetic workloads for benchmarking the interpreter, each stressing a
+// different part of it. Every workload knows the outputs it must produce, so
+// benchmarks can double as correctness checks.
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Workload {
+    pub name: &'static str,
+    pub code: Vec<Int>,
+    pub inputs: Vec<Int>,
+    pub expected: Vec<Int>,
+}
+
+impl Workload {
+    // A computer loaded with the program and its inputs.
+    pub fn computer(&self) -> IntcodeComputer {
+        let mut comp = IntcodeComputer::new(&self.code);
+        comp.input_iter(self.inputs.iter().copied());
+        comp
+    }
+}
+
+// Every workload, with sizes derived from `scale` so that they all take
+// roughly the same number of steps (about `scale` thousand).
+pub fn suite(scale: usize) -> Vec<Workload> {
+    let n = scale.max(1) as Int * 1000;
+    vec![
+        arithmetic_loop(n / 6),
+        memory_thrash(100, n / 500),
+        deep_recursion(n / 8),
+        heavy_io(n / 6),
+    ]
+}
+
+// A tight loop adding `3 * i` to an accumulator for every `i` below
+// `iterations`, then outputting it. About 6 steps per iteration.
+pub fn arithmetic_loop(iterations: Int) -> Workload {
+    let (i, acc, tmp, cond) = (33, 34, 35, 36);
+    let code = vec![
+        1101, 0, 0, i,
+        1101, 0, 0, acc,
+        1007, i, iterations, cond,  // 8: loop
+        1006, cond, 30,
+        1002, i, 3, tmp,
+        1, acc, tmp, acc,
+        1001, i, 1, i,
+        1105, 1, 8,
+        4, acc,                     // 30: done
+        99,
+        0, 0, 0, 0,
+    ];
+    let expected = 3 * iterations * (iterations - 1).max(0) / 2;
+    Workload { name: "arithmetic_loop", code, inputs: vec![], expected: vec![expected] }
+}
+
+// Increments `cells` memory cells spread far apart (so that each one lands
+// somewhere different in memory), `passes` times over, then outputs the
+// first one. About 5 steps per increment.
+pub fn memory_thrash(cells: Int, passes: Int) -> Workload {
+    const BASE: Int = 1000;
+    const STRIDE: Int = 4099;
+    let (pass, cell, cond) = (43, 44, 45);
+    let code = vec![
+        109, BASE,
+        1101, 0, 0, pass,
+        1101, 0, 0, cell,           // 6: next pass
+        21201, 0, 1, 0,             // 10: next cell
+        109, STRIDE,
+        1001, cell, 1, cell,
+        1007, cell, cells, cond,
+        1005, cond, 10,
+        109, -cells * STRIDE,
+        1001, pass, 1, pass,
+        1007, pass, passes, cond,
+        1005, cond, 6,
+        4, BASE,
+        99,
+        0, 0, 0,
+    ];
+    Workload { name: "memory_thrash", code, inputs: vec![], expected: vec![passes.max(1)] }
+}
+
+// A recursive function calling itself `depth` times, keeping its frames on a
+// stack through the relative base, then outputting the depth it counted on
+// the way back. About 8 steps per call.
+pub fn deep_recursion(depth: Int) -> Workload {
+    const STACK: Int = 100;
+    let result = 48;
+    let code = vec![
+        109, STACK,
+        21101, 0, depth, 1,         // argument
+        21101, 0, 13, 0,            // return address
+        1105, 1, 16,
+        4, result,                  // 13
+        99,
+        1206, 1, 41,                // 16: f(n)
+        22101, -1, 1, 3,
+        21101, 0, 32, 2,
+        109, 2,
+        1105, 1, 16,
+        109, -2,                    // 32: back from f(n - 1)
+        1001, result, 1, result,
+        2105, 1, 0,
+        1101, 0, 0, result,         // 41: f(0)
+        2105, 1, 0,
+        0,
+    ];
+    Workload { name: "deep_recursion", code, inputs: vec![], expected: vec![depth.max(0)] }
+}
+
+// Reads `count` values and outputs each of them doubled. About 6 steps per
+// value.
+pub fn heavy_io(count: Int) -> Workload {
+    let (left, value) = (21, 22);
+    let code = vec![
+        3, left,
+        1006, left, 20,             // 2: loop
+        3, value,
+        1002, value, 2, value,
+        4, value,
+        1001, left, -1, left,
+        1105, 1, 2,
+        99,                         // 20
+        0, 0,
+    ];
+    let values: Vec<Int> = (0..count).collect();
+    let inputs = [count].into_iter().chain(values.iter().copied()).collect();
+    let expected = values.iter().map(|v| v * 2).collect();
+    Workload { name: "heavy_io", code, inputs, expected }
+}
@@ -0,0 +1,60 @@
+use std::fs;
+use std::io::{self, Read};
+use std::num::NonZeroUsize;
+use std::process::ExitCode;
+
+use intcode_rs::format::format_program;
+
+// Command line tools built on the library:
+//
+//     intcode fmt [--per-line N] [FILE]
+//
+// prints the program in FILE, or read from stdin, in its canonical form
+// (see `format::format_program`), with 10 values per line by default.
+
+const USAGE: &str = "Usage: intcode fmt [--per-line N] [FILE]";
+const DEFAULT_PER_LINE: usize = 10;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(text) => {
+            print!("{text}");
+            ExitCode::SUCCESS
+        },
+        Err(msg) => {
+            eprintln!("{msg}");
+            ExitCode::FAILURE
+        },
+    }
+}
+
+fn run(args: &[String]) -> Result<String, String> {
+    match args.split_first() {
+        Some((command, args)) if command == "fmt" => fmt(args),
+        _ => Err(USAGE.to_owned()),
+    }
+}
+
+fn fmt(args: &[String]) -> Result<String, String> {
+    let mut per_line = NonZeroUsize::new(DEFAULT_PER_LINE).unwrap();
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--per-line" | "-n" => per_line = args.next().and_then(|n| n.parse().ok()).ok_or(USAGE)?,
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(USAGE.to_owned()),
+        }
+    }
+
+    let code = match path {
+        Some(path) => fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?,
+        None => {
+            let mut code = String::new();
+            io::stdin().read_to_string(&mut code).map_err(|e| e.to_string())?;
+            code
+        },
+    };
+    format_program(&code, per_line).map_err(|e| e.to_string())
+}
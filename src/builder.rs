@@ -0,0 +1,127 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::{Device, InputDecision, IntcodeComputer, Int, Observer, OutputCallback};
+use crate::device::MappedDevice;
+use crate::input::InputSource;
+use crate::intcode::parse_code;
+
+// Configures a computer in one go. Building doesn't consume the builder,
+// so the same configuration can stamp out as many machines as needed. The
+// machines share the callbacks, handlers, devices and observers attached
+// here, like copies of a computer do.
+#[derive(Default, Clone)]
+pub struct IntcodeBuilder {
+    code: Vec<Int>,
+    inputs: Vec<Int>,
+    patches: Vec<(Int, Int)>,
+    step_limit: Option<u64>,
+    strict: bool,
+    buffered_output: bool,
+    output_callback: Option<Arc<Mutex<OutputCallback>>>,
+    input_handler: Option<InputSource>,
+    devices: Vec<MappedDevice>,
+    observers: Vec<Arc<Mutex<dyn Observer>>>,
+}
+
+impl IntcodeBuilder {
+    pub fn new(code: &[Int]) -> Self {
+        Self { code: code.to_vec(), ..Default::default() }
+    }
+
+    pub fn input(mut self, value: Int) -> Self {
+        self.inputs.push(value);
+        self
+    }
+
+    pub fn inputs(mut self, values: impl IntoIterator<Item = Int>) -> Self {
+        self.inputs.extend(values);
+        self
+    }
+
+    // Overwrites a memory position before the program starts.
+    pub fn patch(mut self, pos: Int, value: Int) -> Self {
+        self.patches.push((pos, value));
+        self
+    }
+
+    // Day 2 style initialization of addresses 1 and 2.
+    pub fn noun_verb(self, noun: Int, verb: Int) -> Self {
+        self.patch(1, noun).patch(2, verb)
+    }
+
+    pub fn step_limit(mut self, limit: u64) -> Self {
+        self.step_limit = Some(limit);
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn buffered_output(mut self, buffered: bool) -> Self {
+        self.buffered_output = buffered;
+        self
+    }
+
+    // See `IntcodeComputer::set_output_callback`.
+    pub fn on_output(mut self, callback: impl FnMut(Int) + Send + 'static) -> Self {
+        self.output_callback = Some(Arc::new(Mutex::new(callback)));
+        self
+    }
+
+    // See `IntcodeComputer::set_input_request_handler`.
+    pub fn input_handler(mut self, handler: impl FnMut() -> InputDecision + Send + 'static) -> Self {
+        self.input_handler = Some(InputSource::Handler(Arc::new(Mutex::new(handler))));
+        self
+    }
+
+    // See `IntcodeComputer::attach_device`.
+    pub fn device(mut self, start: Int, len: Int, device: impl Device + 'static) -> Self {
+        let device = MappedDevice::new(start, len, Arc::new(Mutex::new(device)), &self.devices);
+        self.devices.push(device);
+        self
+    }
+
+    // See `IntcodeComputer::add_observer`.
+    pub fn observer(mut self, observer: impl Observer + 'static) -> Self {
+        self.observers.push(Arc::new(Mutex::new(observer)));
+        self
+    }
+
+    pub fn build(&self) -> IntcodeComputer {
+        let mut comp = IntcodeComputer::new(&self.code);
+        for &(pos, value) in &self.patches {
+            comp.write_at(pos, value);
+        }
+        comp.input_iter(self.inputs.iter().copied());
+        comp.set_step_limit(self.step_limit);
+        comp.set_strict(self.strict);
+        comp.set_buffered_output(self.buffered_output);
+        comp.output_callback = self.output_callback.clone();
+        comp.input_handler = self.input_handler.clone();
+        comp.devices = self.devices.clone();
+        comp.observers = self.observers.clone();
+        comp
+    }
+}
+
+impl fmt::Debug for IntcodeBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("IntcodeBuilder")
+            .field("code", &self.code)
+            .field("inputs", &self.inputs)
+            .field("patches", &self.patches)
+            .field("step_limit", &self.step_limit)
+            .field("strict", &self.strict)
+            .field("buffered_output", &self.buffered_output)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: AsRef<str>> From<T> for IntcodeBuilder {
+    fn from(code: T) -> Self {
+        Self::new(&parse_code(code.as_ref()))
+    }
+}
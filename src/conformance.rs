@@ -0,0 +1,112 @@
+use std::fmt;
+
+use crate::{Int, RunResult};
+use crate::engine::{IntcodeEngine, IntcodeMachine};
+use crate::intcode::parse_code;
+
+// Upper bound on the outputs collected per case, so that a broken engine
+// stuck in an output loop still gets reported instead of hanging.
+const MAX_OUTPUTS: usize = 1_000;
+
+// A known program together with the behavior it must exhibit.
+#[derive(Copy, Clone, Debug)]
+pub struct Case {
+    pub name: &'static str,
+    pub program: &'static str,
+    pub inputs: &'static [Int],
+    pub outputs: &'static [Int],
+    pub memory: &'static [(Int, Int)],
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Failure {
+    pub case: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.case, self.message)
+    }
+}
+
+const LARGE_CMP: &str = "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,\
+    1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99";
+const QUINE: &str = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+
+const fn case(name: &'static str, program: &'static str, inputs: &'static [Int],
+              outputs: &'static [Int], memory: &'static [(Int, Int)]) -> Case {
+    Case { name, program, inputs, outputs, memory }
+}
+
+// The example programs given in the puzzle statements of days 2, 5 and 9.
+pub const CASES: &[Case] = &[
+    // Day 2: arithmetic and halting
+    case("d2-example", "1,9,10,3,2,3,11,0,99,30,40,50", &[], &[], &[(0, 3500), (3, 70)]),
+    case("d2-add", "1,0,0,0,99", &[], &[], &[(0, 2)]),
+    case("d2-mul", "2,3,0,3,99", &[], &[], &[(3, 6)]),
+    case("d2-mul-past-end", "2,4,4,5,99,0", &[], &[], &[(5, 9801)]),
+    case("d2-self-modify", "1,1,1,4,99,5,6,0,99", &[], &[], &[(0, 30), (4, 2)]),
+
+    // Day 5: parameter modes, I/O, comparisons and jumps
+    case("d5-modes", "1002,4,3,4,33", &[], &[], &[(4, 99)]),
+    case("d5-negative", "1101,100,-1,4,0", &[], &[], &[(4, 99)]),
+    case("d5-echo", "3,0,4,0,99", &[-1234], &[-1234], &[]),
+    case("d5-eq-position-true", "3,9,8,9,10,9,4,9,99,-1,8", &[8], &[1], &[]),
+    case("d5-eq-position-false", "3,9,8,9,10,9,4,9,99,-1,8", &[7], &[0], &[]),
+    case("d5-lt-position-true", "3,9,7,9,10,9,4,9,99,-1,8", &[7], &[1], &[]),
+    case("d5-lt-position-false", "3,9,7,9,10,9,4,9,99,-1,8", &[8], &[0], &[]),
+    case("d5-eq-immediate-true", "3,3,1108,-1,8,3,4,3,99", &[8], &[1], &[]),
+    case("d5-eq-immediate-false", "3,3,1108,-1,8,3,4,3,99", &[9], &[0], &[]),
+    case("d5-lt-immediate-true", "3,3,1107,-1,8,3,4,3,99", &[-8], &[1], &[]),
+    case("d5-lt-immediate-false", "3,3,1107,-1,8,3,4,3,99", &[8], &[0], &[]),
+    case("d5-jump-position-zero", "3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9", &[0], &[0], &[]),
+    case("d5-jump-position-nonzero", "3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9", &[5], &[1], &[]),
+    case("d5-jump-immediate-zero", "3,3,1105,-1,9,1101,0,0,12,4,12,99,1", &[0], &[0], &[]),
+    case("d5-jump-immediate-nonzero", "3,3,1105,-1,9,1101,0,0,12,4,12,99,1", &[-3], &[1], &[]),
+    case("d5-compare-below", LARGE_CMP, &[7], &[999], &[]),
+    case("d5-compare-equal", LARGE_CMP, &[8], &[1000], &[]),
+    case("d5-compare-above", LARGE_CMP, &[9], &[1001], &[]),
+
+    // Day 9: relative mode and large numbers
+    case("d9-quine", QUINE, &[], &[109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99], &[]),
+    case("d9-16-digits", "1102,34915192,34915192,7,4,7,99,0", &[], &[1_219_070_632_396_864], &[]),
+    case("d9-large", "104,1125899906842624,99", &[], &[1_125_899_906_842_624], &[]),
+];
+
+// Runs every case against the engine, collecting all failures.
+pub fn run_all(engine: &impl IntcodeEngine) -> Result<(), Vec<Failure>> {
+    let failures: Vec<Failure> = CASES.iter().filter_map(|c| run_case(engine, c).err()).collect();
+    if failures.is_empty() { Ok(()) } else { Err(failures) }
+}
+
+pub fn run_case(engine: &impl IntcodeEngine, case: &Case) -> Result<(), Failure> {
+    let fail = |message: String| Failure { case: case.name, message };
+    let code = parse_code(case.program);
+
+    let mut machine = engine.load(&code);
+    for &value in case.inputs {
+        machine.input(value);
+    }
+
+    let mut outputs = vec![];
+    while let RunResult::Output(val) = machine.run() {
+        outputs.push(val);
+        if outputs.len() > MAX_OUTPUTS {
+            return Err(fail(format!("more than {MAX_OUTPUTS} outputs produced")));
+        }
+    }
+
+    if outputs != case.outputs {
+        return Err(fail(format!("expected outputs {:?}, got {outputs:?}", case.outputs)));
+    }
+
+    for &(pos, expected) in case.memory {
+        let found = machine.read_at(pos);
+        if found != expected {
+            return Err(fail(format!("expected {expected} at position {pos}, found {found}")));
+        }
+    }
+
+    Ok(())
+}
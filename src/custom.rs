@@ -0,0 +1,55 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::{IntcodeComputer, IntcodeError, Int};
+
+// User-defined instructions. Opcodes the interpreter doesn't know are looked
+// up in the computer's registry before being reported as unknown, so the
+// instruction set can be extended without touching the dispatcher. Copies of
+// a computer share the handlers registered before cloning.
+
+// A resolved parameter: its value, and the address it refers to (`None` for
+// immediate parameters). Values at addresses are peeked (see `peek_at`), so
+// destinations mapped to devices aren't read; `read_operand` reads sources
+// the way built-in instructions do.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Operand {
+    pub value: Int,
+    pub addr: Option<Int>,
+}
+
+// What the computer does after a custom instruction.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Effect {
+    Next,
+    Jump(Int),
+    Output(Int),
+    Halt,
+}
+
+pub type OpcodeHandler = dyn Fn(&mut IntcodeComputer, &[Operand]) -> Result<Effect, IntcodeError> + Send + Sync;
+
+#[derive(Clone)]
+pub(crate) struct CustomOp {
+    pub n_params: usize,
+    pub handler: Arc<OpcodeHandler>,
+}
+
+impl fmt::Debug for CustomOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CustomOp({} params)", self.n_params)
+    }
+}
+
+impl IntcodeComputer {
+    pub fn read_operand(&self, operand: &Operand) -> Int {
+        operand.addr.map_or(operand.value, |addr| self.read_at(addr))
+    }
+
+    // Writes to the address a parameter refers to.
+    pub fn write_operand(&mut self, operand: &Operand, value: Int) -> Result<(), IntcodeError> {
+        let addr = operand.addr.ok_or(IntcodeError::ImmediateWrite { ip: self.ip() })?;
+        self.write_mem(addr, value);
+        Ok(())
+    }
+}
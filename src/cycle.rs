@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+use std::hash::BuildHasher as _;
+
+use crate::{IntcodeComputer, IntcodeError, Int};
+use crate::hash::BuildHasher;
+
+// Infinite loop detection. Every few instructions the computer checks its
+// state (registers, pending inputs and memory, with unset cells counting as
+// zero, like in `==`) against a checkpoint. Finding the checkpointed state
+// again without any input or output in between means the program will cycle
+// forever, so the run stops with `InfiniteLoop` right after the instruction
+// that closed the loop.
+//
+// Checkpoints follow Brent's algorithm: a new one is taken after 1, 2, 4, 8...
+// checks, which finds any loop within a few times its length (plus the steps
+// before it starts), while only one state is ever stored. Each check hashes
+// the memory, and states are only compared in full when the hashes match,
+// so a loop is only reported when there certainly is one. The cost of a
+// check is linear in the memory used, so longer intervals suit programs with
+// large memories.
+//
+// Reads from devices aren't visible to the detector, so it stays inactive
+// while any device is attached.
+
+#[derive(Clone, Debug)]
+pub(crate) struct CycleDetector {
+    interval: u64,
+    // Hash of the state at the last checkpoint, along with the state.
+    checkpoint: Option<(u64, State)>,
+    // Checks since the last checkpoint, and how many until the next one.
+    checks: u64,
+    period: u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct State {
+    ip: Int,
+    rel_base: Int,
+    is_finished: bool,
+    input_queue: VecDeque<Int>,
+    // Non-zero cells, sorted by address.
+    memory: Vec<(Int, Int)>,
+}
+
+impl CycleDetector {
+    fn new(interval: u64) -> Self {
+        Self { interval, checkpoint: None, checks: 0, period: 1 }
+    }
+
+    // Input or output was performed, or the computer was changed from the
+    // outside, so earlier states say nothing about where the program is
+    // going.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.interval);
+    }
+}
+
+impl IntcodeComputer {
+    // Checks for repeated states every `interval` instructions, or never if
+    // `None`. Shorter intervals catch loops sooner, at a higher cost.
+    pub fn set_cycle_detection(&mut self, interval: Option<u64>) {
+        assert!(interval != Some(0), "The interval must be positive");
+        self.cycles = interval.map(CycleDetector::new);
+    }
+
+    // Forgets the states seen so far, after the computer is changed from the
+    // outside.
+    pub(crate) fn reset_cycles(&mut self) {
+        if let Some(detector) = &mut self.cycles {
+            detector.reset();
+        }
+    }
+
+    pub(crate) fn check_cycle(&mut self) -> Result<(), IntcodeError> {
+        let Some(detector) = &self.cycles else { return Ok(()) };
+        if !self.steps.is_multiple_of(detector.interval) || !self.devices.is_empty() {
+            return Ok(());
+        }
+        let hash = self.cycle_hash();
+        if let Some((seen, state)) = &detector.checkpoint {
+            if *seen == hash && *state == self.cycle_state() {
+                return Err(IntcodeError::InfiniteLoop { ip: self.ip });
+            }
+        }
+
+        let detector = self.cycles.as_mut().unwrap();
+        detector.checks += 1;
+        if detector.checks == detector.period {
+            detector.checks = 0;
+            detector.period *= 2;
+            let state = self.cycle_state();
+            self.cycles.as_mut().unwrap().checkpoint = Some((hash, state));
+        }
+        Ok(())
+    }
+
+    // Hash of the state, which doesn't depend on the order of the cells.
+    fn cycle_hash(&self) -> u64 {
+        let hasher = BuildHasher::default();
+        let memory = self.memory.iter()
+            .filter(|&(_, val)| val != 0)
+            .fold(0_u64, |hash, cell| hash.wrapping_add(hasher.hash_one(cell)));
+        hasher.hash_one((self.ip, self.rel_base, self.is_finished, &self.input_queue, memory))
+    }
+
+    fn cycle_state(&self) -> State {
+        let mut memory: Vec<(Int, Int)> = self.memory.iter().filter(|&(_, val)| val != 0).collect();
+        memory.sort_unstable();
+        State {
+            ip: self.ip,
+            rel_base: self.rel_base,
+            is_finished: self.is_finished,
+            input_queue: self.input_queue.clone(),
+            memory,
+        }
+    }
+}
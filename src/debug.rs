@@ -0,0 +1,214 @@
+use std::ops::Range;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use crate::{CodeWrite, Event, IntcodeComputer, Int, Observer, StopReason};
+use crate::script::{Script, ScriptError};
+use crate::watch::{Expr, ParseError, WatchList};
+
+// Debugger as a library: everything a frontend needs to drive a computer
+// step by step, inspect it and be told about what happens, without deciding
+// how any of it is shown.
+//
+// Subscribers get every event of the machine as it runs, followed by the
+// watches whose values changed and the reason for stopping whenever a step
+// or run command ends.
+//
+// Scripts (see `script`) are checked after every instruction while the
+// session runs, and their output goes to the subscribers too.
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SessionEvent {
+    Machine(Event),
+    WatchChanged { name: String, value: Option<Int> },
+    // A line printed by the script.
+    ScriptOutput(String),
+    Stopped(StopReason),
+}
+
+type Subscribers = Arc<Mutex<Vec<Sender<SessionEvent>>>>;
+
+pub struct DebugSession {
+    comp: IntcodeComputer,
+    watches: WatchList,
+    values: Vec<(String, Option<Int>)>,
+    script: Script,
+    subscribers: Subscribers,
+    observer: Arc<Mutex<dyn Observer>>,
+}
+
+// Sends to every subscriber, forgetting the ones that went away.
+fn broadcast(subscribers: &Subscribers, event: SessionEvent) {
+    subscribers.lock().unwrap().retain(|sub| sub.send(event.clone()).is_ok());
+}
+
+impl DebugSession {
+    pub fn new(mut comp: IntcodeComputer) -> Self {
+        let subscribers = Subscribers::default();
+        let forward = subscribers.clone();
+        let observer = comp.add_observer(move |event: &Event| broadcast(&forward, SessionEvent::Machine(*event)));
+        Self { comp, watches: WatchList::new(), values: vec![], script: Script::default(), subscribers, observer }
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<SessionEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    // Executes a single instruction, returning why the computer stopped if
+    // it can't go on as usual.
+    pub fn step(&mut self) -> Option<StopReason> {
+        let stop = self.exec_one();
+        self.stopped(stop);
+        stop
+    }
+
+    // Runs until something stops the computer, such as a breakpoint.
+    pub fn resume(&mut self) -> StopReason {
+        let stop = match self.script.is_empty() {
+            true => self.comp.run_until_stop(),
+            false => self.resume_scripted(),
+        };
+        self.stopped(Some(stop));
+        stop
+    }
+
+    // Same as `run_until_stop`, checking the script after every instruction.
+    fn resume_scripted(&mut self) -> StopReason {
+        let start = self.comp.steps();
+        loop {
+            if self.comp.steps() != start && self.comp.at_breakpoint() {
+                return StopReason::Breakpoint { ip: self.comp.ip() };
+            }
+            if let Some(stop) = self.exec_one() {
+                return stop;
+            }
+        }
+    }
+
+    fn exec_one(&mut self) -> Option<StopReason> {
+        if self.comp.is_finished() {
+            return Some(StopReason::Finished);
+        }
+        let stop = match self.comp.exec_next() {
+            Ok(Some(ret)) => Some(ret.into()),
+            Ok(None) if self.comp.is_finished() => Some(StopReason::Finished),
+            Ok(None) => self.comp.take_code_write_hit().map(|CodeWrite { ip, addr }| StopReason::CodeWrite { ip, addr }),
+            // Nothing ran, so there's nothing new for the script
+            Err(e) => return Some(e.into()),
+        };
+        if self.script.is_empty() {
+            return stop;
+        }
+
+        let (output, script_stop) = self.script.check(&mut self.comp);
+        for line in output {
+            broadcast(&self.subscribers, SessionEvent::ScriptOutput(line));
+        }
+        stop.or(script_stop.then_some(StopReason::Breakpoint { ip: self.comp.ip() }))
+    }
+
+    fn stopped(&mut self, reason: Option<StopReason>) {
+        let values = self.watches.sample(&self.comp);
+        for (name, value) in &values {
+            if !self.values.contains(&(name.clone(), *value)) {
+                broadcast(&self.subscribers, SessionEvent::WatchChanged { name: name.clone(), value: *value });
+            }
+        }
+        self.values = values;
+        if let Some(reason) = reason {
+            broadcast(&self.subscribers, SessionEvent::Stopped(reason));
+        }
+    }
+
+    // Adds the rules of the script to the ones already loaded. A `stop`
+    // action stops the session with `StopReason::Breakpoint`.
+    pub fn load_script(&mut self, script: &str) -> Result<(), ScriptError> {
+        let mut script: Script = script.parse()?;
+        script.prime(&self.comp);
+        self.script.extend(script);
+        Ok(())
+    }
+
+    pub fn clear_script(&mut self) {
+        self.script = Script::default();
+    }
+
+    pub fn add_breakpoint(&mut self, addr: Int) {
+        self.comp.add_breakpoint(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: Int) {
+        self.comp.remove_breakpoint(addr);
+    }
+
+    pub fn add_opcode_breakpoint(&mut self, opcode: u8) {
+        self.comp.add_opcode_breakpoint(opcode);
+    }
+
+    pub fn remove_opcode_breakpoint(&mut self, opcode: u8) {
+        self.comp.remove_opcode_breakpoint(opcode);
+    }
+
+    pub fn input(&mut self, value: Int) {
+        self.comp.input(value);
+    }
+
+    pub fn ip(&self) -> Int {
+        self.comp.ip()
+    }
+
+    pub fn relative_base(&self) -> Int {
+        self.comp.relative_base()
+    }
+
+    // Memory as the program sees it, devices included.
+    pub fn read(&self, addr: Int) -> Int {
+        self.comp.read_at(addr)
+    }
+
+    pub fn read_range(&self, addrs: Range<Int>) -> Vec<Int> {
+        addrs.map(|addr| self.comp.read_at(addr)).collect()
+    }
+
+    pub fn write(&mut self, addr: Int, value: Int) {
+        self.comp.write_at(addr, value);
+    }
+
+    // Evaluates a watch expression once, see `watch` for the syntax.
+    pub fn eval(&self, expr: &str) -> Result<Option<Int>, ParseError> {
+        Ok(expr.parse::<Expr>()?.eval(&self.comp))
+    }
+
+    pub fn watch(&mut self, name: &str, expr: &str) -> Result<(), ParseError> {
+        self.watches.add(name, expr)?;
+        // Reported as changed at the next stop
+        self.values.retain(|(n, _)| n != name);
+        Ok(())
+    }
+
+    pub fn unwatch(&mut self, name: &str) {
+        self.watches.remove(name);
+        self.values.retain(|(n, _)| n != name);
+    }
+
+    // Current value of every watch.
+    pub fn watches(&self) -> Vec<(String, Option<Int>)> {
+        self.watches.sample(&self.comp)
+    }
+
+    pub fn computer(&self) -> &IntcodeComputer {
+        &self.comp
+    }
+
+    pub fn computer_mut(&mut self) -> &mut IntcodeComputer {
+        &mut self.comp
+    }
+
+    // Ends the session, returning the computer without the session's observer.
+    pub fn into_inner(mut self) -> IntcodeComputer {
+        self.comp.observers.retain(|obs| !Arc::ptr_eq(obs, &self.observer));
+        self.comp
+    }
+}
@@ -0,0 +1,292 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{IntcodeComputer, Int};
+use crate::generate::Rng;
+
+// Memory-mapped I/O. A device is attached to a range of addresses, and every
+// read or write in that range (by the program, or through `read_at` and
+// `write_at`) calls the device instead of touching memory. Offsets passed to
+// the device are relative to the start of its range.
+//
+// Copies of a computer share its devices. `peek_at` looks at them without
+// side effects, while indexing the computer directly bypasses them and sees
+// plain memory.
+
+pub trait Device: Send {
+    fn read(&mut self, offset: Int) -> Int;
+    fn write(&mut self, offset: Int, value: Int);
+
+    // What a read would give, without changing anything, for tools that
+    // only look at memory. Devices whose reads have side effects can leave
+    // it out, and look like they hold zeros.
+    fn peek(&self, _offset: Int) -> Int {
+        0
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct MappedDevice {
+    pub start: Int,
+    pub len: Int,
+    pub device: Arc<Mutex<dyn Device>>,
+}
+
+impl MappedDevice {
+    // Maps the range onto the device, which must not overlap with the others.
+    pub fn new(start: Int, len: Int, device: Arc<Mutex<dyn Device>>, others: &[MappedDevice]) -> Self {
+        assert!(len > 0, "Devices need at least one address");
+        assert!(
+            others.iter().all(|d| start + len <= d.start || d.start + d.len <= start),
+            "Device range overlaps with another device"
+        );
+        Self { start, len, device }
+    }
+
+    pub fn offset_of(&self, addr: Int) -> Option<Int> {
+        let offset = addr.checked_sub(self.start)?;
+        (0..self.len).contains(&offset).then_some(offset)
+    }
+}
+
+impl IntcodeComputer {
+    // Maps `start..start + len` onto the device, returning a handle to it
+    // so the host can still inspect it.
+    pub fn attach_device<D: Device + 'static>(&mut self, start: Int, len: Int, device: D) -> Arc<Mutex<D>> {
+        let device = Arc::new(Mutex::new(device));
+        self.devices.push(MappedDevice::new(start, len, device.clone(), &self.devices));
+        device
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Built-in devices.
+
+// Seedable random numbers, for games and randomized programs that still
+// need reproducible runs. It takes two addresses:
+//  - offset 0: reading gives a random value in [0, 2^64); writing reseeds.
+//  - offset 1: reading gives a random value in [0, bound); writing sets the
+//    bound (1 by default, so reads give 0 until one is set).
+#[derive(Clone, Debug)]
+pub struct RngDevice {
+    rng: Rng,
+    bound: Int,
+}
+
+impl RngDevice {
+    pub const LEN: Int = 2;
+
+    pub fn new(seed: u64) -> Self {
+        Self { rng: Rng::new(seed), bound: 1 }
+    }
+}
+
+impl Device for RngDevice {
+    fn read(&mut self, offset: Int) -> Int {
+        match offset {
+            0 => self.rng.next_u64() as Int,
+            _ => self.rng.range(0, self.bound - 1),
+        }
+    }
+
+    fn write(&mut self, offset: Int, value: Int) {
+        match offset {
+            0 => self.rng = Rng::new(value as u64),
+            _ => self.bound = value.max(1),
+        }
+    }
+}
+
+// Time as seen by a `ClockDevice`.
+pub trait Clock: Send {
+    // Monotonic time since the clock started.
+    fn elapsed(&mut self) -> Duration;
+    // Wall-clock time since the Unix epoch.
+    fn unix_time(&mut self) -> Duration;
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SystemClock(Instant);
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self(Instant::now())
+    }
+}
+
+impl Clock for SystemClock {
+    fn elapsed(&mut self) -> Duration {
+        self.0.elapsed()
+    }
+
+    fn unix_time(&mut self) -> Duration {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+    }
+}
+
+// Clock that only moves when told to, for deterministic runs. With a tick
+// set, every reading advances it by that much, so consecutive readings still
+// differ.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct FakeClock {
+    pub elapsed: Duration,
+    pub unix_time: Duration,
+    pub tick: Duration,
+}
+
+impl FakeClock {
+    pub fn advance(&mut self, by: Duration) {
+        self.elapsed += by;
+        self.unix_time += by;
+    }
+}
+
+impl Clock for FakeClock {
+    fn elapsed(&mut self) -> Duration {
+        let now = self.elapsed;
+        self.advance(self.tick);
+        now
+    }
+
+    fn unix_time(&mut self) -> Duration {
+        let now = self.unix_time;
+        self.advance(self.tick);
+        now
+    }
+}
+
+// Time readings, e.g. for programs benchmarking their own routines. Writes
+// are ignored. It takes two addresses:
+//  - offset 0: microseconds since the clock started.
+//  - offset 1: seconds since the Unix epoch.
+#[derive(Clone, Default, Debug)]
+pub struct ClockDevice<C = SystemClock> {
+    clock: C,
+}
+
+impl<C: Clock> ClockDevice<C> {
+    pub const LEN: Int = 2;
+
+    pub fn new(clock: C) -> Self {
+        Self { clock }
+    }
+
+    pub fn clock_mut(&mut self) -> &mut C {
+        &mut self.clock
+    }
+}
+
+impl<C: Clock> Device for ClockDevice<C> {
+    fn read(&mut self, offset: Int) -> Int {
+        match offset {
+            0 => self.clock.elapsed().as_micros() as Int,
+            _ => self.clock.unix_time().as_secs() as Int,
+        }
+    }
+
+    fn write(&mut self, _offset: Int, _value: Int) {}
+}
+
+// Block storage backed by a host file, which is treated as a sequence of
+// blocks of `BLOCK_SIZE` cells (16 bytes each, little endian). Programs
+// select a block, load it into the device's buffer, work on the buffer and
+// store it back. Addresses:
+//  - offset 0: selected block.
+//  - offset 1: writing runs a command (`LOAD` or `STORE`) on the selected
+//    block; reading gives the status of the last one (0, or -1 if it failed).
+//  - offsets 2 to `BLOCK_SIZE + 1`: the buffer.
+//
+// Blocks at or past the cap can't be used, and read-only storage rejects
+// every store. Parts of a block past the end of the file read as zero.
+#[derive(Debug)]
+pub struct StorageDevice {
+    file: File,
+    max_blocks: u64,
+    read_only: bool,
+    block: Int,
+    status: Int,
+    buffer: Vec<Int>,
+}
+
+impl StorageDevice {
+    pub const BLOCK_SIZE: usize = 64;
+    pub const LEN: Int = Self::BLOCK_SIZE as Int + 2;
+    pub const LOAD: Int = 1;
+    pub const STORE: Int = 2;
+
+    const CELL_BYTES: usize = 16;
+
+    // Opens (or creates) the file for reading and writing.
+    pub fn open(path: impl AsRef<Path>, max_blocks: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        Ok(Self::with_file(file, max_blocks, false))
+    }
+
+    pub fn open_read_only(path: impl AsRef<Path>, max_blocks: u64) -> io::Result<Self> {
+        Ok(Self::with_file(File::open(path)?, max_blocks, true))
+    }
+
+    fn with_file(file: File, max_blocks: u64, read_only: bool) -> Self {
+        Self { file, max_blocks, read_only, block: 0, status: 0, buffer: vec![0; Self::BLOCK_SIZE] }
+    }
+
+    fn run_command(&mut self, command: Int) -> io::Result<()> {
+        let block = u64::try_from(self.block).ok()
+            .filter(|&b| b < self.max_blocks)
+            .ok_or(io::ErrorKind::InvalidInput)?;
+        let block_bytes = Self::BLOCK_SIZE * Self::CELL_BYTES;
+        self.file.seek(SeekFrom::Start(block * block_bytes as u64))?;
+        let mut bytes = vec![0; block_bytes];
+
+        match command {
+            Self::LOAD => {
+                // Short reads near the end of the file leave zeros behind
+                let mut read = 0;
+                while read < bytes.len() {
+                    match self.file.read(&mut bytes[read..])? {
+                        0 => break,
+                        n => read += n,
+                    }
+                }
+                for (cell, chunk) in self.buffer.iter_mut().zip(bytes.chunks_exact(Self::CELL_BYTES)) {
+                    *cell = Int::from_le_bytes(chunk.try_into().unwrap());
+                }
+            },
+            Self::STORE if !self.read_only => {
+                for (cell, chunk) in self.buffer.iter().zip(bytes.chunks_exact_mut(Self::CELL_BYTES)) {
+                    chunk.copy_from_slice(&cell.to_le_bytes());
+                }
+                self.file.write_all(&bytes)?;
+                self.file.flush()?;
+            },
+            _ => return Err(io::ErrorKind::Unsupported.into()),
+        }
+        Ok(())
+    }
+}
+
+impl Device for StorageDevice {
+    fn read(&mut self, offset: Int) -> Int {
+        self.peek(offset)
+    }
+
+    fn peek(&self, offset: Int) -> Int {
+        match offset {
+            0 => self.block,
+            1 => self.status,
+            _ => self.buffer[offset as usize - 2],
+        }
+    }
+
+    fn write(&mut self, offset: Int, value: Int) {
+        match offset {
+            0 => self.block = value,
+            1 => self.status = if self.run_command(value).is_ok() { 0 } else { -1 },
+            _ => self.buffer[offset as usize - 2] = value,
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::fmt;
+
+use crate::Int;
+use crate::intcode::Opcodes;
+
+// Instruction-level program diffs. Both programs are decoded linearly from
+// address 0, taking every word that isn't a valid instruction as a single
+// data word. Instructions are then matched with a longest common
+// subsequence, so an insertion only shows up once instead of shifting every
+// address after it. Within the unmatched stretches, instructions with the
+// same opcode and modes are paired up as changes to their operands.
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Instr {
+    pub addr: Int,
+    // Opcode word first, then the parameters. A single word for data.
+    pub words: Vec<Int>,
+}
+
+impl Instr {
+    pub fn is_data(&self) -> bool {
+        decode_len(self.words[0]) != Some(self.words.len())
+    }
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words: Vec<String> = self.words.iter().map(Int::to_string).collect();
+        match self.is_data() {
+            true => write!(f, "{}: DATA {}", self.addr, words[0]),
+            false => write!(f, "{}: {} {}", self.addr, Opcodes::mnemonic((self.words[0] % 100) as u8), words.join(",")),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DiffOp {
+    Same { old: Instr, new: Instr },
+    // Same opcode and modes, different operands.
+    Changed { old: Instr, new: Instr },
+    Deleted(Instr),
+    Inserted(Instr),
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct ProgramDiff {
+    pub ops: Vec<DiffOp>,
+}
+
+impl ProgramDiff {
+    pub fn is_empty(&self) -> bool {
+        self.ops.iter().all(|op| matches!(op, DiffOp::Same { .. }))
+    }
+}
+
+// Only the differences, one per line: `-` for deletions, `+` for insertions
+// and `~` for changes, showing both versions.
+impl fmt::Display for ProgramDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for op in &self.ops {
+            match op {
+                DiffOp::Same { .. } => {},
+                DiffOp::Changed { old, new } => writeln!(f, "~ {old}\n  {new}")?,
+                DiffOp::Deleted(old) => writeln!(f, "- {old}")?,
+                DiffOp::Inserted(new) => writeln!(f, "+ {new}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Length of the instruction starting with the word, if it's a valid one.
+fn decode_len(word: Int) -> Option<usize> {
+    let n_params = Opcodes::n_params(u8::try_from(word % 100).ok()?)?;
+    let mut modes = word / 100;
+    for _ in 0..n_params {
+        if modes % 10 > 2 {
+            return None;
+        }
+        modes /= 10;
+    }
+    (modes == 0).then_some(1 + n_params)
+}
+
+pub fn decode(code: &[Int]) -> Vec<Instr> {
+    let mut instrs = vec![];
+    let mut pos = 0;
+    while pos < code.len() {
+        let len = decode_len(code[pos]).filter(|len| pos + len <= code.len()).unwrap_or(1);
+        instrs.push(Instr { addr: pos as Int, words: code[pos..pos + len].to_vec() });
+        pos += len;
+    }
+    instrs
+}
+
+pub fn diff_programs(old: &[Int], new: &[Int]) -> ProgramDiff {
+    let (old, new) = (decode(old), decode(new));
+    let mut ops = vec![];
+    let (mut deleted, mut inserted) = (vec![], vec![]);
+    for step in align(&old, &new, |a, b| a.words == b.words) {
+        match step {
+            (Some(i), Some(j)) => {
+                flush_gap(&mut ops, &deleted, &inserted);
+                (deleted, inserted) = (vec![], vec![]);
+                ops.push(DiffOp::Same { old: old[i].clone(), new: new[j].clone() });
+            },
+            (Some(i), None) => deleted.push(old[i].clone()),
+            (None, Some(j)) => inserted.push(new[j].clone()),
+            (None, None) => unreachable!(),
+        }
+    }
+    flush_gap(&mut ops, &deleted, &inserted);
+    ProgramDiff { ops }
+}
+
+// Turns a stretch of unmatched instructions into diff operations, pairing
+// up the ones that share opcode and modes (again keeping their order).
+fn flush_gap(ops: &mut Vec<DiffOp>, deleted: &[Instr], inserted: &[Instr]) {
+    let same_kind = |a: &Instr, b: &Instr| a.words[0] == b.words[0] && !a.is_data();
+    for step in align(deleted, inserted, same_kind) {
+        ops.push(match step {
+            (Some(i), Some(j)) => DiffOp::Changed { old: deleted[i].clone(), new: inserted[j].clone() },
+            (Some(i), None) => DiffOp::Deleted(deleted[i].clone()),
+            (None, Some(j)) => DiffOp::Inserted(inserted[j].clone()),
+            (None, None) => unreachable!(),
+        });
+    }
+}
+
+// Longest common subsequence alignment: every element of `a` and `b` in
+// order, either matched with one on the other side or on its own. Between
+// two matches, the unmatched elements of `a` come first. The common prefix
+// and suffix are matched directly, and the rest goes through Hirschberg's
+// algorithm, so memory stays linear in the length of the programs.
+fn align<T>(a: &[T], b: &[T], eq: impl Fn(&T, &T) -> bool) -> Vec<(Option<usize>, Option<usize>)> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| eq(x, y)).count();
+    let (a_rest, b_rest) = (&a[prefix..], &b[prefix..]);
+    let suffix = a_rest.iter().rev().zip(b_rest.iter().rev()).take_while(|(x, y)| eq(x, y)).count();
+
+    let mut steps: Vec<_> = (0..prefix).map(|i| (Some(i), Some(i))).collect();
+    let middle = steps.len();
+    let (a_mid, b_mid) = (&a_rest[..a_rest.len() - suffix], &b_rest[..b_rest.len() - suffix]);
+    hirschberg(a_mid, b_mid, (prefix, prefix), &eq, &mut steps);
+    for gap in steps[middle..].split_mut(|step| matches!(step, (Some(_), Some(_)))) {
+        gap.sort_by_key(|step| step.0.is_none());
+    }
+    steps.extend((0..suffix).map(|k| (Some(a.len() - suffix + k), Some(b.len() - suffix + k))));
+    steps
+}
+
+// Blocks up to this many cells are aligned with a full table.
+const TABLE_CELLS: usize = 1 << 16;
+
+// Aligns `a` and `b`, whose first elements are at `offset` in the original
+// sequences, by splitting `a` in half and `b` wherever the two halves add up
+// to the longest common subsequence.
+fn hirschberg<T>(a: &[T], b: &[T], offset: (usize, usize), eq: &impl Fn(&T, &T) -> bool,
+                 steps: &mut Vec<(Option<usize>, Option<usize>)>) {
+    let (n, m) = (a.len(), b.len());
+    if n <= 1 || n.saturating_mul(m) <= TABLE_CELLS {
+        let shift = |i: Option<usize>, base| i.map(|i| i + base);
+        steps.extend(align_dense(a, b, eq).into_iter().map(|(i, j)| (shift(i, offset.0), shift(j, offset.1))));
+        return;
+    }
+
+    let mid = n / 2;
+    let front = lcs_row(a[..mid].iter(), b.iter(), eq);
+    let back = lcs_row(a[mid..].iter().rev(), b.iter().rev(), eq);
+    let split = (0..=m).max_by_key(|&k| front[k] + back[m - k]).unwrap();
+    hirschberg(&a[..mid], &b[..split], offset, eq, steps);
+    hirschberg(&a[mid..], &b[split..], (offset.0 + mid, offset.1 + split), eq, steps);
+}
+
+// Length of the longest common subsequence of all of `a` and every prefix
+// of `b`, keeping a single row of the table.
+fn lcs_row<'t, T: 't>(a: impl Iterator<Item = &'t T>, b: impl Iterator<Item = &'t T> + Clone,
+                      eq: &impl Fn(&T, &T) -> bool) -> Vec<u32> {
+    let mut row = vec![0u32; b.clone().count() + 1];
+    for x in a {
+        // row[j] from the previous row, before it gets overwritten
+        let mut diag = 0;
+        for (j, y) in b.clone().enumerate() {
+            let up = row[j + 1];
+            row[j + 1] = match eq(x, y) {
+                true => diag + 1,
+                false => up.max(row[j]),
+            };
+            diag = up;
+        }
+    }
+    row
+}
+
+// The whole table at once, for small blocks.
+fn align_dense<T>(a: &[T], b: &[T], eq: &impl Fn(&T, &T) -> bool) -> Vec<(Option<usize>, Option<usize>)> {
+    let (n, m) = (a.len(), b.len());
+    // lcs[i][j]: length of the longest common subsequence of a[i..] and b[j..]
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = match eq(&a[i], &b[j]) {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+
+    let mut steps = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && eq(&a[i], &b[j]) && lcs[i][j] == lcs[i + 1][j + 1] + 1 {
+            steps.push((Some(i), Some(j)));
+            (i, j) = (i + 1, j + 1);
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            steps.push((Some(i), None));
+            i += 1;
+        } else {
+            steps.push((None, Some(j)));
+            j += 1;
+        }
+    }
+    steps
+}
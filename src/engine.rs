@@ -0,0 +1,43 @@
+use crate::{IntcodeComputer, Int, RunResult};
+
+// A backend capable of loading Intcode programs into runnable machines.
+// The conformance suite is written against this so that alternative
+// implementations can be checked against the same expectations.
+pub trait IntcodeEngine {
+    type Machine: IntcodeMachine;
+
+    fn load(&self, code: &[Int]) -> Self::Machine;
+}
+
+// The minimal surface a loaded machine has to offer.
+pub trait IntcodeMachine {
+    fn input(&mut self, value: Int);
+    fn run(&mut self) -> RunResult;
+    fn read_at(&self, pos: Int) -> Int;
+}
+
+// The reference engine: the interpreter in this crate.
+#[derive(Default, Copy, Clone, Debug)]
+pub struct Interpreter;
+
+impl IntcodeEngine for Interpreter {
+    type Machine = IntcodeComputer;
+
+    fn load(&self, code: &[Int]) -> Self::Machine {
+        IntcodeComputer::new(code)
+    }
+}
+
+impl IntcodeMachine for IntcodeComputer {
+    fn input(&mut self, value: Int) {
+        IntcodeComputer::input(self, value);
+    }
+
+    fn run(&mut self) -> RunResult {
+        IntcodeComputer::run(self)
+    }
+
+    fn read_at(&self, pos: Int) -> Int {
+        IntcodeComputer::read_at(self, pos)
+    }
+}
@@ -0,0 +1,61 @@
+use std::fmt;
+
+use crate::Int;
+
+// Everything that can go wrong while executing an Intcode program.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum IntcodeError {
+    UnknownOpcode { ip: Int, opcode: Int },
+    UnknownParamMode { ip: Int, mode: Int },
+    ImmediateWrite { ip: Int },
+    NoInput { ip: Int },
+    Overflow { ip: Int },
+    NegativeAddress { ip: Int, addr: Int },
+    StepLimit { steps: u64 },
+    DivisionByZero { ip: Int },
+    // The program reached the same state twice without doing any I/O.
+    InfiniteLoop { ip: Int },
+    // Stopped by the pre-instruction hook.
+    Aborted { ip: Int },
+    // Raised by user-provided code running on behalf of the computer.
+    Handler { ip: Int, reason: &'static str },
+}
+
+impl IntcodeError {
+    // Address of the instruction that failed, if known.
+    pub fn ip(&self) -> Option<Int> {
+        match *self {
+            Self::UnknownOpcode { ip, .. }
+            | Self::UnknownParamMode { ip, .. }
+            | Self::ImmediateWrite { ip }
+            | Self::NoInput { ip }
+            | Self::Overflow { ip }
+            | Self::NegativeAddress { ip, .. }
+            | Self::DivisionByZero { ip }
+            | Self::InfiniteLoop { ip }
+            | Self::Aborted { ip }
+            | Self::Handler { ip, .. } => Some(ip),
+            Self::StepLimit { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownOpcode { ip, opcode } => write!(f, "Unexpected opcode at {ip}: {opcode}"),
+            Self::UnknownParamMode { ip, mode } => write!(f, "Unknown param mode at {ip}: {mode}"),
+            Self::ImmediateWrite { ip } => write!(f, "Output addresses cannot be in immediate mode (at {ip})"),
+            Self::NoInput { ip } => write!(f, "No input available (at {ip})"),
+            Self::Overflow { ip } => write!(f, "Integer overflow at {ip}"),
+            Self::NegativeAddress { ip, addr } => write!(f, "Access to negative address {addr} at {ip}"),
+            Self::StepLimit { steps } => write!(f, "Step limit reached after {steps} steps"),
+            Self::DivisionByZero { ip } => write!(f, "Division by zero at {ip}"),
+            Self::InfiniteLoop { ip } => write!(f, "Infinite loop detected at {ip}"),
+            Self::Aborted { ip } => write!(f, "Execution aborted at {ip}"),
+            Self::Handler { ip, reason } => write!(f, "Handler failed at {ip}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for IntcodeError {}
@@ -0,0 +1,488 @@
+use std::fmt;
+
+use crate::{IntcodeComputer, IntcodeError, Int, RunResult};
+
+// Deterministic execution of several machines at once. Every round, each
+// machine runs one slice of instructions in index order, and only then are
+// the outputs of the round delivered to the machines linked to their source.
+// The result never depends on thread timing, so experiments where timing
+// matters can be reproduced exactly.
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct MachineError {
+    pub machine: usize,
+    pub error: IntcodeError,
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Machine {}: {}", self.machine, self.error)
+    }
+}
+
+impl std::error::Error for MachineError {}
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Round {
+    // Outputs produced during the round, with the machine they came from.
+    pub outputs: Vec<(usize, Int)>,
+    // Instructions executed by all machines.
+    pub steps: usize,
+}
+
+// An address range mapped onto the same buffer in several machines.
+// Machines see the buffer as it is when their slice starts, and their writes
+// are published when it ends. Since slices run in machine order, writes in a
+// round are ordered by machine index, and later machines see the writes of
+// earlier ones in the same round.
+struct SharedRegion {
+    start: Int,
+    data: Vec<Int>,
+    machines: Vec<usize>,
+}
+
+pub struct Lockstep {
+    machines: Vec<IntcodeComputer>,
+    // Machines receiving the outputs of each machine.
+    links: Vec<Vec<usize>>,
+    shared: Vec<SharedRegion>,
+    slice: usize,
+    rounds: u64,
+}
+
+impl Lockstep {
+    // By default, machines advance one instruction per round.
+    pub fn new(machines: Vec<IntcodeComputer>) -> Self {
+        let links = vec![vec![]; machines.len()];
+        Self { machines, links, shared: vec![], slice: 1, rounds: 0 }
+    }
+
+    pub fn with_slice(mut self, steps: usize) -> Self {
+        self.slice = steps.max(1);
+        self
+    }
+
+    // Sends every output of `from` to `to` as well (outputs go to all the
+    // machines linked to their source).
+    pub fn connect(&mut self, from: usize, to: usize) {
+        self.links[from].push(to);
+    }
+
+    // Maps the addresses `start..start + len` of the given machines onto a
+    // shared buffer, initialized from the first machine's memory. Returns
+    // the region's index.
+    pub fn share(&mut self, start: Int, len: usize, machines: &[usize]) -> usize {
+        assert!(!machines.is_empty(), "Shared regions need at least one machine");
+        let end = start.checked_add(len as Int).expect("The shared region goes past the end of memory");
+        let first = &self.machines[machines[0]];
+        let data = (start..end).map(|addr| first.read_at(addr)).collect();
+        self.shared.push(SharedRegion { start, data, machines: machines.to_vec() });
+        self.shared.len() - 1
+    }
+
+    // Current contents of a shared region.
+    pub fn shared(&self, region: usize) -> &[Int] {
+        &self.shared[region].data
+    }
+
+    pub fn machines(&self) -> &[IntcodeComputer] {
+        &self.machines
+    }
+
+    pub fn machine_mut(&mut self, index: usize) -> &mut IntcodeComputer {
+        &mut self.machines[index]
+    }
+
+    pub fn rounds(&self) -> u64 {
+        self.rounds
+    }
+
+    // Machines waiting for input are skipped until it's delivered.
+    pub fn step(&mut self) -> Result<Round, MachineError> {
+        let mut round = Round::default();
+
+        for (machine, comp) in self.machines.iter_mut().enumerate() {
+            let regions: Vec<&mut SharedRegion> = self.shared.iter_mut().filter(|r| r.machines.contains(&machine)).collect();
+            for region in &regions {
+                for (i, &val) in region.data.iter().enumerate() {
+                    comp.write_at(region.start + i as Int, val);
+                }
+            }
+
+            for _ in 0..self.slice {
+                if comp.is_finished() {
+                    break;
+                }
+                match comp.exec_next() {
+                    Ok(ret) => {
+                        round.steps += 1;
+                        if let Some(RunResult::Output(val)) = ret {
+                            round.outputs.push((machine, val));
+                        }
+                    },
+                    Err(IntcodeError::NoInput { .. }) => break,
+                    Err(error) => return Err(MachineError { machine, error }),
+                }
+            }
+
+            for region in regions {
+                for (i, val) in region.data.iter_mut().enumerate() {
+                    *val = comp.read_at(region.start + i as Int);
+                }
+            }
+        }
+
+        for &(src, val) in &round.outputs {
+            for &dest in &self.links[src] {
+                self.machines[dest].input(val);
+            }
+        }
+        self.rounds += 1;
+        Ok(round)
+    }
+
+    // Runs until a round in which no machine can move, returning all the
+    // outputs produced in order.
+    pub fn run(&mut self) -> Result<Vec<(usize, Int)>, MachineError> {
+        let mut outputs = vec![];
+        loop {
+            let round = self.step()?;
+            if round.steps == 0 {
+                return Ok(outputs);
+            }
+            outputs.extend(round.outputs);
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Cooperative round-robin scheduling on a single thread: every machine runs
+// until it blocks on input, finishes, or produces `max_outputs` values, and
+// then the next one gets its turn. What happens to the outputs is up to the
+// router, which delivers them right away.
+
+pub trait Router {
+    // Deliveries `(machine, value)` for the outputs of a turn.
+    fn route(&mut self, src: usize, outputs: &[Int]) -> Vec<(usize, Int)>;
+
+    // Input to give a machine whose queue is empty before its turn, if any
+    // (e.g. day 23's -1).
+    fn idle_input(&mut self, _machine: usize) -> Option<Int> {
+        None
+    }
+}
+
+impl<F: FnMut(usize, &[Int]) -> Vec<(usize, Int)>> Router for F {
+    fn route(&mut self, src: usize, outputs: &[Int]) -> Vec<(usize, Int)> {
+        self(src, outputs)
+    }
+}
+
+pub struct Scheduler<R: Router> {
+    machines: Vec<IntcodeComputer>,
+    router: R,
+    max_outputs: usize,
+}
+
+impl<R: Router> Scheduler<R> {
+    pub fn new(machines: Vec<IntcodeComputer>, router: R) -> Self {
+        Self { machines, router, max_outputs: usize::MAX }
+    }
+
+    // Ends turns early after this many outputs.
+    pub fn with_max_outputs(mut self, max_outputs: usize) -> Self {
+        self.max_outputs = max_outputs.max(1);
+        self
+    }
+
+    pub fn machines(&self) -> &[IntcodeComputer] {
+        &self.machines
+    }
+
+    pub fn router(&self) -> &R {
+        &self.router
+    }
+
+    // Gives every machine one turn, returning the instructions executed.
+    pub fn round(&mut self) -> Result<u64, MachineError> {
+        let mut steps = 0;
+        for machine in 0..self.machines.len() {
+            steps += self.turn(machine)?;
+        }
+        Ok(steps)
+    }
+
+    // Runs rounds until no machine can move.
+    pub fn run(&mut self) -> Result<(), MachineError> {
+        while self.round()? > 0 {}
+        Ok(())
+    }
+
+    fn turn(&mut self, machine: usize) -> Result<u64, MachineError> {
+        let comp = &mut self.machines[machine];
+        if comp.is_finished() {
+            return Ok(0);
+        }
+        if comp.pending_inputs() == 0 {
+            if let Some(val) = self.router.idle_input(machine) {
+                comp.input(val);
+            }
+        }
+
+        let start = comp.steps();
+        let mut outputs = vec![];
+        while outputs.len() < self.max_outputs {
+            match comp.try_run() {
+                Ok(RunResult::Output(val)) => outputs.push(val),
+                Ok(RunResult::Finished) | Err(IntcodeError::NoInput { .. }) => break,
+                Err(error) => return Err(MachineError { machine, error }),
+            }
+        }
+        let steps = comp.steps() - start;
+
+        for (dest, val) in self.router.route(machine, &outputs) {
+            if let Some(comp) = self.machines.get_mut(dest) {
+                comp.input(val);
+            }
+        }
+        Ok(steps)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Machines connected in series, each one's outputs becoming the next one's
+// inputs as they're produced. Optionally, the last machine feeds back into
+// the first (like day 7's feedback loop).
+
+pub struct Pipeline {
+    stages: Vec<IntcodeComputer>,
+    feedback: bool,
+}
+
+impl Pipeline {
+    pub fn new(first: IntcodeComputer) -> Self {
+        Self { stages: vec![first], feedback: false }
+    }
+
+    pub fn then(mut self, next: IntcodeComputer) -> Self {
+        self.stages.push(next);
+        self
+    }
+
+    // Also sends the last machine's outputs into the first one.
+    pub fn with_feedback(mut self) -> Self {
+        self.feedback = true;
+        self
+    }
+
+    // Input for the first machine.
+    pub fn input(mut self, value: Int) -> Self {
+        self.stages[0].input(value);
+        self
+    }
+
+    // Runs until no machine can move, returning the last machine's outputs.
+    pub fn run(self) -> Result<Vec<Int>, MachineError> {
+        let last = self.stages.len() - 1;
+        let feedback = self.feedback;
+        let mut outputs = vec![];
+
+        let mut scheduler = Scheduler::new(self.stages, |src: usize, out: &[Int]| {
+            if src < last {
+                return out.iter().map(|&val| (src + 1, val)).collect();
+            }
+            outputs.extend_from_slice(out);
+            if feedback { out.iter().map(|&val| (0, val)).collect() } else { vec![] }
+        });
+        scheduler.run()?;
+        drop(scheduler);
+        Ok(outputs)
+    }
+}
+
+// Runs `a` with its outputs piped into `b`, returning the outputs of `b`.
+pub fn pipe(a: IntcodeComputer, b: IntcodeComputer) -> Result<Vec<Int>, MachineError> {
+    Pipeline::new(a).then(b).run()
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Arbitrary graphs of machines and host devices (plain Rust code standing in
+// for a node), connected by channels. Running the topology drives every
+// node in rounds until nothing moves anymore.
+
+pub trait Host {
+    // Receives the values sent to this node since its last turn (possibly
+    // none), returning the values it sends.
+    fn receive(&mut self, inputs: &[Int]) -> Vec<Int>;
+}
+
+impl<F: FnMut(&[Int]) -> Vec<Int>> Host for F {
+    fn receive(&mut self, inputs: &[Int]) -> Vec<Int> {
+        self(inputs)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Channel {
+    // Every value, as is.
+    Stream,
+    // Every value, transformed on the way.
+    Map(fn(Int) -> Int),
+    // The sender's output is split into packets of `len` values whose first
+    // value is a destination address. Packets for `address` go through this
+    // channel, without the address. Packets include the address, so `len`
+    // is at least 1, and every packet channel leaving a node must agree on it.
+    Packet { address: Int, len: usize },
+}
+
+enum Node<'a> {
+    Machine(Box<IntcodeComputer>),
+    Host { host: Box<dyn Host + 'a>, inbox: Vec<Int> },
+}
+
+pub struct Topology<'a> {
+    nodes: Vec<Node<'a>>,
+    edges: Vec<(usize, usize, Channel)>,
+    // Packet being assembled by every node, and everything it has sent.
+    partial: Vec<Vec<Int>>,
+    sent: Vec<Vec<Int>>,
+}
+
+impl Default for Topology<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Topology<'a> {
+    pub fn new() -> Self {
+        Self { nodes: vec![], edges: vec![], partial: vec![], sent: vec![] }
+    }
+
+    // Adds a node, returning its id.
+    pub fn add_machine(&mut self, comp: IntcodeComputer) -> usize {
+        self.add_node(Node::Machine(Box::new(comp)))
+    }
+
+    pub fn add_host(&mut self, host: impl Host + 'a) -> usize {
+        self.add_node(Node::Host { host: Box::new(host), inbox: vec![] })
+    }
+
+    pub fn connect(&mut self, from: usize, to: usize, channel: Channel) -> &mut Self {
+        if let Channel::Packet { len, .. } = channel {
+            assert!(len >= 1, "Packets need room for their address");
+            let other = self.edges.iter().find_map(|&(src, _, channel)| match channel {
+                Channel::Packet { len, .. } if src == from => Some(len),
+                _ => None,
+            });
+            assert!(other.unwrap_or(len) == len, "Packet channels leaving a node must have the same length");
+        }
+
+        self.edges.push((from, to, channel));
+        self
+    }
+
+    pub fn machine(&self, node: usize) -> Option<&IntcodeComputer> {
+        match &self.nodes[node] {
+            Node::Machine(comp) => Some(comp),
+            Node::Host { .. } => None,
+        }
+    }
+
+    // Everything a node has sent so far.
+    pub fn outputs(&self, node: usize) -> &[Int] {
+        &self.sent[node]
+    }
+
+    // Runs rounds until no machine executes anything and no host sends
+    // anything.
+    pub fn run(&mut self) -> Result<(), MachineError> {
+        while self.round()? {}
+        Ok(())
+    }
+
+    // Gives every node one turn, returning whether anything happened.
+    pub fn round(&mut self) -> Result<bool, MachineError> {
+        let mut active = false;
+
+        for id in 0..self.nodes.len() {
+            let outputs = match &mut self.nodes[id] {
+                Node::Machine(comp) => {
+                    let start = comp.steps();
+                    let outputs = drain(comp).map_err(|error| MachineError { machine: id, error })?;
+                    active |= comp.steps() > start;
+                    outputs
+                },
+                Node::Host { host, inbox } => {
+                    let outputs = host.receive(&std::mem::take(inbox));
+                    active |= !outputs.is_empty();
+                    outputs
+                },
+            };
+
+            for val in outputs {
+                self.send(id, val);
+            }
+        }
+
+        Ok(active)
+    }
+
+    fn add_node(&mut self, node: Node<'a>) -> usize {
+        self.nodes.push(node);
+        self.partial.push(vec![]);
+        self.sent.push(vec![]);
+        self.nodes.len() - 1
+    }
+
+    fn send(&mut self, src: usize, val: Int) {
+        self.sent[src].push(val);
+        let mut packet_len = None;
+
+        for &(from, to, channel) in &self.edges {
+            match channel {
+                _ if from != src => {},
+                Channel::Stream => deliver(&mut self.nodes[to], &[val]),
+                Channel::Map(f) => deliver(&mut self.nodes[to], &[f(val)]),
+                Channel::Packet { len, .. } => packet_len = Some(len),
+            }
+        }
+
+        // All the packet channels of a node share the same buffer
+        let Some(len) = packet_len else { return };
+        self.partial[src].push(val);
+        if self.partial[src].len() < len {
+            return;
+        }
+        let packet = std::mem::take(&mut self.partial[src]);
+        for &(from, to, channel) in &self.edges {
+            if let (true, Channel::Packet { address, .. }) = (from == src, channel) {
+                if packet[0] == address {
+                    deliver(&mut self.nodes[to], &packet[1..]);
+                }
+            }
+        }
+    }
+}
+
+fn deliver(node: &mut Node, values: &[Int]) {
+    match node {
+        Node::Machine(comp) => comp.input_iter(values.iter().copied()),
+        Node::Host { inbox, .. } => inbox.extend_from_slice(values),
+    }
+}
+
+// Runs a machine until it blocks or finishes, collecting its outputs.
+fn drain(comp: &mut IntcodeComputer) -> Result<Vec<Int>, IntcodeError> {
+    let mut outputs = vec![];
+    loop {
+        match comp.try_run() {
+            Ok(RunResult::Output(val)) => outputs.push(val),
+            Ok(RunResult::Finished) | Err(IntcodeError::NoInput { .. }) => return Ok(outputs),
+            Err(e) => return Err(e),
+        }
+    }
+}
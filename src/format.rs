@@ -0,0 +1,57 @@
+use std::fmt;
+use std::num::NonZeroUsize;
+
+use crate::Int;
+use crate::header::HEADER_PREFIX;
+
+// Canonical text form for programs, so hand-maintained ones can be diffed
+// and reviewed. Values are laid out `per_line` to a line, right-aligned in
+// columns as wide as the widest value, with a comma after every value but
+// the last. The result is still valid program text, both for `from` and
+// `from_reader`. Header lines are kept as they are.
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FormatError {
+    // 0-based position of the value among all values.
+    pub index: usize,
+    pub token: String,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid value {:?} at position {}", self.token, self.index)
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+// Accepts the same text as `IntcodeComputer::from_reader`: values separated
+// by commas or newlines, skipping blank ones.
+pub fn format_program(code: &str, per_line: NonZeroUsize) -> Result<String, FormatError> {
+    let mut text = String::new();
+    let mut code = code;
+    while code.starts_with(HEADER_PREFIX) {
+        let (line, rest) = code.split_once('\n').unwrap_or((code, ""));
+        text.push_str(line.trim_end());
+        text.push('\n');
+        code = rest;
+    }
+
+    let tokens = code.split([',', '\n']).map(str::trim).filter(|t| !t.is_empty());
+    // Normalized, so "+5" and "05" are written as 5
+    let values = tokens.enumerate().map(|(index, token)| match token.parse::<Int>() {
+        Ok(val) => Ok(val.to_string()),
+        Err(_) => Err(FormatError { index, token: token.to_owned() }),
+    }).collect::<Result<Vec<_>, _>>()?;
+    let width = values.iter().map(String::len).max().unwrap_or(0);
+
+    for (i, line) in values.chunks(per_line.get()).enumerate() {
+        if i > 0 {
+            text.push_str(",\n");
+        }
+        let line: Vec<String> = line.iter().map(|val| format!("{val:>width$}")).collect();
+        text.push_str(&line.join(", "));
+    }
+    text.push('\n');
+    Ok(text)
+}
@@ -0,0 +1,86 @@
+use crate::{IntcodeComputer, IntcodeError, Int, RunResult};
+
+// Entry points for fuzzing the interpreter. A cargo-fuzz target only needs:
+//
+//     fuzz_target!(|data: &[u8]| { intcode_rs::fuzz::run(data); });
+//
+// Arbitrary bytes are decoded into a program plus its inputs, which are then
+// executed under strict limits through the non-panicking API. Any panic that
+// escapes from here is a bug in the interpreter.
+
+#[derive(Copy, Clone, Debug)]
+pub struct Limits {
+    pub max_steps: u64,
+    pub max_outputs: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self { max_steps: 10_000, max_outputs: 1_000 }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FuzzInput {
+    pub code: Vec<Int>,
+    pub inputs: Vec<Int>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Stop {
+    Finished,
+    OutputLimit,
+    Error(IntcodeError),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Outcome {
+    pub outputs: Vec<Int>,
+    pub stop: Stop,
+}
+
+// The first byte tells how many of the trailing values are inputs (up to 7),
+// the rest is read as little-endian 16-bit signed values. Small values keep
+// the fuzzer close to meaningful opcodes, modes and addresses.
+pub fn decode(data: &[u8]) -> FuzzInput {
+    let Some((&header, rest)) = data.split_first() else {
+        return FuzzInput { code: vec![], inputs: vec![] };
+    };
+
+    let mut values: Vec<Int> = rest.chunks(2)
+        .map(|c| i16::from_le_bytes([c[0], c.get(1).copied().unwrap_or_default()]) as Int)
+        .collect();
+    let n_inputs = (header as usize % 8).min(values.len());
+    let inputs = values.split_off(values.len() - n_inputs);
+
+    FuzzInput { code: values, inputs }
+}
+
+pub fn run(data: &[u8]) -> Outcome {
+    run_with_limits(data, Limits::default())
+}
+
+pub fn run_with_limits(data: &[u8], limits: Limits) -> Outcome {
+    let FuzzInput { code, inputs } = decode(data);
+    let mut comp = IntcodeComputer::new(&code);
+    comp.set_step_limit(Some(limits.max_steps));
+    for val in inputs {
+        comp.input(val);
+    }
+
+    let mut outputs = vec![];
+    let stop = loop {
+        match comp.try_run() {
+            Ok(RunResult::Output(val)) => {
+                if outputs.len() == limits.max_outputs {
+                    break Stop::OutputLimit;
+                }
+                outputs.push(val);
+            },
+            Ok(RunResult::Finished) => break Stop::Finished,
+            Err(e) => break Stop::Error(e),
+        }
+    };
+
+    Outcome { outputs, stop }
+}
@@ -0,0 +1,190 @@
+use crate::Int;
+use crate::intcode::Opcodes;
+
+// Random generation of structurally valid Intcode programs, meant to drive
+// property tests (e.g. "two engines agree on every generated program").
+//
+// Generated programs are laid out as a block of instructions, a final END,
+// and a data region. Instructions only ever write into the data region and
+// jumps only go forward, so every program terminates after at most
+// `max_instructions` steps and never modifies its own code.
+
+// Small, fast and seedable PRNG (SplitMix64), so that failing cases can be
+// reproduced from their seed alone.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform-ish value in the inclusive range [lo, hi].
+    pub fn range(&mut self, lo: Int, hi: Int) -> Int {
+        let span = (hi - lo + 1) as u128;
+        lo + (self.next_u64() as u128 % span) as Int
+    }
+
+    pub fn chance(&mut self, percent: u64) -> bool {
+        self.next_u64() % 100 < percent
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GeneratedProgram {
+    pub code: Vec<Int>,
+    // Exactly the inputs the program may consume, one per IN instruction.
+    pub inputs: Vec<Int>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ProgramGenerator {
+    pub max_instructions: usize,
+    pub data_cells: usize,
+    // Bound for the absolute value of constants, initial data and inputs.
+    pub max_value: Int,
+}
+
+// Every arithmetic instruction can at most double the magnitude of a value,
+// so this keeps `max_value * 2^max_instructions` well inside `Int`.
+const MAX_INSTRUCTIONS: usize = 64;
+
+impl Default for ProgramGenerator {
+    fn default() -> Self {
+        Self { max_instructions: 32, data_cells: 8, max_value: 1_000 }
+    }
+}
+
+// Operations the generator picks from, with their number of parameters.
+const CHOICES: [(u8, usize); 9] = [
+    (Opcodes::ADD, 3), (Opcodes::MUL, 3), (Opcodes::IN, 1), (Opcodes::OUT, 1),
+    (Opcodes::JMP, 2), (Opcodes::JMN, 2), (Opcodes::LT, 3), (Opcodes::EQ, 3),
+    (Opcodes::RLB, 1),
+];
+
+impl ProgramGenerator {
+    pub fn generate(&self, rng: &mut Rng) -> GeneratedProgram {
+        assert!(self.max_instructions <= MAX_INSTRUCTIONS, "At most {MAX_INSTRUCTIONS} instructions are supported");
+        assert!(self.data_cells > 0, "At least one data cell is needed");
+
+        let n_instr = rng.range(0, self.max_instructions as Int) as usize;
+        let ops: Vec<(u8, usize)> = (0..n_instr).map(|_| CHOICES[rng.range(0, 8) as usize]).collect();
+
+        // Instruction addresses are known up front, which lets jumps target any
+        // later instruction. The extra entry is the address of the final END.
+        let mut addrs = Vec::with_capacity(n_instr + 1);
+        let mut addr = 0;
+        for &(_, n_params) in &ops {
+            addrs.push(addr);
+            addr += 1 + n_params as Int;
+        }
+        addrs.push(addr);
+        let data_start = addr + 1;
+
+        // Jumps must not skip over a RLB, otherwise the relative base at a
+        // given instruction would depend on the path taken and relative
+        // parameters could escape the data region.
+        let mut barriers = vec![n_instr; n_instr + 1];
+        for i in (0..n_instr).rev() {
+            barriers[i] = if ops[i].0 == Opcodes::RLB { i } else { barriers[i + 1] };
+        }
+
+        let mut ctx = Context { rng, data_start, data_cells: self.data_cells as Int, max_value: self.max_value, rel_base: 0 };
+        let mut code = vec![];
+        let mut inputs = vec![];
+
+        for (i, &(opcode, _)) in ops.iter().enumerate() {
+            let (modes, params) = match opcode {
+                Opcodes::ADD | Opcodes::LT | Opcodes::EQ => {
+                    let (m1, p1) = ctx.read_param();
+                    let (m2, p2) = ctx.read_param();
+                    let (m3, p3) = ctx.write_param();
+                    ([m1, m2, m3], vec![p1, p2, p3])
+                },
+                Opcodes::MUL => {
+                    // Multiplying by a small constant keeps values bounded
+                    let (m1, p1) = ctx.read_param();
+                    let p2 = ctx.rng.range(-2, 2);
+                    let (m3, p3) = ctx.write_param();
+                    ([m1, 1, m3], vec![p1, p2, p3])
+                },
+                Opcodes::IN => {
+                    inputs.push(ctx.rng.range(-self.max_value, self.max_value));
+                    let (m, p) = ctx.write_param();
+                    ([m, 0, 0], vec![p])
+                },
+                Opcodes::OUT => {
+                    let (m, p) = ctx.read_param();
+                    ([m, 0, 0], vec![p])
+                },
+                Opcodes::JMP | Opcodes::JMN => {
+                    let (m, p) = ctx.read_param();
+                    let target = ctx.rng.range(i as Int + 1, barriers[i] as Int);
+                    ([m, 1, 0], vec![p, addrs[target as usize]])
+                },
+                Opcodes::RLB => {
+                    // Moves the relative base to a new offset inside the data region
+                    let new_base = ctx.data_start + ctx.rng.range(0, ctx.data_cells - 1);
+                    let delta = new_base - ctx.rel_base;
+                    ctx.rel_base = new_base;
+                    ([1, 0, 0], vec![delta])
+                },
+                _ => unreachable!(),
+            };
+            code.push(encode(opcode, modes));
+            code.extend(params);
+        }
+
+        code.push(Opcodes::END as Int);
+        for _ in 0..self.data_cells {
+            code.push(ctx.rng.range(-self.max_value, self.max_value));
+        }
+
+        GeneratedProgram { code, inputs }
+    }
+
+    // Endless stream of programs derived from a single seed.
+    pub fn programs(self, seed: u64) -> impl Iterator<Item = GeneratedProgram> {
+        let mut rng = Rng::new(seed);
+        std::iter::repeat_with(move || self.generate(&mut rng))
+    }
+}
+
+struct Context<'a> {
+    rng: &'a mut Rng,
+    data_start: Int,
+    data_cells: Int,
+    max_value: Int,
+    rel_base: Int,
+}
+
+impl Context<'_> {
+    fn read_param(&mut self) -> (u8, Int) {
+        match self.rng.range(0, 2) {
+            1 => (1, self.rng.range(-self.max_value, self.max_value)),
+            _ => self.write_param(),
+        }
+    }
+
+    // Position or relative parameter pointing into the data region.
+    fn write_param(&mut self) -> (u8, Int) {
+        let cell = self.data_start + self.rng.range(0, self.data_cells - 1);
+        if self.rng.chance(50) {
+            (0, cell)
+        } else {
+            (2, cell - self.rel_base)
+        }
+    }
+}
+
+fn encode(opcode: u8, modes: [u8; 3]) -> Int {
+    opcode as Int + 100 * modes[0] as Int + 1_000 * modes[1] as Int + 10_000 * modes[2] as Int
+}
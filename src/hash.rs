@@ -0,0 +1,12 @@
+// Hash maps used throughout the crate. With the `fxhash` feature (on by
+// default) they use rustc-hash's fast, non-cryptographic hasher. Without it
+// they fall back to the standard library's, so the crate needs no
+// dependencies at all. Either way hashing is deterministic.
+
+#[cfg(feature = "fxhash")]
+pub type BuildHasher = rustc_hash::FxBuildHasher;
+#[cfg(not(feature = "fxhash"))]
+pub type BuildHasher = std::hash::BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+
+pub type HashMap<K, V> = std::collections::HashMap<K, V, BuildHasher>;
+pub type HashSet<T> = std::collections::HashSet<T, BuildHasher>;
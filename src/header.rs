@@ -0,0 +1,146 @@
+use std::fmt;
+
+use crate::Int;
+
+// Optional metadata at the top of program files, so shared programs can be
+// identified and corrupted ones rejected before they misbehave. Header lines
+// start with `#!` and hold one `key: value` field each:
+//
+//     #! name: BOOST
+//     #! version: 1.2
+//     #! entry: 0
+//     #! checksum: 0123456789abcdef
+//
+// The checksum is a 64-bit FNV-1a hash of the values, each one taken as 16
+// little-endian bytes. Loaders check it when present, and start running at
+// the entry point instead of 0. Unknown fields are kept as they are.
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+pub(crate) const HEADER_PREFIX: &str = "#!";
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct ProgramHeader {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub entry: Option<Int>,
+    pub checksum: Option<u64>,
+    pub extra: Vec<(String, String)>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum HeaderError {
+    InvalidLine(String),
+    ChecksumMismatch { expected: u64, actual: u64 },
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidLine(line) => write!(f, "Invalid header line: {line:?}"),
+            Self::ChecksumMismatch { expected, actual } => {
+                write!(f, "Checksum mismatch: the header says {expected:016x}, the program has {actual:016x}")
+            },
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+// Incremental checksum, for loading programs as they're read.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Checksum(u64);
+
+impl Default for Checksum {
+    fn default() -> Self {
+        Self(FNV_OFFSET)
+    }
+}
+
+impl Checksum {
+    pub fn push(&mut self, value: Int) {
+        for byte in value.to_le_bytes() {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    pub fn finish(self) -> u64 {
+        self.0
+    }
+}
+
+impl ProgramHeader {
+    pub fn checksum_of(code: &[Int]) -> u64 {
+        let mut checksum = Checksum::default();
+        code.iter().for_each(|&val| checksum.push(val));
+        checksum.finish()
+    }
+
+    // Sets the checksum to the program's.
+    pub fn seal(&mut self, code: &[Int]) {
+        self.checksum = Some(Self::checksum_of(code));
+    }
+
+    pub(crate) fn verify_checksum(&self, actual: u64) -> Result<(), HeaderError> {
+        match self.checksum {
+            Some(expected) if expected != actual => Err(HeaderError::ChecksumMismatch { expected, actual }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn verify(&self, code: &[Int]) -> Result<(), HeaderError> {
+        self.verify_checksum(Self::checksum_of(code))
+    }
+
+    // Adds the field in a `#! key: value` line.
+    pub(crate) fn parse_line(&mut self, line: &str) -> Result<(), HeaderError> {
+        let invalid = || HeaderError::InvalidLine(line.to_owned());
+        let field = line.trim().strip_prefix(HEADER_PREFIX).ok_or_else(invalid)?;
+        let (key, value) = field.split_once(':').ok_or_else(invalid)?;
+        let (key, value) = (key.trim(), value.trim());
+        match key {
+            "name" => self.name = Some(value.to_owned()),
+            "version" => self.version = Some(value.to_owned()),
+            "entry" => self.entry = Some(value.parse().map_err(|_| invalid())?),
+            "checksum" => self.checksum = Some(u64::from_str_radix(value, 16).map_err(|_| invalid())?),
+            _ => self.extra.push((key.to_owned(), value.to_owned())),
+        }
+        Ok(())
+    }
+}
+
+// The header lines, each one ending with a newline.
+impl fmt::Display for ProgramHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(name) = &self.name {
+            writeln!(f, "{HEADER_PREFIX} name: {name}")?;
+        }
+        if let Some(version) = &self.version {
+            writeln!(f, "{HEADER_PREFIX} version: {version}")?;
+        }
+        if let Some(entry) = self.entry {
+            writeln!(f, "{HEADER_PREFIX} entry: {entry}")?;
+        }
+        if let Some(checksum) = self.checksum {
+            writeln!(f, "{HEADER_PREFIX} checksum: {checksum:016x}")?;
+        }
+        for (key, value) in &self.extra {
+            writeln!(f, "{HEADER_PREFIX} {key}: {value}")?;
+        }
+        Ok(())
+    }
+}
+
+// Splits a program's text into its header, if it starts with one, and the
+// code.
+pub fn split_header(text: &str) -> Result<(Option<ProgramHeader>, &str), HeaderError> {
+    let mut header = None;
+    let mut rest = text;
+    while rest.starts_with(HEADER_PREFIX) {
+        let (line, tail) = rest.split_once('\n').unwrap_or((rest, ""));
+        header.get_or_insert_with(ProgramHeader::default).parse_line(line)?;
+        rest = tail;
+    }
+    Ok((header, rest))
+}
@@ -0,0 +1,55 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{IntcodeComputer, Int, Operand};
+use crate::intcode::Opcodes;
+
+// Callbacks around every instruction, for instrumentation and teaching tools.
+// Both get the computer and the decoded instruction: the pre-hook right
+// before executing it, deciding whether it runs at all, and the post-hook
+// right after, once the IP has moved on. Operand values are the ones before
+// the instruction ran, peeked (see `peek_at`) so looking at them doesn't
+// touch devices.
+//
+// Like devices, hooks are shared by copies of a computer.
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Instruction {
+    pub ip: Int,
+    pub opcode: u8,
+    pub operands: Vec<Operand>,
+}
+
+impl Instruction {
+    // "???" for opcodes that aren't built in.
+    pub fn mnemonic(&self) -> &'static str {
+        Opcodes::mnemonic(self.opcode)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum HookAction {
+    Continue,
+    // Moves the IP past the instruction without executing it. It still
+    // counts as a step.
+    Skip,
+    // Stops with an `Aborted` error, leaving the IP at the instruction.
+    Abort,
+}
+
+pub type PreHook = dyn FnMut(&IntcodeComputer, &Instruction) -> HookAction + Send;
+pub type PostHook = dyn FnMut(&IntcodeComputer, &Instruction) + Send;
+
+impl IntcodeComputer {
+    pub fn set_pre_hook(&mut self, hook: impl FnMut(&IntcodeComputer, &Instruction) -> HookAction + Send + 'static) {
+        self.pre_hook = Some(Arc::new(Mutex::new(hook)));
+    }
+
+    pub fn set_post_hook(&mut self, hook: impl FnMut(&IntcodeComputer, &Instruction) + Send + 'static) {
+        self.post_hook = Some(Arc::new(Mutex::new(hook)));
+    }
+
+    pub fn clear_hooks(&mut self) {
+        self.pre_hook = None;
+        self.post_hook = None;
+    }
+}
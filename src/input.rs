@@ -0,0 +1,64 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{IntcodeComputer, Int};
+
+// Input produced on demand. When an IN instruction finds the queue empty, the
+// handler is asked what to do before giving up with a `NoInput` error. This
+// fits programs that poll for input, like day 13's joystick or day 23's
+// network reading -1 when there's nothing for it.
+//
+// Like devices, the handler is shared by copies of a computer.
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum InputDecision {
+    Provide(Int),
+    // Stops with a `NoInput` error, as if there was no handler.
+    Block,
+    // Provides the computer's default input (see `set_default_input`).
+    DefaultValue,
+}
+
+pub type InputRequestHandler = dyn FnMut() -> InputDecision + Send;
+
+#[derive(Clone)]
+pub(crate) enum InputSource {
+    Handler(Arc<Mutex<InputRequestHandler>>),
+    // Handlers that can be called from many threads at once, so one of them
+    // blocking doesn't hold up copies of the computer sharing it.
+    Shared(Arc<dyn Fn() -> InputDecision + Send + Sync>),
+}
+
+impl IntcodeComputer {
+    pub fn set_input_request_handler(&mut self, handler: impl FnMut() -> InputDecision + Send + 'static) {
+        self.input_handler = Some(InputSource::Handler(Arc::new(Mutex::new(handler))));
+    }
+
+    pub(crate) fn set_shared_input_handler(&mut self, handler: impl Fn() -> InputDecision + Send + Sync + 'static) {
+        self.input_handler = Some(InputSource::Shared(Arc::new(handler)));
+    }
+
+    pub fn clear_input_request_handler(&mut self) {
+        self.input_handler = None;
+    }
+
+    // Value given for `InputDecision::DefaultValue`, 0 unless set.
+    pub fn set_default_input(&mut self, value: Int) {
+        self.default_input = value;
+    }
+
+    // Asks the handler for an input, queuing it. Returns whether it did.
+    pub(crate) fn request_input(&mut self) -> bool {
+        let Some(handler) = &self.input_handler else { return false };
+        let decision = match handler {
+            InputSource::Handler(handler) => handler.lock().unwrap()(),
+            InputSource::Shared(handler) => handler(),
+        };
+        let value = match decision {
+            InputDecision::Provide(value) => value,
+            InputDecision::DefaultValue => self.default_input,
+            InputDecision::Block => return false,
+        };
+        self.input_queue.push_back(value);
+        true
+    }
+}
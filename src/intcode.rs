@@ -1,29 +1,129 @@
-use rustc_hash::FxHashMap;
 use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::ops::{Index, IndexMut};
+use std::sync::{Arc, Mutex};
+
+use crate::builder::IntcodeBuilder;
+use crate::custom::{CustomOp, Effect, Operand};
+use crate::cycle::CycleDetector;
+use crate::device::MappedDevice;
+use crate::error::IntcodeError;
+use crate::hash::{HashMap, HashSet};
+use crate::header::{split_header, Checksum, HeaderError, ProgramHeader, HEADER_PREFIX};
+use crate::hooks::{HookAction, Instruction, PostHook, PreHook};
+use crate::input::InputSource;
+use crate::interrupt::InputInterrupt;
+use crate::memory::Memory;
+use crate::observer::{Event, Observer};
+use crate::selfmod::{CodeWatch, CodeWrite};
+use crate::symbols::SymbolTable;
+use crate::stats::StatsCollector;
 
 // Type for the integers used by the computer.
 pub type Int = i128;
 
+// Fractional bits of the fixed-point numbers used by FMUL and FDIV, so
+// `1 << FIXED_POINT_BITS` stands for 1.0.
+#[cfg(feature = "extensions")]
+pub const FIXED_POINT_BITS: u32 = 16;
+
+pub type OutputCallback = dyn FnMut(Int) + Send;
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum RunResult {
     Output(Int),
     Finished,
 }
 
+// Every reason for `run_until_stop` to return, so callers can handle them
+// all in a single match.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum StopReason {
+    Output(Int),
+    Finished,
+    NeedsInput { ip: Int },
+    Breakpoint { ip: Int },
+    // Executed code was overwritten, see `set_code_write_mode`.
+    CodeWrite { ip: Int, addr: Int },
+    StepLimit { steps: u64 },
+    Error(IntcodeError),
+}
+
+impl From<RunResult> for StopReason {
+    fn from(result: RunResult) -> Self {
+        match result {
+            RunResult::Output(val) => Self::Output(val),
+            RunResult::Finished => Self::Finished,
+        }
+    }
+}
+
+impl From<IntcodeError> for StopReason {
+    fn from(error: IntcodeError) -> Self {
+        match error {
+            IntcodeError::NoInput { ip } => Self::NeedsInput { ip },
+            IntcodeError::StepLimit { steps } => Self::StepLimit { steps },
+            e => Self::Error(e),
+        }
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Output(val) => write!(f, "Output: {val}"),
+            Self::Finished => write!(f, "Finished"),
+            Self::NeedsInput { ip } => write!(f, "Waiting for input at {ip}"),
+            Self::Breakpoint { ip } => write!(f, "Breakpoint at {ip}"),
+            Self::CodeWrite { ip, addr } => write!(f, "Code at {addr} overwritten by the instruction at {ip}"),
+            Self::StepLimit { steps } => write!(f, "Step limit reached after {steps} steps"),
+            Self::Error(e) => write!(f, "Error: {e}"),
+        }
+    }
+}
+
 #[derive(Default, Clone)]
 pub struct IntcodeComputer {
-    memory: FxHashMap<Int, Int>,
-    input_queue: VecDeque<Int>,
-    ip: Int,
-    rel_base: Int,
-    is_finished: bool,
+    pub(crate) memory: Memory,
+    pub(crate) input_queue: VecDeque<Int>,
+    pub(crate) ip: Int,
+    pub(crate) rel_base: Int,
+    pub(crate) is_finished: bool,
+    pub(crate) steps: u64,
+    step_limit: Option<u64>,
+    strict: bool,
+    custom_ops: HashMap<u8, CustomOp>,
+    pub(crate) devices: Vec<MappedDevice>,
+    pub(crate) interrupt: Option<InputInterrupt>,
+    breakpoints: HashSet<Int>,
+    opcode_breakpoints: HashSet<u8>,
+    output_buffer: Option<Vec<Int>>,
+    partial_chunk: Vec<Int>,
+    pub(crate) observers: Vec<Arc<Mutex<dyn Observer>>>,
+    pub(crate) pre_hook: Option<Arc<Mutex<PreHook>>>,
+    pub(crate) post_hook: Option<Arc<Mutex<PostHook>>>,
+    pub(crate) input_handler: Option<InputSource>,
+    pub(crate) default_input: Int,
+    pub(crate) output_callback: Option<Arc<Mutex<OutputCallback>>>,
+    pub(crate) stats: Option<Box<StatsCollector>>,
+    pub(crate) cycles: Option<CycleDetector>,
+    pub(crate) code_watch: Option<Box<CodeWatch>>,
+    pub(crate) symbols: Option<Arc<SymbolTable>>,
+    header: Option<Arc<ProgramHeader>>,
 }
 
 //////////////////////////////////////////////////////////////////////////////////////////////////////
-/// Internal stuff
+// Internal stuff
+
+#[cfg(feature = "extensions")]
+fn floor_div(a: Int, b: Int) -> Option<Int> {
+    let q = a.checked_div(b)?;
+    Some(if a % b != 0 && (a < 0) != (b < 0) { q - 1 } else { q })
+}
 
 // Intcode operation codes.
-struct Opcodes;
+pub(crate) struct Opcodes;
 impl Opcodes {
     pub const ADD: u8 = 1;
     pub const MUL: u8 = 2;
@@ -35,6 +135,55 @@ impl Opcodes {
     pub const EQ:  u8 = 8;
     pub const RLB: u8 = 9;
     pub const END: u8 = 99;
+
+    // Extensions, not part of the AoC instruction set. DIV truncates towards
+    // zero and MOD takes the sign of the dividend. FMUL and FDIV work on
+    // fixed-point numbers, rounding towards negative infinity.
+    #[cfg(feature = "extensions")]
+    pub const DIV: u8 = 10;
+    #[cfg(feature = "extensions")]
+    pub const MOD: u8 = 11;
+    #[cfg(feature = "extensions")]
+    pub const FMUL: u8 = 12;
+    #[cfg(feature = "extensions")]
+    pub const FDIV: u8 = 13;
+
+    // Number of parameters of a built-in instruction.
+    pub fn n_params(opcode: u8) -> Option<usize> {
+        match opcode {
+            Self::END                                   => Some(0),
+            Self::IN  | Self::OUT | Self::RLB           => Some(1),
+            Self::JMP | Self::JMN                       => Some(2),
+            Self::ADD | Self::MUL | Self::EQ | Self::LT => Some(3),
+            #[cfg(feature = "extensions")]
+            Self::DIV | Self::MOD | Self::FMUL | Self::FDIV => Some(3),
+            _ => None,
+        }
+    }
+
+    pub fn mnemonic(opcode: u8) -> &'static str {
+        match opcode {
+            Self::ADD => "ADD",
+            Self::MUL => "MUL",
+            Self::IN  => "IN",
+            Self::OUT => "OUT",
+            Self::JMP => "JMP",
+            Self::JMN => "JMN",
+            Self::LT  => "LT",
+            Self::EQ  => "EQ",
+            Self::RLB => "RLB",
+            Self::END => "END",
+            #[cfg(feature = "extensions")]
+            Self::DIV => "DIV",
+            #[cfg(feature = "extensions")]
+            Self::MOD => "MOD",
+            #[cfg(feature = "extensions")]
+            Self::FMUL => "FMUL",
+            #[cfg(feature = "extensions")]
+            Self::FDIV => "FDIV",
+            _ => "???",
+        }
+    }
 }
 
 // Parameter modes
@@ -52,155 +201,889 @@ struct Param {
     value: Int,
 }
 
+type OpResult = Result<(), IntcodeError>;
+
 // These intcode computers are one-time use only, proudly contributing to e-waste.
 impl IntcodeComputer {
 
     pub fn new(code: &[Int]) -> Self {
-        let memory = code.iter().enumerate().map(|(i, v)| (i as Int, *v)).collect();
-        Self { memory, input_queue: VecDeque::new(), ip: 0, rel_base: 0, is_finished: false }
+        Self { memory: Memory::new(code), ..Default::default() }
+    }
+
+    // Turns this computer into a fresh one loaded with the code, as if
+    // created by `new`, but reusing the memory it has already allocated.
+    pub fn reset(&mut self, code: &[Int]) {
+        let mut memory = std::mem::take(&mut self.memory);
+        memory.reset(code);
+        let mut input_queue = std::mem::take(&mut self.input_queue);
+        input_queue.clear();
+        *self = Self { memory, input_queue, ..Default::default() };
     }
 
     pub fn input(&mut self, value: Int) {
         self.input_queue.push_back(value);
+        self.raise_interrupt();
+    }
+
+    pub fn input_iter(&mut self, values: impl IntoIterator<Item = Int>) {
+        self.input_queue.extend(values);
+        self.raise_interrupt();
+    }
+
+    // Limits the total number of instructions this computer will execute.
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    // In strict mode, accessing negative addresses is an error.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    // With buffering on, outputs are kept in an internal buffer instead of
+    // stopping the run, which then only returns once the program finishes
+    // (or can't go on). Turning it off drops anything still buffered.
+    pub fn set_buffered_output(&mut self, buffered: bool) {
+        self.output_buffer = buffered.then(|| self.output_buffer.take().unwrap_or_default());
+    }
+
+    // Takes every output buffered so far.
+    pub fn drain_outputs(&mut self) -> Vec<Int> {
+        self.output_buffer.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    // Sends every output to the callback instead of stopping the run, which
+    // then only returns once the program finishes (or can't go on). Takes
+    // precedence over buffering. Copies of the computer share the callback.
+    pub fn set_output_callback(&mut self, callback: impl FnMut(Int) + Send + 'static) {
+        self.output_callback = Some(Arc::new(Mutex::new(callback)));
+    }
+
+    pub fn clear_output_callback(&mut self) {
+        self.output_callback = None;
+    }
+
+    // Adds an instruction to this computer. The handler gets the resolved
+    // parameters and the computer itself, with the IP still pointing at the
+    // instruction. Built-in opcodes can't be replaced.
+    pub fn register_opcode<F>(&mut self, opcode: u8, n_params: usize, handler: F)
+    where
+        F: Fn(&mut IntcodeComputer, &[Operand]) -> Result<Effect, IntcodeError> + Send + Sync + 'static,
+    {
+        assert!(Opcodes::mnemonic(opcode) == "???" && opcode < 100, "Opcode {opcode} is already taken");
+        assert!(n_params <= 3, "Instructions have at most 3 parameters");
+        self.custom_ops.insert(opcode, CustomOp { n_params, handler: Arc::new(handler) });
+    }
+
+    pub fn builder(code: &[Int]) -> IntcodeBuilder {
+        IntcodeBuilder::new(code)
     }
 
     pub fn run(&mut self) -> RunResult {
-        while !self.is_finished {
-            let (opcode, params) = self.parse_operation();
-
-            match opcode {
-                Opcodes::ADD => self.op_add(&params),
-                Opcodes::MUL => self.op_mul(&params),
-                Opcodes::IN => self.op_in(&params),
-                Opcodes::OUT => {
-                    let ret = self.param_value(&params[0]);
-                    return RunResult::Output(ret);
+        self.try_run().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    // Queues the inputs and runs to completion, returning every output.
+    // Panics on errors, including running out of input.
+    pub fn run_with_inputs(&mut self, inputs: &[Int]) -> Vec<Int> {
+        self.input_iter(inputs.iter().copied());
+        let mut outputs = vec![];
+        while let RunResult::Output(val) = self.run() {
+            outputs.push(val);
+        }
+        outputs
+    }
+
+    // Same as `run`, but never panics: any problem is reported as an error.
+    // When no input is available, the IN instruction is left unexecuted so
+    // the computer can be resumed after providing one.
+    pub fn try_run(&mut self) -> Result<RunResult, IntcodeError> {
+        self.timed(|comp| {
+            while !comp.is_finished {
+                if let Some(ret) = comp.exec_next()? {
+                    return Ok(ret);
+                }
+            }
+
+            Ok(RunResult::Finished)
+        })
+    }
+
+    // Runs until `N` outputs have been produced and returns them together,
+    // for protocols where outputs come in groups (e.g. day 13's tiles). If
+    // the run stops earlier because of an error, such as waiting for input,
+    // the outputs so far are kept for the next call. Returns `None` once the
+    // program finishes, dropping any incomplete chunk. Buffered outputs are
+    // left in the buffer and don't count.
+    pub fn run_until_n_outputs<const N: usize>(&mut self) -> Result<Option<[Int; N]>, IntcodeError> {
+        while self.partial_chunk.len() < N {
+            match self.try_run()? {
+                RunResult::Output(val) => self.partial_chunk.push(val),
+                RunResult::Finished => {
+                    self.partial_chunk.clear();
+                    return Ok(None);
                 },
-                Opcodes::JMP => self.op_jmp(&params),
-                Opcodes::JMN => self.op_jmn(&params),
-                Opcodes::LT => self.op_lt(&params),
-                Opcodes::EQ => self.op_eq(&params),
-                Opcodes::RLB => self.op_rlb(&params),
-                Opcodes::END => self.is_finished = true,
-                x => panic!("Unexpected opcode: {x}"),
             }
         }
+        let chunk = self.partial_chunk.drain(..N).collect::<Vec<_>>();
+        Ok(Some(chunk.try_into().unwrap()))
+    }
+
+    // Same as `try_run`, also stopping before executing an instruction at a
+    // breakpoint. The first instruction is always executed, so calling it
+    // again resumes from a breakpoint.
+    pub fn run_until_stop(&mut self) -> StopReason {
+        self.timed(|comp| {
+            let start = comp.steps;
+            while !comp.is_finished {
+                if comp.steps != start && comp.at_breakpoint() {
+                    return StopReason::Breakpoint { ip: comp.ip };
+                }
+                match comp.exec_next() {
+                    Ok(Some(ret)) => return ret.into(),
+                    Ok(None) => if let Some(CodeWrite { ip, addr }) = comp.take_code_write_hit() {
+                        return StopReason::CodeWrite { ip, addr };
+                    },
+                    Err(e) => return e.into(),
+                }
+            }
+
+            StopReason::Finished
+        })
+    }
+
+    // Breakpoints are only honored by `run_until_stop`.
+    pub fn add_breakpoint(&mut self, addr: Int) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: Int) {
+        self.breakpoints.remove(&addr);
+    }
+
+    // Breaks before every instruction with the opcode, wherever it is.
+    pub fn add_opcode_breakpoint(&mut self, opcode: u8) {
+        self.opcode_breakpoints.insert(opcode);
+    }
+
+    pub fn remove_opcode_breakpoint(&mut self, opcode: u8) {
+        self.opcode_breakpoints.remove(&opcode);
+    }
 
-        RunResult::Finished
+    pub(crate) fn at_breakpoint(&self) -> bool {
+        self.breakpoints.contains(&self.ip)
+            || !self.opcode_breakpoints.is_empty()
+                && self.peek_instruction().is_ok_and(|(opcode, _)| self.opcode_breakpoints.contains(&opcode))
     }
 
     pub fn read_at(&self, pos: Int) -> Int {
+        if let Some((dev, offset)) = self.device_at(pos) {
+            return dev.device.lock().unwrap().read(offset);
+        }
         // Reads raw data from memory from a given position
-        self.memory.get(&pos).copied().unwrap_or_default()
+        self.memory.get(pos).copied().unwrap_or_default()
+    }
+
+    // Same as `read_at`, but peeking at devices instead of reading them, so
+    // nothing changes.
+    pub fn peek_at(&self, pos: Int) -> Int {
+        if let Some((dev, offset)) = self.device_at(pos) {
+            return dev.device.lock().unwrap().peek(offset);
+        }
+        self.memory.get(pos).copied().unwrap_or_default()
+    }
+
+    pub fn write_at(&mut self, pos: Int, value: Int) {
+        self.reset_cycles();
+        self.store(pos, value);
+    }
+
+    fn store(&mut self, pos: Int, value: Int) {
+        if let Some((dev, offset)) = self.device_at(pos) {
+            return dev.device.lock().unwrap().write(offset, value);
+        }
+        // Writes raw data to memory at a given position
+        self.memory.insert(pos, value);
+    }
+
+    // All memory cells that have ever been set, sorted by address.
+    pub fn memory(&self) -> impl Iterator<Item = (Int, Int)> {
+        let mut cells: Vec<(Int, Int)> = self.memory.iter().collect();
+        cells.sort_unstable();
+        cells.into_iter()
+    }
+
+    pub fn memory_len(&self) -> usize {
+        self.memory.len()
+    }
+
+    pub fn max_addr(&self) -> Option<Int> {
+        self.memory.iter().map(|(pos, _)| pos).max()
+    }
+
+    // Addresses of the cells holding the value, sorted. Like `memory`, only
+    // looks at cells that have been set, and ignores devices.
+    pub fn find_value(&self, value: Int) -> Vec<Int> {
+        self.memory().filter(|&(_, val)| val == value).map(|(pos, _)| pos).collect()
+    }
+
+    // Addresses where the values appear in consecutive cells, sorted. Matches
+    // must start at a cell that has been set, but may run past them.
+    pub fn find_sequence(&self, values: &[Int]) -> Vec<Int> {
+        let Some(&first) = values.first() else { return vec![] };
+        let matches_at = |pos: Int| values.iter().enumerate().all(|(i, &val)| {
+            pos.checked_add(i as Int).is_some_and(|addr| self[addr] == val)
+        });
+        self.find_value(first).into_iter().filter(|&pos| matches_at(pos)).collect()
+    }
+
+    pub fn ip(&self) -> Int {
+        self.ip
+    }
+
+    pub fn relative_base(&self) -> Int {
+        self.rel_base
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.is_finished
+    }
+
+    pub fn pending_inputs(&self) -> usize {
+        self.input_queue.len()
+    }
+
+    // Number of instructions executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn step_limit(&self) -> Option<u64> {
+        self.step_limit
     }
 
     //////////////////////////////////////////////////////////////////////////////////////////////////////
 
-    fn op_add(&mut self, params: &[Param]) {
-        let v1 = self.param_value(&params[0]);
-        let v2 = self.param_value(&params[1]);
-        let res = v1 + v2;
-        self.write_to(&params[2], res);
+    fn device_at(&self, pos: Int) -> Option<(&MappedDevice, Int)> {
+        self.devices.iter().find_map(|dev| dev.offset_of(pos).map(|offset| (dev, offset)))
     }
 
-    fn op_mul(&mut self, params: &[Param]) {
-        let v1 = self.param_value(&params[0]);
-        let v2 = self.param_value(&params[1]);
-        let res = v1 * v2;
-        self.write_to(&params[2], res);
+    // Opcode and number of parameters of the instruction at the IP.
+    pub(crate) fn peek_instruction(&self) -> Result<(u8, usize), IntcodeError> {
+        self.peek_operation_at(self.ip).map(|(opcode, _, n_params)| (opcode, n_params))
     }
 
-    fn op_in(&mut self, params: &[Param]) {
-        // I just wanted to implement the thing, not solve the rest of the problems
-        // that involve using the computer, so I made the simplification of assuming
-        // that an input will always be available.
-        let input = self.input_queue.pop_front().expect("No input available");
-        self.write_to(&params[0], input);
+    // Opcode of the instruction at the IP and the memory cell behind each of
+    // its parameters (for immediate ones, the cell holding the value).
+    pub(crate) fn param_addrs(&self) -> Result<(u8, Vec<Int>), IntcodeError> {
+        let (opcode, params, n_params) = self.peek_operation_at(self.ip)?;
+        let addrs = params.iter().take(n_params).enumerate().map(|(i, param)| match param.mode {
+            ParamMode::Immediate => self.ip.checked_add(i as Int + 1).ok_or(self.overflow()),
+            ParamMode::Position => self.checked_addr(param.value),
+            ParamMode::Relative => self.relative_addr(param),
+        }).collect::<Result<_, _>>()?;
+        Ok((opcode, addrs))
     }
 
-    fn op_jmp(&mut self, params: &[Param]) {
-        let val = self.param_value(&params[0]);
+    fn raise_interrupt(&mut self) {
+        if let Some(int) = &mut self.interrupt {
+            int.pending |= !self.input_queue.is_empty();
+        }
+    }
+
+    // Jumps to the interrupt handler if there's an interrupt to serve.
+    fn dispatch_interrupt(&mut self) {
+        let Some(int) = &mut self.interrupt else { return };
+        if !int.pending || int.active || self.input_queue.is_empty() {
+            return;
+        }
+        int.pending = false;
+        int.active = true;
+        let (handler, save_area) = (int.handler, int.save_area);
+        self.write_mem(save_area, self.ip);
+        self.write_mem(save_area + 1, self.rel_base);
+        self.ip = handler;
+    }
+
+    // Target of a taken jump. Jumping back from the interrupt handler
+    // unmasks interrupts.
+    fn jump(&mut self, target: Int) -> Int {
+        if let Some(int) = self.interrupt.filter(|int| int.active && target == self.peek_at(int.save_area)) {
+            self.rel_base = self.peek_at(int.save_area + 1);
+            self.interrupt = Some(InputInterrupt { active: false, ..int });
+        }
+        target
+    }
+
+    pub(crate) fn next_input(&self) -> Option<Int> {
+        self.input_queue.front().copied()
+    }
+
+    // Executes the instruction at the IP, returning something if `run` must stop.
+    pub(crate) fn exec_next(&mut self) -> Result<Option<RunResult>, IntcodeError> {
+        let result = self.exec_instruction();
+        match result {
+            Err(IntcodeError::NoInput { .. }) | Ok(_) => {},
+            Err(e) => self.emit(Event::Trapped(e)),
+        }
+        result
+    }
+
+    fn exec_instruction(&mut self) -> Result<Option<RunResult>, IntcodeError> {
+        if self.step_limit.is_some_and(|limit| self.steps >= limit) {
+            return Err(IntcodeError::StepLimit { steps: self.steps });
+        }
+        self.dispatch_interrupt();
+
+        let ip = self.ip;
+        let (opcode, params, n_params) = self.parse_operation()?;
+        if opcode == Opcodes::IN && self.input_queue.is_empty() && !self.request_input() {
+            return Err(IntcodeError::NoInput { ip });
+        }
+
+        let mut next_ip = ip.checked_add(1 + n_params as Int).ok_or(IntcodeError::Overflow { ip })?;
+        let mut ret = None;
+        // For conditional jumps, whether it was taken
+        let mut taken = None;
+
+        // Highest address the instruction touches, before it changes anything
+        let max_addr = match self.stats.is_some() {
+            true => self.param_addrs()?.1.into_iter().fold(ip, Int::max),
+            false => ip,
+        };
+
+        let (pre_hook, post_hook) = (self.pre_hook.clone(), self.post_hook.clone());
+        let instruction = match pre_hook.is_some() || post_hook.is_some() {
+            true => Some(Instruction { ip, opcode, operands: self.operands(&params[..n_params])? }),
+            false => None,
+        };
+        if let (Some(hook), Some(instr)) = (&pre_hook, &instruction) {
+            match hook.lock().unwrap()(self, instr) {
+                HookAction::Continue => {},
+                HookAction::Skip => {
+                    self.ip = next_ip;
+                    self.steps += 1;
+                    return Ok(None);
+                },
+                HookAction::Abort => return Err(IntcodeError::Aborted { ip }),
+            }
+        }
+
+        match opcode {
+            Opcodes::ADD => self.op_add(&params)?,
+            Opcodes::MUL => self.op_mul(&params)?,
+            Opcodes::IN => self.op_in(&params)?,
+            Opcodes::OUT => ret = Some(RunResult::Output(self.param_value(&params[0])?)),
+            Opcodes::JMP | Opcodes::JMN => {
+                let target = if opcode == Opcodes::JMP { self.op_jmp(&params)? } else { self.op_jmn(&params)? };
+                taken = Some(target.is_some());
+                next_ip = target.map_or(next_ip, |target| self.jump(target));
+            },
+            Opcodes::LT => self.op_lt(&params)?,
+            Opcodes::EQ => self.op_eq(&params)?,
+            Opcodes::RLB => self.op_rlb(&params)?,
+            Opcodes::END => self.is_finished = true,
+            #[cfg(feature = "extensions")]
+            Opcodes::DIV => self.op_div(&params, Int::checked_div)?,
+            #[cfg(feature = "extensions")]
+            Opcodes::MOD => self.op_div(&params, Int::checked_rem)?,
+            #[cfg(feature = "extensions")]
+            Opcodes::FMUL => self.op_fmul(&params)?,
+            #[cfg(feature = "extensions")]
+            Opcodes::FDIV => self.op_div(&params, |a, b| floor_div(a.checked_mul(1 << FIXED_POINT_BITS)?, b))?,
+            _ => match self.exec_custom(opcode, &params[..n_params])? {
+                Effect::Next => {},
+                Effect::Jump(target) => next_ip = target,
+                Effect::Output(val) => ret = Some(RunResult::Output(val)),
+                Effect::Halt => self.is_finished = true,
+            },
+        }
+
+        if !self.observers.is_empty() {
+            if let Some(RunResult::Output(value)) = ret {
+                self.emit(Event::OutputProduced { ip, value });
+            }
+            self.emit(Event::InstructionExecuted { ip, opcode });
+            if self.is_finished {
+                self.emit(Event::Halted { ip });
+            }
+        }
+
+        let output = matches!(ret, Some(RunResult::Output(_)));
+        if let Some(RunResult::Output(val)) = ret {
+            if let Some(callback) = &self.output_callback {
+                callback.lock().unwrap()(val);
+                ret = None;
+            } else if let Some(buffer) = &mut self.output_buffer {
+                buffer.push(val);
+                ret = None;
+            }
+        }
+
+        // The IP only moves once the instruction has succeeded, so errors
+        // always point at the offending instruction.
+        self.ip = next_ip;
+        self.steps += 1;
+        if let Some(watch) = &mut self.code_watch {
+            watch.record_executed(ip, n_params);
+        }
+        if let Some(stats) = &mut self.stats {
+            stats.record_instruction(opcode == Opcodes::IN, output, max_addr, self.rel_base);
+            if let Some(taken) = taken {
+                stats.record_branch(ip, taken);
+            }
+        }
+        if let Some(cycles) = self.cycles.as_mut().filter(|_| opcode == Opcodes::IN || output) {
+            cycles.reset();
+        }
+
+        if let (Some(hook), Some(instr)) = (&post_hook, &instruction) {
+            hook.lock().unwrap()(self, instr);
+        }
+        self.check_cycle()?;
+        Ok(ret)
+    }
+
+    fn op_add(&mut self, params: &[Param]) -> OpResult {
+        let v1 = self.param_value(&params[0])?;
+        let v2 = self.param_value(&params[1])?;
+        let res = v1.checked_add(v2).ok_or(self.overflow())?;
+        self.write_to(&params[2], res)
+    }
+
+    fn op_mul(&mut self, params: &[Param]) -> OpResult {
+        let v1 = self.param_value(&params[0])?;
+        let v2 = self.param_value(&params[1])?;
+        let res = v1.checked_mul(v2).ok_or(self.overflow())?;
+        self.write_to(&params[2], res)
+    }
+
+    fn op_in(&mut self, params: &[Param]) -> OpResult {
+        // Availability was checked before executing the instruction.
+        let value = self.input_queue.pop_front().unwrap();
+        self.emit(Event::InputConsumed { ip: self.ip, value });
+        self.write_to(&params[0], value)
+    }
+
+    // Jumps return the new IP if they are taken.
+    fn op_jmp(&self, params: &[Param]) -> Result<Option<Int>, IntcodeError> {
+        let val = self.param_value(&params[0])?;
         if val != 0 {
-            self.ip = self.param_value(&params[1]);
+            return Ok(Some(self.param_value(&params[1])?));
         }
+        Ok(None)
     }
 
-    fn op_jmn(&mut self, params: &[Param]) {
-        let val = self.param_value(&params[0]);
+    fn op_jmn(&self, params: &[Param]) -> Result<Option<Int>, IntcodeError> {
+        let val = self.param_value(&params[0])?;
         if val == 0 {
-            self.ip = self.param_value(&params[1]);
+            return Ok(Some(self.param_value(&params[1])?));
         }
+        Ok(None)
     }
 
-    fn op_lt(&mut self, params: &[Param]) {
-        let v1 = self.param_value(&params[0]);
-        let v2 = self.param_value(&params[1]);
+    fn op_lt(&mut self, params: &[Param]) -> OpResult {
+        let v1 = self.param_value(&params[0])?;
+        let v2 = self.param_value(&params[1])?;
         let res = (v1 < v2) as Int;
-        self.write_to(&params[2], res);
+        self.write_to(&params[2], res)
     }
 
-    fn op_eq(&mut self, params: &[Param]) {
-        let v1 = self.param_value(&params[0]);
-        let v2 = self.param_value(&params[1]);
+    fn op_eq(&mut self, params: &[Param]) -> OpResult {
+        let v1 = self.param_value(&params[0])?;
+        let v2 = self.param_value(&params[1])?;
         let res = (v1 == v2) as Int;
-        self.write_to(&params[2], res);
+        self.write_to(&params[2], res)
+    }
+
+    #[cfg(feature = "extensions")]
+    fn op_div(&mut self, params: &[Param], op: fn(Int, Int) -> Option<Int>) -> OpResult {
+        let v1 = self.param_value(&params[0])?;
+        let v2 = self.param_value(&params[1])?;
+        if v2 == 0 {
+            return Err(IntcodeError::DivisionByZero { ip: self.ip });
+        }
+        let res = op(v1, v2).ok_or(self.overflow())?;
+        self.write_to(&params[2], res)
+    }
+
+    #[cfg(feature = "extensions")]
+    fn op_fmul(&mut self, params: &[Param]) -> OpResult {
+        let v1 = self.param_value(&params[0])?;
+        let v2 = self.param_value(&params[1])?;
+        let res = v1.checked_mul(v2).ok_or(self.overflow())? >> FIXED_POINT_BITS;
+        self.write_to(&params[2], res)
     }
 
-    fn op_rlb(&mut self, params: &[Param]) {
-        let val = self.param_value(&params[0]);
-        self.rel_base += val;
+    fn exec_custom(&mut self, opcode: u8, params: &[Param]) -> Result<Effect, IntcodeError> {
+        // Parsing only lets registered opcodes through
+        let op = self.custom_ops[&opcode].clone();
+        let operands = self.operands(params)?;
+        (op.handler)(self, &operands)
+    }
+
+    fn operands(&self, params: &[Param]) -> Result<Vec<Operand>, IntcodeError> {
+        params.iter().map(|param| {
+            let addr = match param.mode {
+                ParamMode::Immediate => None,
+                ParamMode::Position => Some(self.checked_addr(param.value)?),
+                ParamMode::Relative => Some(self.relative_addr(param)?),
+            };
+            let value = addr.map_or(param.value, |addr| self.peek_at(addr));
+            Ok(Operand { value, addr })
+        }).collect()
+    }
+
+    fn op_rlb(&mut self, params: &[Param]) -> OpResult {
+        let val = self.param_value(&params[0])?;
+        self.rel_base = self.rel_base.checked_add(val).ok_or(self.overflow())?;
+        Ok(())
     }
 
     //////////////////////////////////////////////////////////////////////////////////////////////////////
 
-    fn parse_operation(&mut self) -> (u8, [Param; 3]) {
-        let opcode = (self.memory[&self.ip] % 100) as u8;
-        let mut flags = self.memory[&self.ip] / 100;
-        let n_params = match opcode {
-            Opcodes::END                                            => 0,
-            Opcodes::IN  | Opcodes::OUT | Opcodes::RLB              => 1,
-            Opcodes::JMP | Opcodes::JMN                             => 2,
-            Opcodes::ADD | Opcodes::MUL | Opcodes::EQ | Opcodes::LT => 3,
-            _ => panic!("Unknown opcode"),
+    fn parse_operation(&self) -> Result<(u8, [Param; 3], usize), IntcodeError> {
+        self.parse_operation_with(self.ip, Self::read_at)
+    }
+
+    // Same as `parse_operation`, at any address and peeking at devices, for
+    // looking at the code without running it.
+    fn peek_operation_at(&self, ip: Int) -> Result<(u8, [Param; 3], usize), IntcodeError> {
+        self.parse_operation_with(ip, Self::peek_at)
+    }
+
+    fn parse_operation_with(&self, ip: Int, read: fn(&Self, Int) -> Int) -> Result<(u8, [Param; 3], usize), IntcodeError> {
+        let ip = self.checked_addr(ip)?;
+        let raw = read(self, ip);
+        let unknown = IntcodeError::UnknownOpcode { ip, opcode: raw };
+        let opcode = u8::try_from(raw % 100).map_err(|_| unknown)?;
+        let mut flags = raw / 100;
+        let n_params = match Opcodes::n_params(opcode) {
+            Some(n) => n,
+            None => self.custom_ops.get(&opcode).ok_or(unknown)?.n_params,
         };
         let mut params = [Param::default(); 3];
 
-        for i in 0..n_params {
+        for (i, param) in params.iter_mut().enumerate().take(n_params) {
             let mode = match flags % 10 {
                 0 => ParamMode::Position,
                 1 => ParamMode::Immediate,
                 2 => ParamMode::Relative,
-                x => panic!("Unknown param mode: {x}"),
+                x => return Err(IntcodeError::UnknownParamMode { ip, mode: x }),
             };
             flags /= 10;
-            let value = self.read_at(self.ip + i as Int + 1);
-            params[i] = Param{ mode, value };
+            let addr = ip.checked_add(i as Int + 1).ok_or(IntcodeError::Overflow { ip })?;
+            let value = read(self, addr);
+            *param = Param{ mode, value };
         }
-        self.ip += 1 + n_params as Int;
-        (opcode, params)
+        Ok((opcode, params, n_params))
     }
 
-    fn param_value(&self, param: &Param) -> Int {
+    fn param_value(&self, param: &Param) -> Result<Int, IntcodeError> {
         match param.mode {
-            ParamMode::Immediate => param.value,
-            ParamMode::Position => self.read_at(param.value),
-            ParamMode::Relative => self.read_at(param.value + self.rel_base),
+            ParamMode::Immediate => Ok(param.value),
+            ParamMode::Position => Ok(self.read_at(self.checked_addr(param.value)?)),
+            ParamMode::Relative => Ok(self.read_at(self.relative_addr(param)?)),
         }
     }
 
-    fn write_to(&mut self, param: &Param, value: Int) {
+    fn write_to(&mut self, param: &Param, value: Int) -> OpResult {
         let addr = match param.mode {
-            ParamMode::Immediate => panic!("Output addresses cannot be in immediate mode"),
-            ParamMode::Position => param.value,
-            ParamMode::Relative => param.value + self.rel_base,
+            ParamMode::Immediate => return Err(IntcodeError::ImmediateWrite { ip: self.ip }),
+            ParamMode::Position => self.checked_addr(param.value)?,
+            ParamMode::Relative => self.relative_addr(param)?,
         };
-        self.memory.insert(addr, value);
+        self.write_mem(addr, value);
+        Ok(())
+    }
+
+    // Memory write performed by an instruction.
+    pub(crate) fn write_mem(&mut self, addr: Int, value: Int) {
+        let old = self.peek_at(addr);
+        self.store(addr, value);
+        if let Some(stats) = &mut self.stats {
+            stats.record_write(addr);
+        }
+        if let Some(watch) = &mut self.code_watch {
+            watch.record_write(self.ip, addr);
+        }
+        if !self.observers.is_empty() {
+            self.emit(Event::MemoryWritten { ip: self.ip, addr, old, new: value });
+        }
+    }
+
+    fn relative_addr(&self, param: &Param) -> Result<Int, IntcodeError> {
+        let addr = param.value.checked_add(self.rel_base).ok_or(self.overflow())?;
+        self.checked_addr(addr)
+    }
+
+    // Negative addresses are invalid, but only rejected in strict mode.
+    fn checked_addr(&self, addr: Int) -> Result<Int, IntcodeError> {
+        if self.strict && addr < 0 {
+            return Err(IntcodeError::NegativeAddress { ip: self.ip, addr });
+        }
+        Ok(addr)
+    }
+
+    fn overflow(&self) -> IntcodeError {
+        IntcodeError::Overflow { ip: self.ip }
+    }
+}
+
+// Two computers are equal when they'd behave the same from now on: same
+// registers, pending inputs and memory, where cells that were never set
+// count as zero. Step counts, limits, extensions and devices are ignored.
+impl PartialEq for IntcodeComputer {
+    fn eq(&self, other: &Self) -> bool {
+        let same_memory = |a: &Self, b: &Self| a.memory.iter().all(|(pos, val)| b[pos] == val);
+        self.ip == other.ip
+            && self.rel_base == other.rel_base
+            && self.is_finished == other.is_finished
+            && self.input_queue == other.input_queue
+            && same_memory(self, other)
+            && same_memory(other, self)
+    }
+}
+
+impl Eq for IntcodeComputer {}
+
+impl Extend<Int> for IntcodeComputer {
+    fn extend<T: IntoIterator<Item = Int>>(&mut self, values: T) {
+        self.input_iter(values);
+    }
+}
+
+// Direct access to memory. Unlike `read_at` and `write_at`, it ignores
+// devices and sees the memory under them.
+impl Index<Int> for IntcodeComputer {
+    type Output = Int;
+
+    fn index(&self, pos: Int) -> &Int {
+        self.memory.get(pos).unwrap_or(&0)
     }
 }
 
+impl IndexMut<Int> for IntcodeComputer {
+    fn index_mut(&mut self, pos: Int) -> &mut Int {
+        self.reset_cycles();
+        self.memory.get_mut(pos)
+    }
+}
+
+// Number of instructions shown, starting at the IP, when displaying a computer.
+const DISPLAY_WINDOW: usize = 5;
+// Number of memory cells shown by `Debug`, unless asked otherwise.
+const DEBUG_WINDOW: usize = 16;
+
+impl IntcodeComputer {
+    // Debug view of the computer showing up to `window` memory cells: the
+    // non-zero ones with the lowest addresses.
+    pub fn debug_with(&self, window: usize) -> impl fmt::Debug + '_ {
+        DebugView { comp: self, window }
+    }
+}
+
+struct DebugView<'a> {
+    comp: &'a IntcodeComputer,
+    window: usize,
+}
+
+// Non-zero memory cells, sorted by address.
+struct MemoryWindow<'a>(&'a DebugView<'a>);
+
+impl fmt::Debug for DebugView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let comp = self.comp;
+        f.debug_struct("IntcodeComputer")
+            .field("ip", &comp.ip)
+            .field("rel_base", &comp.rel_base)
+            .field("is_finished", &comp.is_finished)
+            .field("steps", &comp.steps)
+            .field("input_queue", &comp.input_queue)
+            .field("memory", &MemoryWindow(self))
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Debug for MemoryWindow<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut cells: Vec<_> = self.0.comp.memory.iter().filter(|&(_, v)| v != 0).collect();
+        cells.sort_unstable();
+
+        let mut map = f.debug_map();
+        map.entries(cells.iter().take(self.0.window).copied());
+        if cells.len() > self.0.window {
+            map.finish_non_exhaustive()
+        } else {
+            map.finish()
+        }
+    }
+}
+
+impl fmt::Debug for IntcodeComputer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.debug_with(DEBUG_WINDOW).fmt(f)
+    }
+}
+
+// Registers, pending inputs and the upcoming instructions. Disassembly stops
+// at the first END or at anything that isn't a valid instruction.
+impl fmt::Display for IntcodeComputer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ip: {}, relative base: {}, finished: {}", self.ip, self.rel_base, self.is_finished)?;
+        write!(f, "\ninputs: {:?}", self.input_queue)?;
+
+        let symbols = self.symbols();
+        let mut ip = self.ip;
+        for i in 0..DISPLAY_WINDOW {
+            let marker = if i == 0 { '>' } else { ' ' };
+            if let Some(label) = symbols.and_then(|s| s.name_of(ip)) {
+                write!(f, "\n  {label}:")?;
+            }
+            let Ok((opcode, params, n_params)) = self.peek_operation_at(ip) else {
+                return write!(f, "\n{marker} {ip}: {}", self.peek_at(ip));
+            };
+
+            match Opcodes::mnemonic(opcode) {
+                "???" => write!(f, "\n{marker} {ip}: OP{opcode}")?,
+                mnemonic => write!(f, "\n{marker} {ip}: {mnemonic}")?,
+            }
+            for (j, param) in params[..n_params].iter().enumerate() {
+                let sep = if j == 0 { " " } else { ", " };
+                match param.mode {
+                    ParamMode::Position => match symbols.and_then(|s| s.name_of(param.value)) {
+                        Some(name) => write!(f, "{sep}[{name}]")?,
+                        None => write!(f, "{sep}[{}]", param.value)?,
+                    },
+                    ParamMode::Immediate => write!(f, "{sep}{}", param.value)?,
+                    ParamMode::Relative => write!(f, "{sep}[rb{:+}]", param.value)?,
+                }
+            }
+
+            if opcode == Opcodes::END {
+                break;
+            }
+            ip += 1 + n_params as Int;
+        }
+        Ok(())
+    }
+}
+
+impl IntcodeComputer {
+    // Loads a program as it's being read, without keeping the whole text in
+    // memory. Values can be separated by commas or newlines; blank values
+    // (e.g. empty lines) are skipped. A header is verified, and errors are
+    // reported as `InvalidData`.
+    pub fn from_reader(mut reader: impl BufRead) -> io::Result<Self> {
+        let invalid = |e: HeaderError| io::Error::new(io::ErrorKind::InvalidData, e);
+        let mut header: Option<ProgramHeader> = None;
+        while reader.fill_buf()?.starts_with(HEADER_PREFIX.as_bytes()) {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            header.get_or_insert_with(Default::default).parse_line(&line).map_err(invalid)?;
+        }
+
+        let mut memory = Memory::default();
+        let mut checksum = Checksum::default();
+        let mut len: Int = 0;
+        let mut token = Vec::new();
+        let mut end_token = |token: &mut Vec<u8>| {
+            let trimmed = token.trim_ascii();
+            if !trimmed.is_empty() {
+                let value = parse_int(trimmed).ok_or_else(|| io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid value in program: {:?}", String::from_utf8_lossy(trimmed)),
+                ))?;
+                memory.insert(len, value);
+                checksum.push(value);
+                len += 1;
+            }
+            token.clear();
+            io::Result::Ok(())
+        };
+
+        loop {
+            let buf = reader.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            let n = buf.len();
+            for &b in buf {
+                match b {
+                    b',' | b'\n' => end_token(&mut token)?,
+                    _ => token.push(b),
+                }
+            }
+            reader.consume(n);
+        }
+        end_token(&mut token)?;
+
+        let mut comp = Self { memory, ..Default::default() };
+        if let Some(header) = header {
+            header.verify_checksum(checksum.finish()).map_err(invalid)?;
+            comp.set_header(header);
+        }
+        Ok(comp)
+    }
+
+    // Header the program was loaded with, if any.
+    pub fn header(&self) -> Option<&ProgramHeader> {
+        self.header.as_deref()
+    }
+
+    // Moves the IP to the header's entry point, if it has one.
+    fn set_header(&mut self, header: ProgramHeader) {
+        self.ip = header.entry.unwrap_or(self.ip);
+        self.header = Some(Arc::new(header));
+    }
+}
+
+// Panics on invalid programs, including headers that don't match.
 impl<T: AsRef<str>> From<T> for IntcodeComputer {
     fn from(code: T) -> Self {
-        let vec: Vec<Int> = code.as_ref().trim().split(',').map(|x| x.trim().parse().unwrap()).collect();
-        Self::new(&vec)
+        let code = code.as_ref();
+        if !code.starts_with(HEADER_PREFIX) {
+            return Self::new(&parse_code(code));
+        }
+        let (header, code) = split_header(code).unwrap_or_else(|e| panic!("{e}"));
+        let code = parse_code(code);
+        let mut comp = Self::new(&code);
+        if let Some(header) = header {
+            header.verify(&code).unwrap_or_else(|e| panic!("{e}"));
+            comp.set_header(header);
+        }
+        comp
     }
-}
\ No newline at end of file
+}
+
+// Parsing is done by hand, as it shows up in profiles of searches that load
+// the program over and over. Panics on anything that isn't a list of
+// comma-separated integers.
+pub(crate) fn parse_code(code: &str) -> Vec<Int> {
+    let bytes = code.trim().as_bytes();
+    let mut values = Vec::with_capacity(bytes.iter().filter(|&&b| b == b',').count() + 1);
+    for token in bytes.split(|&b| b == b',') {
+        match parse_int(token.trim_ascii()) {
+            Some(value) => values.push(value),
+            None => panic!("Invalid value in program: {:?}", String::from_utf8_lossy(token)),
+        }
+    }
+    values
+}
+
+fn parse_int(token: &[u8]) -> Option<Int> {
+    let (negative, digits) = match token {
+        [b'-', rest @ ..] => (true, rest),
+        [b'+', rest @ ..] => (false, rest),
+        _ => (false, token),
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    // Negative numbers are built downwards so that `Int::MIN` fits
+    digits.iter().try_fold(0 as Int, |acc, &d| {
+        let digit = d.checked_sub(b'0').filter(|&d| d < 10)? as Int;
+        let acc = acc.checked_mul(10)?;
+        if negative { acc.checked_sub(digit) } else { acc.checked_add(digit) }
+    })
+}
@@ -0,0 +1,43 @@
+use crate::{IntcodeComputer, Int};
+
+// Input interrupts, for event-driven programs that would rather be told
+// about new input than poll for it.
+//
+// Once enabled, providing input to the computer raises an interrupt. Before
+// running its next instruction, the computer saves its IP at `save_area`
+// and its relative base at `save_area + 1` (writes observers see like any
+// other), then jumps to the handler.
+// Further interrupts are masked until the handler returns, which it does by
+// jumping to the address stored at `save_area` (e.g. `106,0,<save_area>`);
+// the relative base is restored at that point. Input arriving while the
+// handler runs raises a new interrupt right after it returns, as long as
+// there's still some left in the queue.
+//
+// A program waiting on an IN instruction is interrupted too: the handler
+// runs first, and the IN tries again once it returns.
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) struct InputInterrupt {
+    pub handler: Int,
+    pub save_area: Int,
+    pub pending: bool,
+    pub active: bool,
+}
+
+impl IntcodeComputer {
+    pub fn set_input_interrupt(&mut self, handler: Int, save_area: Int) {
+        assert!(save_area < Int::MAX, "The save area needs two addresses");
+        self.interrupt = Some(InputInterrupt { handler, save_area, pending: false, active: false });
+    }
+
+    // Disables interrupts. A handler that is currently running keeps
+    // running, but won't be detected as returning.
+    pub fn clear_input_interrupt(&mut self) {
+        self.interrupt = None;
+    }
+
+    // Whether an interrupt handler is currently running.
+    pub fn in_interrupt(&self) -> bool {
+        self.interrupt.is_some_and(|int| int.active)
+    }
+}
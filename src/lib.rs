@@ -1,5 +1,67 @@
 mod intcode;
+mod ascii;
+mod builder;
+mod custom;
+mod cycle;
+mod device;
+mod engine;
+mod header;
+mod error;
+mod hooks;
+mod input;
+mod interrupt;
+mod memory;
+mod observer;
+mod rewrite;
+mod selfmod;
+mod symbols;
+mod stats;
+pub mod abi;
+pub mod analysis;
+pub mod aoc;
+pub mod bench;
+pub mod conformance;
+pub mod debug;
+pub mod diff;
+pub mod executor;
+pub mod format;
+pub mod fuzz;
+pub mod generate;
+pub mod hash;
+pub mod obfuscate;
+pub mod parallel;
+pub mod pgo;
+pub mod pool;
+pub mod process;
+pub mod routines;
+pub mod script;
+pub mod search;
+pub mod snapshot;
+pub mod syscall;
+pub mod taint;
+pub mod trace;
+pub mod watch;
+#[cfg(feature = "smt")]
+pub mod smt;
+#[cfg(feature = "term")]
+pub mod term;
 #[cfg(test)]
 mod tests;
 
-pub use intcode::{IntcodeComputer, Int, RunResult};
+pub use intcode::{IntcodeComputer, Int, OutputCallback, RunResult, StopReason};
+#[cfg(feature = "extensions")]
+pub use intcode::FIXED_POINT_BITS;
+pub use builder::IntcodeBuilder;
+pub use custom::{Effect, OpcodeHandler, Operand};
+pub use device::{Clock, ClockDevice, Device, FakeClock, RngDevice, StorageDevice, SystemClock};
+pub use hooks::{HookAction, Instruction, PostHook, PreHook};
+pub use input::{InputDecision, InputRequestHandler};
+pub use header::{split_header, HeaderError, ProgramHeader};
+pub use observer::{Event, Observer};
+pub use rewrite::RewriteError;
+pub use selfmod::{CodeWrite, CodeWriteMode, ModifiedCode, SelfModReport, MAX_LOGGED_WRITES};
+pub use symbols::{SymbolError, SymbolTable};
+pub use stats::{BranchCount, RunStats};
+pub use ascii::{AsciiOutput, AsciiReader, AsciiStop, AsciiWriter, NonAsciiOutput};
+pub use engine::{IntcodeEngine, IntcodeMachine, Interpreter};
+pub use error::IntcodeError;
@@ -0,0 +1,134 @@
+use std::sync::Arc;
+
+use crate::Int;
+use crate::hash::HashMap;
+
+// Storage for the computer's memory. The low part of the address space is
+// split into fixed-size pages, so access is a couple of indexing operations
+// even for programs spreading their data over a large range. A page is only
+// allocated once `DENSE_CELLS` of its cells have been written, and until
+// then they live in a hash map, like addresses past the paged range (or
+// negative), so scattered writes don't take a whole page each. Cells
+// remember whether they've ever been set, so both parts behave the same way
+// from the outside.
+//
+// Pages are shared between copies of the memory until one of them writes to
+// the page, which makes forking a computer cheap.
+
+pub(crate) const PAGE_BITS: u32 = 10;
+pub(crate) const PAGE_SIZE: usize = 1 << PAGE_BITS;
+// Number of pages the page table may grow to, which bounds its own size.
+pub(crate) const MAX_PAGES: usize = 1 << 16;
+// Cells written to a page before it's allocated.
+pub(crate) const DENSE_CELLS: usize = PAGE_SIZE / 16;
+
+type Page = [Option<Int>; PAGE_SIZE];
+
+#[derive(Clone, Default, Debug)]
+pub(crate) struct Memory {
+    pages: Vec<Option<Arc<Page>>>,
+    sparse: HashMap<Int, Int>,
+    // Cells in `sparse` from each page that isn't allocated.
+    sparse_pages: HashMap<usize, usize>,
+}
+
+impl Memory {
+    pub fn new(code: &[Int]) -> Self {
+        let mut memory = Self { pages: Vec::with_capacity(code.len().div_ceil(PAGE_SIZE)), ..Default::default() };
+        for (pos, &value) in code.iter().enumerate() {
+            memory.insert(pos as Int, value);
+        }
+        memory
+    }
+
+    // Replaces the contents with the code, keeping the pages this memory
+    // owns allocated.
+    pub fn reset(&mut self, code: &[Int]) {
+        for page in &mut self.pages {
+            match page.as_mut().and_then(Arc::get_mut) {
+                Some(owned) => owned.fill(None),
+                None => *page = None,
+            }
+        }
+        self.sparse.clear();
+        self.sparse_pages.clear();
+        for (pos, &value) in code.iter().enumerate() {
+            self.insert(pos as Int, value);
+        }
+    }
+
+    pub fn get(&self, pos: Int) -> Option<&Int> {
+        match page_of(pos).and_then(|(page, offset)| Some((self.pages.get(page)?.as_ref()?, offset))) {
+            Some((page, offset)) => page[offset].as_ref(),
+            None => self.sparse.get(&pos),
+        }
+    }
+
+    // Cell at the given position, setting it to zero if it wasn't set.
+    pub fn get_mut(&mut self, pos: Int) -> &mut Int {
+        let Some((page, offset)) = page_of(pos) else {
+            return self.sparse.entry(pos).or_default();
+        };
+        if self.pages.get(page).is_none_or(Option::is_none) {
+            if self.sparse.contains_key(&pos) {
+                return self.sparse.get_mut(&pos).unwrap();
+            }
+            let count = self.sparse_pages.entry(page).or_default();
+            *count += 1;
+            if *count < DENSE_CELLS {
+                return self.sparse.entry(pos).or_default();
+            }
+            self.allocate(page);
+        }
+        let page = self.pages[page].as_mut().unwrap();
+        Arc::make_mut(page)[offset].get_or_insert(0)
+    }
+
+    // Allocates a page, moving its cells out of the hash map.
+    fn allocate(&mut self, page: usize) {
+        if self.pages.len() <= page {
+            self.pages.resize(page + 1, None);
+        }
+        let start = (page << PAGE_BITS) as Int;
+        let mut cells = [None; PAGE_SIZE];
+        if self.sparse_pages.remove(&page).is_some() {
+            for (offset, cell) in cells.iter_mut().enumerate() {
+                *cell = self.sparse.remove(&(start + offset as Int));
+            }
+        }
+        self.pages[page] = Some(Arc::new(cells));
+    }
+
+    pub fn insert(&mut self, pos: Int, value: Int) {
+        *self.get_mut(pos) = value;
+    }
+
+    // Number of cells that have been set.
+    pub fn len(&self) -> usize {
+        self.pages.iter().flatten().map(|page| page.iter().flatten().count()).sum::<usize>() + self.sparse.len()
+    }
+
+    // Cells that have been set, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (Int, Int)> + '_ {
+        let paged = self.pages.iter().enumerate().flat_map(|(n, page)| {
+            page.iter().flat_map(move |page| page.iter().enumerate().filter_map(move |(offset, cell)| {
+                cell.map(|val| (((n << PAGE_BITS) + offset) as Int, val))
+            }))
+        });
+        paged.chain(self.sparse.iter().map(|(&pos, &val)| (pos, val)))
+    }
+
+    // Number of pages allocated by this memory alone, not shared with any
+    // copy of it.
+    #[cfg(test)]
+    pub fn owned_pages(&self) -> usize {
+        self.pages.iter().flatten().filter(|page| Arc::strong_count(page) == 1).count()
+    }
+}
+
+// Page number and offset within the page of a paged address.
+fn page_of(pos: Int) -> Option<(usize, usize)> {
+    let pos = usize::try_from(pos).ok()?;
+    let page = pos >> PAGE_BITS;
+    (page < MAX_PAGES).then_some((page, pos & (PAGE_SIZE - 1)))
+}
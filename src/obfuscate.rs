@@ -0,0 +1,93 @@
+use crate::Int;
+use crate::generate::Rng;
+use crate::rewrite::{basic_blocks, decode_program, Layout, RewriteError, Word};
+
+// Behavior-preserving scrambling of programs, for publishing challenges
+// whose structure isn't obvious at a glance. On top of what every rewrite
+// does (see `rewrite`), which already drops unreachable code:
+//
+// - Basic blocks are laid out in a random order, and chained back together
+//   with unconditional jumps.
+// - The data region is shuffled.
+// - No-ops are sprinkled between instructions (jumps that are never taken,
+//   comparisons into a scratch cell, jumps over junk words), and junk words
+//   between blocks.
+
+#[derive(Copy, Clone, Debug)]
+pub struct Obfuscator {
+    pub shuffle_blocks: bool,
+    pub shuffle_data: bool,
+    // Chance, in percent, of padding before every instruction and between
+    // blocks.
+    pub padding: u64,
+}
+
+impl Default for Obfuscator {
+    fn default() -> Self {
+        Self { shuffle_blocks: true, shuffle_data: true, padding: 30 }
+    }
+}
+
+impl Obfuscator {
+    pub fn obfuscate(&self, code: &[Int], rng: &mut Rng) -> Result<Vec<Int>, RewriteError> {
+        let instrs = decode_program(code)?;
+        let mut blocks = basic_blocks(&instrs);
+        if self.shuffle_blocks {
+            shuffle(&mut blocks, rng);
+        }
+
+        let mut layout = Layout::default();
+        if blocks.first().is_some_and(|block| block[0].addr != 0) {
+            layout.jump_to(0);
+        }
+        for (i, block) in blocks.iter().enumerate() {
+            for instr in block {
+                if rng.chance(self.padding) {
+                    pad(&mut layout.words, rng);
+                }
+                layout.place(instr);
+            }
+            let next_placed = blocks.get(i + 1).map(|block| block[0].addr);
+            if layout.chain(block.last().unwrap(), next_placed) && rng.chance(self.padding) {
+                junk(&mut layout.words, rng);
+            }
+        }
+
+        Ok(layout.finish(code, |cells| if self.shuffle_data {
+            shuffle(cells, rng);
+        }))
+    }
+}
+
+// One instruction that does nothing visible to the program.
+fn pad(words: &mut Vec<Word>, rng: &mut Rng) {
+    let value = |rng: &mut Rng| Word::Lit(rng.range(-1_000, 1_000));
+    match rng.range(0, 3) {
+        // Jump if true on 0
+        0 => words.extend([Word::Lit(1105), Word::Lit(0), value(rng)]),
+        // Jump if false on a non-zero value
+        1 => words.extend([Word::Lit(1106), Word::Lit(rng.range(1, 1_000)), value(rng)]),
+        // Comparison into the scratch cell, which can't overflow
+        2 => words.extend([Word::Lit(1108), value(rng), value(rng), Word::Scratch]),
+        // Jump over a few junk words
+        _ => {
+            let start = words.len();
+            words.extend([Word::Lit(1105), Word::Lit(1), Word::Here(0)]);
+            junk(words, rng);
+            words[start + 2] = Word::Here((words.len() - start - 2) as Int);
+        },
+    }
+}
+
+// A few random words that are never executed.
+fn junk(words: &mut Vec<Word>, rng: &mut Rng) {
+    for _ in 0..rng.range(1, 3) {
+        words.push(Word::Lit(rng.range(-1_000, 1_000)));
+    }
+}
+
+fn shuffle<T>(items: &mut [T], rng: &mut Rng) {
+    for i in (1..items.len()).rev() {
+        items.swap(i, rng.range(0, i as Int) as usize);
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{IntcodeComputer, IntcodeError, Int};
+
+// Observers get notified of everything the computer does while running, so
+// tracers, profilers or visualizers can be built on top of it without
+// touching the interpreter. Events carry the IP of the instruction causing
+// them. For every instruction, memory writes and I/O come first, followed by
+// `InstructionExecuted` (and `Halted` if it stopped the program). Errors other
+// than waiting for input are reported as `Trapped`.
+//
+// Like devices, observers are shared by copies of a computer.
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Event {
+    InstructionExecuted { ip: Int, opcode: u8 },
+    InputConsumed { ip: Int, value: Int },
+    OutputProduced { ip: Int, value: Int },
+    // `old` is what the address held before, as given by `peek_at`.
+    MemoryWritten { ip: Int, addr: Int, old: Int, new: Int },
+    Halted { ip: Int },
+    Trapped(IntcodeError),
+}
+
+pub trait Observer: Send {
+    fn on_event(&mut self, event: &Event);
+}
+
+impl<F: FnMut(&Event) + Send> Observer for F {
+    fn on_event(&mut self, event: &Event) {
+        self(event)
+    }
+}
+
+// Keeps every event.
+impl Observer for Vec<Event> {
+    fn on_event(&mut self, event: &Event) {
+        self.push(*event);
+    }
+}
+
+impl IntcodeComputer {
+    // Registers an observer, returning a handle to it so the host can
+    // inspect it afterwards.
+    pub fn add_observer<O: Observer + 'static>(&mut self, observer: O) -> Arc<Mutex<O>> {
+        let observer = Arc::new(Mutex::new(observer));
+        self.observers.push(observer.clone());
+        observer
+    }
+
+    // Calls `callback(addr, old, new, ip)` for every memory write done by
+    // the program. A shorthand for an observer only caring about writes.
+    pub fn on_memory_write(&mut self, mut callback: impl FnMut(Int, Int, Int, Int) + Send + 'static) {
+        self.add_observer(move |event: &Event| if let &Event::MemoryWritten { ip, addr, old, new } = event {
+            callback(addr, old, new, ip);
+        });
+    }
+
+    pub fn clear_observers(&mut self) {
+        self.observers.clear();
+    }
+
+    pub(crate) fn emit(&self, event: Event) {
+        for observer in &self.observers {
+            observer.lock().unwrap().on_event(&event);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::{IntcodeComputer, Int};
+use crate::search::Setup;
+
+// Running many independent copies of a program at once, one per set of
+// inputs, spread over all available threads. Each thread takes the next
+// pending job when it's done with the previous one, so uneven run times
+// don't leave threads idle.
+
+// Runs a copy of the program for every set of inputs, returning the outputs
+// of each run in the same order. Panics if any run fails.
+pub fn run_batch<I: IntoIterator<Item = Int>>(program: &IntcodeComputer, inputs: impl IntoIterator<Item = I>) -> Vec<Vec<Int>> {
+    let jobs: Vec<Vec<Int>> = inputs.into_iter().map(|job| job.into_iter().collect()).collect();
+    let n_jobs = jobs.len();
+    let next = AtomicUsize::new(0);
+
+    let mut results = vec![vec![]; n_jobs];
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..n_threads()).map(|_| scope.spawn(|| {
+            let mut done = vec![];
+            loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= n_jobs {
+                    break done;
+                }
+                done.push((i, program.clone().run_with_inputs(&jobs[i])));
+            }
+        })).collect();
+
+        for worker in workers {
+            for (i, outputs) in worker.join().unwrap() {
+                results[i] = outputs;
+            }
+        }
+    });
+    results
+}
+
+// Earliest candidate whose run succeeds within `step_limit` steps (if any)
+// and whose outputs satisfy the predicate, together with those outputs.
+// Once a match is found, workers stop picking up candidates after it, and
+// give up on the ones after it they're already running.
+pub fn parallel_search(
+    program: &IntcodeComputer,
+    candidates: &[Setup],
+    step_limit: Option<u64>,
+    predicate: impl Fn(&[Int]) -> bool + Sync,
+) -> Option<(usize, Vec<Int>)> {
+    let next = AtomicUsize::new(0);
+    let best = AtomicUsize::new(usize::MAX);
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..n_threads()).map(|_| scope.spawn(|| {
+            loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= candidates.len() || i > best.load(Ordering::Relaxed) {
+                    break None;
+                }
+                let outputs = candidates[i].outputs_until(program, step_limit, || best.load(Ordering::Relaxed) < i);
+                if let Some(outputs) = outputs.filter(|out| predicate(out)) {
+                    best.fetch_min(i, Ordering::Relaxed);
+                    // Later candidates taken by this worker can't do better
+                    break Some((i, outputs));
+                }
+            }
+        })).collect();
+
+        workers.into_iter().filter_map(|w| w.join().unwrap()).min_by_key(|&(i, _)| i)
+    })
+}
+
+// Every available thread, for work split between them.
+pub(crate) fn n_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
@@ -0,0 +1,204 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+
+use crate::{BranchCount, Event, IntcodeComputer, Int, RunResult};
+use crate::hash::HashMap;
+use crate::intcode::Opcodes;
+use crate::rewrite::{basic_blocks, decode_program, Instr, Layout, RewriteError, Word};
+
+// Profile-guided optimization. The program is run on sample inputs, counting
+// how often every instruction runs, every transition between them is taken
+// and every conditional jump goes each way. The basic blocks are then laid
+// out again (see `rewrite`), chaining each one with its hottest successor so
+// the common path runs straight through:
+//
+// - A conditional jump whose target is placed right after it gets the
+//   opposite condition, so the hot path falls through.
+// - An unconditional jump to the block placed right after it is dropped.
+//
+// Then pairs of instructions passing a value through a cell nothing else
+// uses are merged into one (superinstruction selection):
+//
+// - A copy (`ADD x, 0, t` or `MUL x, 1, t`) followed by an instruction
+//   reading `t` becomes that instruction reading `x` directly.
+// - A test for zero (`EQ x, 0, t`) followed by a conditional jump on `t`
+//   becomes the opposite conditional jump on `x`.
+//
+// Every instruction left out is one less to run, while cold blocks that
+// lose their place may need an extra jump. The result behaves exactly like
+// the original on every input, not only on the samples. Loop specialization
+// (unrolling or versioning hot loops) isn't done: blocks can only be placed
+// once, so it would need copies of them to be told apart first.
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Profile {
+    // Times each instruction ran.
+    pub counts: HashMap<Int, u64>,
+    // Times each instruction ran right after another one, by their addresses.
+    pub edges: HashMap<(Int, Int), u64>,
+    // Outcomes of every conditional jump, as in `RunStats`.
+    pub branches: BTreeMap<Int, BranchCount>,
+}
+
+impl Profile {
+    // Runs the program once per sample, until it finishes, fails, runs out
+    // of input or takes `max_steps` steps.
+    pub fn collect(code: &[Int], samples: &[Vec<Int>], max_steps: u64) -> Self {
+        let profile = Arc::new(Mutex::new(Profile::default()));
+        for inputs in samples {
+            let mut comp = IntcodeComputer::new(code);
+            comp.set_step_limit(Some(max_steps));
+            comp.input_iter(inputs.iter().copied());
+            let mut prev = None;
+            let shared = profile.clone();
+            comp.add_observer(move |event: &Event| if let &Event::InstructionExecuted { ip, .. } = event {
+                let mut profile = shared.lock().unwrap();
+                *profile.counts.entry(ip).or_default() += 1;
+                if let Some(prev) = prev.replace(ip) {
+                    *profile.edges.entry((prev, ip)).or_default() += 1;
+                }
+            });
+            comp.set_collect_stats(true);
+            while let Ok(RunResult::Output(_)) = comp.try_run() {}
+
+            let mut profile = profile.lock().unwrap();
+            for (ip, count) in comp.take_stats().unwrap().branches {
+                let total = profile.branches.entry(ip).or_default();
+                total.taken += count.taken;
+                total.not_taken += count.not_taken;
+            }
+        }
+        // The computers, and their observers, are gone by now
+        Arc::try_unwrap(profile).unwrap().into_inner().unwrap()
+    }
+
+    pub fn count(&self, ip: Int) -> u64 {
+        self.counts.get(&ip).copied().unwrap_or_default()
+    }
+
+    pub fn edge(&self, from: Int, to: Int) -> u64 {
+        self.edges.get(&(from, to)).copied().unwrap_or_default()
+    }
+}
+
+pub fn optimize(code: &[Int], profile: &Profile) -> Result<Vec<Int>, RewriteError> {
+    let instrs = decode_program(code)?;
+    let blocks = basic_blocks(&instrs);
+    let by_addr: HashMap<Int, usize> = blocks.iter().enumerate().map(|(i, block)| (block[0].addr, i)).collect();
+
+    // Greedy chaining: after each block goes its hottest successor that isn't
+    // placed yet, falling back to the hottest block left (the entry first)
+    let mut order = vec![];
+    let mut left: BTreeSet<usize> = (0..blocks.len()).collect();
+    let mut current = Some(0);
+    while let Some(i) = current.or_else(|| left.iter().copied().max_by_key(|&i| (profile.count(blocks[i][0].addr), usize::MAX - i))) {
+        left.remove(&i);
+        order.push(i);
+        let last = blocks[i].last().unwrap();
+        // Falling through wins ties, as it needs no changes
+        current = [last.next, last.target].into_iter().flatten()
+            .filter_map(|addr| by_addr.get(&addr).copied().filter(|j| left.contains(j)))
+            .map(|j| (profile.edge(last.addr, blocks[j][0].addr), j))
+            .filter(|&(count, _)| count > 0)
+            .reduce(|best, succ| if succ.0 > best.0 { succ } else { best })
+            .map(|(_, j)| j);
+    }
+
+    let mut uses: HashMap<Int, usize> = HashMap::default();
+    for word in instrs.iter().flat_map(|instr| &instr.words) {
+        if let Word::Data(addr) = word {
+            *uses.entry(*addr).or_default() += 1;
+        }
+    }
+    let blocks: Vec<Vec<Instr>> = blocks.into_iter().map(|block| fuse(block, &uses)).collect();
+
+    let mut layout = Layout::default();
+    for (k, &i) in order.iter().enumerate() {
+        let next_placed = order.get(k + 1).map(|&j| blocks[j][0].addr);
+        let (body, last) = blocks[i].split_at(blocks[i].len() - 1);
+        body.iter().for_each(|instr| layout.place(instr));
+
+        let mut last: Instr = last[0].clone();
+        if last.is_goto() && last.target == next_placed {
+            layout.skip(&last);
+            continue;
+        }
+        if last.target.is_some() && last.target == next_placed {
+            last = last.inverted().unwrap_or(last);
+        }
+        layout.place(&last);
+        layout.chain(&last, next_placed);
+    }
+    Ok(layout.finish(code, |_| {}))
+}
+
+// Merges pairs of instructions in the block, given how many times each cell
+// appears in the program.
+fn fuse(block: Vec<Instr>, uses: &HashMap<Int, usize>) -> Vec<Instr> {
+    let mut fused: Vec<Instr> = vec![];
+    for instr in block {
+        match fused.last().and_then(|prev| merge(prev, &instr, uses)) {
+            Some(merged) => *fused.last_mut().unwrap() = merged,
+            None => fused.push(instr),
+        }
+    }
+    fused
+}
+
+// The single instruction doing the same as `first` followed by `second`, if
+// the cell `first` writes is only ever read by `second`. Both are in the
+// same block, so `second` only runs right after `first`.
+fn merge(first: &Instr, second: &Instr, uses: &HashMap<Int, usize>) -> Option<Instr> {
+    let (opcode, modes) = split_word(&first.words[0]);
+    let Some(&Word::Data(temp)) = first.words.get(3) else { return None };
+    if uses.get(&temp) != Some(&2) {
+        return None;
+    }
+    let (next_opcode, _) = split_word(&second.words[0]);
+    let read = sources(next_opcode).find(|&i| matches!(second.words[i + 1], Word::Data(addr) if addr == temp))?;
+    let is_imm = |i: usize, value: Int| modes[i] == 1 && matches!(first.words[i + 1], Word::Lit(v) if v == value);
+
+    let mut merged = Instr { addr: first.addr, ..second.clone() };
+    match opcode {
+        // Copies, reading the other parameter instead
+        Opcodes::ADD | Opcodes::MUL => {
+            let neutral = if opcode == Opcodes::ADD { 0 } else { 1 };
+            let src = (0..2).find(|&i| is_imm(1 - i, neutral))?;
+            merged.words[read + 1] = first.words[src + 1];
+            set_mode(&mut merged.words[0], read, modes[src]);
+        },
+        // Zero tests followed by a jump on them
+        Opcodes::EQ if read == 0 && matches!(next_opcode, Opcodes::JMP | Opcodes::JMN) => {
+            let src = (0..2).find(|&i| is_imm(1 - i, 0))?;
+            merged.words[1] = first.words[src + 1];
+            let opposite = if next_opcode == Opcodes::JMP { Opcodes::JMN } else { Opcodes::JMP };
+            let (_, next_modes) = split_word(&second.words[0]);
+            merged.words[0] = Word::Lit(opposite as Int + 100 * modes[src] + 1000 * next_modes[1]);
+        },
+        _ => return None,
+    }
+    Some(merged)
+}
+
+// Opcode and parameter modes of an instruction's first word.
+fn split_word(word: &Word) -> (u8, [Int; 3]) {
+    let &Word::Lit(word) = word else { unreachable!() };
+    ((word % 100) as u8, [word / 100 % 10, word / 1_000 % 10, word / 10_000 % 10])
+}
+
+fn set_mode(word: &mut Word, param: usize, mode: Int) {
+    if let Word::Lit(word) = word {
+        let unit = 10_i128.pow(param as u32 + 2);
+        *word += (mode - *word / unit % 10) * unit;
+    }
+}
+
+// Parameters an instruction reads.
+fn sources(opcode: u8) -> impl Iterator<Item = usize> {
+    let n = match opcode {
+        Opcodes::IN | Opcodes::END => 0,
+        _ if Opcodes::n_params(opcode) == Some(3) => 2,
+        _ => 1,
+    };
+    0..n
+}
@@ -0,0 +1,73 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+use crate::{IntcodeComputer, Int};
+
+// Recycling machines for workloads that go through thousands of short-lived
+// ones (e.g. one per query in day 19, or brute-force searches). Machines
+// handed out by the pool go back to it when dropped, and are reset for the
+// next program instead of allocating a new one. The pool can be shared
+// between threads.
+
+#[derive(Default)]
+pub struct MachinePool {
+    free: Mutex<Vec<IntcodeComputer>>,
+}
+
+impl MachinePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A fresh machine loaded with the program.
+    pub fn get(&self, program: &[Int]) -> PooledMachine<'_> {
+        let comp = match self.free.lock().unwrap().pop() {
+            Some(mut comp) => {
+                comp.reset(program);
+                comp
+            },
+            None => IntcodeComputer::new(program),
+        };
+        PooledMachine { pool: self, comp: Some(comp) }
+    }
+
+    // Number of machines waiting to be reused.
+    pub fn available(&self) -> usize {
+        self.free.lock().unwrap().len()
+    }
+}
+
+pub struct PooledMachine<'a> {
+    pool: &'a MachinePool,
+    // Only taken when dropped.
+    comp: Option<IntcodeComputer>,
+}
+
+impl PooledMachine<'_> {
+    // Takes the machine out of the pool for good.
+    pub fn into_inner(mut self) -> IntcodeComputer {
+        self.comp.take().unwrap()
+    }
+}
+
+impl Deref for PooledMachine<'_> {
+    type Target = IntcodeComputer;
+
+    fn deref(&self) -> &IntcodeComputer {
+        self.comp.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledMachine<'_> {
+    fn deref_mut(&mut self) -> &mut IntcodeComputer {
+        self.comp.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledMachine<'_> {
+    fn drop(&mut self) {
+        if let Some(comp) = self.comp.take() {
+            self.pool.free.lock().unwrap().push(comp);
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::{IntcodeComputer, IntcodeError, InputDecision, Int, RunResult};
+
+// Machines as processes: a computer moved onto its own thread, talking to
+// the rest of the world through channels. Reading input blocks until a
+// value is sent, and outputs are sent as soon as they're produced.
+
+pub struct MachineHandle {
+    pub input: Sender<Int>,
+    pub output: Receiver<Int>,
+    thread: JoinHandle<Result<IntcodeComputer, IntcodeError>>,
+}
+
+impl IntcodeComputer {
+    pub fn spawn(mut self) -> MachineHandle {
+        let (input, input_rx) = mpsc::channel();
+        let (output_tx, output) = mpsc::channel();
+
+        let thread = thread::spawn(move || loop {
+            match self.try_run() {
+                // Nobody listening isn't a reason to stop the machine
+                Ok(RunResult::Output(val)) => _ = output_tx.send(val),
+                Ok(RunResult::Finished) => return Ok(self),
+                Err(IntcodeError::NoInput { ip }) => match input_rx.recv() {
+                    Ok(val) => self.input(val),
+                    // Every sender is gone, so no input will ever arrive
+                    Err(_) => return Err(IntcodeError::NoInput { ip }),
+                },
+                Err(e) => return Err(e),
+            }
+        });
+
+        MachineHandle { input, output, thread }
+    }
+}
+
+impl MachineHandle {
+    // Waits for the machine to finish, returning it in its final state.
+    // Dropping the handle's input sender first means a machine waiting for
+    // input ends with `NoInput` instead of blocking forever (unless other
+    // senders are still around).
+    pub fn join(self) -> Result<IntcodeComputer, IntcodeError> {
+        let Self { input, thread, .. } = self;
+        drop(input);
+        thread.join().expect("The machine thread panicked")
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Actor-style wrapper: the computer lives on its own thread and is only
+// reached through messages. It runs in slices of instructions, checking its
+// mailbox in between, so it can be paused or inspected even in the middle of
+// a long computation.
+
+const SLICE_STEPS: usize = 1_000;
+
+pub enum ActorMessage {
+    PushInput(Int),
+    // Asks for a copy of the computer in its current state.
+    RequestState(Sender<IntcodeComputer>),
+    Pause,
+    Resume,
+    Terminate,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ActorEvent {
+    Output(Int),
+    Finished,
+    Failed(IntcodeError),
+}
+
+pub struct IntcodeActor {
+    mailbox: Sender<ActorMessage>,
+    events: Receiver<ActorEvent>,
+    thread: JoinHandle<IntcodeComputer>,
+}
+
+impl IntcodeActor {
+    pub fn start(comp: IntcodeComputer) -> Self {
+        let (mailbox, inbox) = mpsc::channel();
+        let (events_tx, events) = mpsc::channel();
+        let thread = thread::spawn(move || actor_loop(comp, inbox, events_tx));
+        Self { mailbox, events, thread }
+    }
+
+    // Returns false if the actor has already terminated.
+    pub fn send(&self, message: ActorMessage) -> bool {
+        self.mailbox.send(message).is_ok()
+    }
+
+    pub fn push_input(&self, value: Int) -> bool {
+        self.send(ActorMessage::PushInput(value))
+    }
+
+    // Outputs, and eventually how the program ended.
+    pub fn events(&self) -> &Receiver<ActorEvent> {
+        &self.events
+    }
+
+    pub fn state(&self) -> Option<IntcodeComputer> {
+        let (reply, state) = mpsc::channel();
+        self.send(ActorMessage::RequestState(reply));
+        state.recv().ok()
+    }
+
+    // Stops the actor, returning the computer in its final state.
+    pub fn terminate(self) -> IntcodeComputer {
+        self.send(ActorMessage::Terminate);
+        self.thread.join().expect("The actor thread panicked")
+    }
+}
+
+fn actor_loop(mut comp: IntcodeComputer, inbox: Receiver<ActorMessage>, events: Sender<ActorEvent>) -> IntcodeComputer {
+    let (mut paused, mut blocked, mut done) = (false, false, false);
+
+    loop {
+        // Wait for a message when there's nothing to run
+        let message = if paused || blocked || done {
+            match inbox.recv() {
+                Ok(message) => Some(message),
+                Err(_) => return comp,
+            }
+        } else {
+            // A closed mailbox doesn't stop the computer, it only means no
+            // more messages will come
+            inbox.try_recv().ok()
+        };
+
+        if let Some(message) = message {
+            match message {
+                ActorMessage::PushInput(val) => {
+                    comp.input(val);
+                    blocked = false;
+                },
+                ActorMessage::RequestState(reply) => _ = reply.send(comp.clone()),
+                ActorMessage::Pause => paused = true,
+                ActorMessage::Resume => paused = false,
+                ActorMessage::Terminate => return comp,
+            }
+            continue;
+        }
+
+        for _ in 0..SLICE_STEPS {
+            let event = match comp.exec_next() {
+                Ok(Some(RunResult::Output(val))) => ActorEvent::Output(val),
+                Ok(_) if comp.is_finished() => ActorEvent::Finished,
+                Ok(_) => continue,
+                Err(IntcodeError::NoInput { .. }) => {
+                    blocked = true;
+                    break;
+                },
+                Err(e) => ActorEvent::Failed(e),
+            };
+            done = !matches!(event, ActorEvent::Output(_));
+            _ = events.send(event);
+            if done {
+                break;
+            }
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Shared input: values pushed through an `InputHandle` are read by the
+// computer once its own queue is empty. Handles can be cloned and used from
+// any thread, so many producers can feed one machine without going through
+// whoever owns it. In blocking mode, the thread running the computer is
+// parked until a value arrives, like a process blocked reading stdin.
+
+#[derive(Default)]
+struct SharedInput {
+    state: Mutex<InputState>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct InputState {
+    queue: VecDeque<Int>,
+    closed: bool,
+}
+
+impl SharedInput {
+    fn take(&self) -> Option<Int> {
+        self.state.lock().unwrap().queue.pop_front()
+    }
+
+    // Next value, or `None` if the input was closed or the timeout expired.
+    fn wait(&self, timeout: Option<Duration>) -> Option<Int> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(val) = state.queue.pop_front() {
+                return Some(val);
+            }
+            if state.closed {
+                return None;
+            }
+            state = match deadline {
+                None => self.ready.wait(state).unwrap(),
+                Some(deadline) => {
+                    let left = deadline.checked_duration_since(Instant::now()).filter(|d| !d.is_zero())?;
+                    self.ready.wait_timeout(state, left).unwrap().0
+                },
+            };
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct InputHandle(Arc<SharedInput>);
+
+impl InputHandle {
+    pub fn push(&self, value: Int) {
+        self.0.state.lock().unwrap().queue.push_back(value);
+        self.0.ready.notify_all();
+    }
+
+    // No more input will come: once the values already pushed are consumed,
+    // reading fails with `NoInput` instead of waiting.
+    pub fn close(&self) {
+        self.0.state.lock().unwrap().closed = true;
+        self.0.ready.notify_all();
+    }
+}
+
+impl IntcodeComputer {
+    // Makes the computer read from the returned handle whenever its own input
+    // queue is empty. Running out of both stops the run with `NoInput`, as
+    // usual. This replaces any input request handler.
+    pub fn input_handle(&mut self) -> InputHandle {
+        let shared = Arc::new(SharedInput::default());
+        let reading = shared.clone();
+        self.set_shared_input_handler(move || match reading.take() {
+            Some(val) => InputDecision::Provide(val),
+            None => InputDecision::Block,
+        });
+        InputHandle(shared)
+    }
+
+    // Like `input_handle`, but the computer waits for values to be pushed,
+    // for at most `timeout` if given. Timing out (or the handle being closed)
+    // stops the run with `NoInput`.
+    pub fn attach_blocking_input(&mut self, timeout: Option<Duration>) -> InputHandle {
+        let shared = Arc::new(SharedInput::default());
+        let waiting = shared.clone();
+        self.set_shared_input_handler(move || match waiting.wait(timeout) {
+            Some(val) => InputDecision::Provide(val),
+            None => InputDecision::Block,
+        });
+        InputHandle(shared)
+    }
+}
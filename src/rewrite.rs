@@ -0,0 +1,244 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::Int;
+use crate::analysis::{analyze, Branch};
+use crate::hash::HashMap;
+use crate::intcode::Opcodes;
+
+// Shared machinery for transforms that move code and data around, such as
+// the obfuscator and the profile-guided optimizer. The program is rebuilt
+// from its reachable instructions only, split into basic blocks, which the
+// transform lays out in any order. Every memory cell the program uses gets
+// a new address in a data region after the code.
+//
+// Rewriting addresses is only safe when all of them are known statically,
+// so the program must be fully understood by the range analysis, and must
+// not jump to computed addresses or touch its own code as data. Relative
+// parameters are turned into position ones, which needs the relative base
+// to be the same on every path to them. Programs outside these rules are
+// rejected rather than risking a change in behavior.
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RewriteError {
+    // The analysis couldn't follow every path of the program.
+    Incomplete,
+    RelativeParam { ip: Int },
+    IndirectJump { ip: Int },
+    // Reads, writes or jumps into the middle of instructions.
+    CodeAccess { ip: Int },
+    NegativeAddress { ip: Int },
+}
+
+impl fmt::Display for RewriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Incomplete => write!(f, "The control flow of the program can't be fully determined"),
+            Self::RelativeParam { ip } => write!(f, "Relative parameters are not supported (at {ip})"),
+            Self::IndirectJump { ip } => write!(f, "Jumps to computed addresses are not supported (at {ip})"),
+            Self::CodeAccess { ip } => write!(f, "The instruction at {ip} accesses code as data"),
+            Self::NegativeAddress { ip } => write!(f, "Access to a negative address at {ip}"),
+        }
+    }
+}
+
+impl std::error::Error for RewriteError {}
+
+// Word of the output, before addresses are assigned.
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum Word {
+    Lit(Int),
+    // New address of the instruction at the old one.
+    Code(Int),
+    // New address of the memory cell at the old one.
+    Data(Int),
+    // A cell of its own, for writes nobody reads.
+    Scratch,
+    // Address of this word, plus the offset.
+    Here(Int),
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Instr {
+    pub addr: Int,
+    pub words: Vec<Word>,
+    // Instruction run next when not jumping, if any.
+    pub next: Option<Int>,
+    // Target of a jump that may be taken.
+    pub target: Option<Int>,
+}
+
+impl Instr {
+    // Whether it's a jump that is always taken.
+    pub fn is_goto(&self) -> bool {
+        self.target.is_some() && self.next.is_none()
+    }
+
+    // The same conditional jump with the opposite condition, swapping the
+    // target and the next instruction.
+    pub fn inverted(&self) -> Option<Instr> {
+        let (target, next) = (self.target?, self.next?);
+        let Word::Lit(word) = self.words[0] else { unreachable!() };
+        let mut words = self.words.clone();
+        words[0] = Word::Lit(if word % 100 == Opcodes::JMP as Int { word + 1 } else { word - 1 });
+        words[2] = Word::Code(next);
+        Some(Instr { addr: self.addr, words, next: Some(target), target: Some(next) })
+    }
+}
+
+pub(crate) fn decode_program(code: &[Int]) -> Result<Vec<Instr>, RewriteError> {
+    let analysis = analyze(code);
+    if !analysis.is_complete() {
+        return Err(RewriteError::Incomplete);
+    }
+
+    // The analysis only reaches instructions that decode, so these are valid,
+    // unless they're only there after the program writes them
+    let instrs: Vec<Instr> = analysis.reachable.iter().map(|&ip| {
+        let word = usize::try_from(ip).ok().and_then(|ip| code.get(ip)).copied().ok_or(RewriteError::CodeAccess { ip })?;
+        let opcode = (word % 100) as u8;
+        let n_params = Opcodes::n_params(opcode).unwrap();
+        let branch = analysis.branches.get(&ip).copied();
+        let may_jump = matches!(branch, Some(Branch::AlwaysTaken | Branch::Both));
+
+        let mut words = vec![Word::Lit(word)];
+        let mut target = None;
+        for i in 0..n_params {
+            let mut raw = code.get(ip as usize + 1 + i).copied().unwrap_or_default();
+            let mut mode = word / 10_i128.pow(i as u32 + 2) % 10;
+            // With a known base, relative parameters become position ones
+            if mode == 2 {
+                let base = analysis.rel_bases.get(&ip).ok_or(RewriteError::RelativeParam { ip })?;
+                raw = base.checked_add(raw).ok_or(RewriteError::NegativeAddress { ip })?;
+                mode = 0;
+                if let Word::Lit(word) = &mut words[0] {
+                    *word -= 2 * 10_i128.pow(i as u32 + 2);
+                }
+            }
+            let is_target = i == 1 && may_jump;
+            words.push(match mode {
+                0 if is_target => return Err(RewriteError::IndirectJump { ip }),
+                0 if raw < 0 => return Err(RewriteError::NegativeAddress { ip }),
+                0 => Word::Data(raw),
+                _ if is_target => {
+                    target = Some(raw);
+                    Word::Code(raw)
+                },
+                _ => Word::Lit(raw),
+            });
+        }
+
+        let falls_through = opcode != Opcodes::END && branch != Some(Branch::AlwaysTaken);
+        let next = falls_through.then_some(ip + 1 + n_params as Int);
+        Ok(Instr { addr: ip, words, next, target })
+    }).collect::<Result<_, _>>()?;
+
+    // Instructions must not overlap, nor be used as data
+    let mut code_words = BTreeMap::new();
+    for instr in &instrs {
+        for addr in instr.addr..instr.addr + instr.words.len() as Int {
+            if code_words.insert(addr, instr.addr).is_some() {
+                return Err(RewriteError::CodeAccess { ip: instr.addr });
+            }
+        }
+    }
+    for instr in &instrs {
+        for word in &instr.words {
+            let clash = match *word {
+                Word::Data(addr) => code_words.contains_key(&addr),
+                Word::Code(addr) => code_words.get(&addr) != Some(&addr),
+                _ => false,
+            };
+            if clash {
+                return Err(RewriteError::CodeAccess { ip: instr.addr });
+            }
+        }
+    }
+    Ok(instrs)
+}
+
+// Basic blocks: they start at the entry point, at jump targets, after jumps
+// and wherever the code isn't contiguous. The entry block comes first.
+pub(crate) fn basic_blocks(instrs: &[Instr]) -> Vec<Vec<Instr>> {
+    let mut blocks: Vec<Vec<Instr>> = vec![];
+    let targets: Vec<Int> = instrs.iter().filter_map(|instr| instr.target).collect();
+    let mut prev: Option<&Instr> = None;
+    for instr in instrs {
+        let leader = match prev {
+            None => true,
+            Some(prev) => prev.target.is_some() || prev.next != Some(instr.addr) || targets.contains(&instr.addr),
+        };
+        match leader {
+            true => blocks.push(vec![instr.clone()]),
+            false => blocks.last_mut().unwrap().push(instr.clone()),
+        }
+        prev = Some(instr);
+    }
+    blocks
+}
+
+// The new program, as it's laid out.
+#[derive(Default)]
+pub(crate) struct Layout {
+    pub words: Vec<Word>,
+    new_addrs: HashMap<Int, Int>,
+}
+
+impl Layout {
+    pub fn jump_to(&mut self, addr: Int) {
+        self.words.extend([Word::Lit(1105), Word::Lit(1), Word::Code(addr)]);
+    }
+
+    pub fn place(&mut self, instr: &Instr) {
+        self.new_addrs.insert(instr.addr, self.words.len() as Int);
+        self.words.extend(&instr.words);
+    }
+
+    // Leaves out a jump to whatever is placed next, which other jumps to it
+    // also go to.
+    pub fn skip(&mut self, instr: &Instr) {
+        self.new_addrs.insert(instr.addr, self.words.len() as Int);
+    }
+
+    // Jumps to the instruction that followed the block ending with `last`,
+    // unless it's placed right after it anyway. Returns whether the jump was
+    // needed.
+    pub fn chain(&mut self, last: &Instr, next_placed: Option<Int>) -> bool {
+        match last.next {
+            Some(next) if next_placed != Some(next) => {
+                self.jump_to(next);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    // Resolves every address, with the data region after the code. `order`
+    // can rearrange the cells, given by their old address (`None` for the
+    // scratch cell).
+    pub fn finish(self, code: &[Int], order: impl FnOnce(&mut Vec<Option<Int>>)) -> Vec<Int> {
+        let mut cells: Vec<Option<Int>> = self.words.iter().filter_map(|word| match word {
+            Word::Data(addr) => Some(Some(*addr)),
+            Word::Scratch => Some(None),
+            _ => None,
+        }).collect();
+        cells.sort_unstable();
+        cells.dedup();
+        order(&mut cells);
+
+        let data_start = self.words.len() as Int;
+        let data_addrs: HashMap<Option<Int>, Int> = cells.iter().enumerate().map(|(i, &cell)| (cell, data_start + i as Int)).collect();
+        let mut program: Vec<Int> = self.words.iter().enumerate().map(|(pos, word)| match *word {
+            Word::Lit(val) => val,
+            Word::Code(addr) => self.new_addrs[&addr],
+            Word::Data(addr) => data_addrs[&Some(addr)],
+            Word::Scratch => data_addrs[&None],
+            Word::Here(offset) => pos as Int + offset,
+        }).collect();
+        program.extend(cells.iter().map(|cell| match cell {
+            Some(addr) => usize::try_from(*addr).ok().and_then(|a| code.get(a)).copied().unwrap_or_default(),
+            None => 0,
+        }));
+        program
+    }
+}
@@ -0,0 +1,302 @@
+use crate::Int;
+use crate::abi::{Object, Param, ProgramWriter};
+
+// Ready-made routines following the calling convention in `abi`, linked
+// into a program on demand. Each one goes in at most once, no matter how
+// many times it's included, and should be placed where execution doesn't
+// fall into it (e.g. after the final halt).
+//
+// Arrays are stored as their length followed by the elements, and `array`
+// reserves one. Accesses through `std_array_get` and `std_array_set` check
+// the index against the length, and stop the program on the invalid
+// instruction at `std_bounds_error` when it's out of range, instead of
+// silently reading or clobbering other memory. As Intcode has no indirect
+// addressing, they patch their own instructions with the address.
+//
+// The heap starts at `HEAP_START`, far above any program and its stack, and
+// `std_alloc` hands out arrays from it, which are never freed. Strings are
+// arrays of characters, as written by `string` or read by `std_read_line`.
+//
+// Instead of including them in every program, routines can also be built
+// once into a relocatable library with `library`, and linked with programs
+// calling them by name (see `abi::link`).
+
+const BOUNDS_ERROR: &str = "std_bounds_error";
+// Cell holding the next free heap address.
+const HEAP_TOP: &str = "std_heap_top";
+
+pub const HEAP_START: Int = 1 << 40;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Routine {
+    // (a, b) -> (b, a), in place: the caller pops the arguments back
+    // instead of dropping them.
+    Swap,
+    // (a, b, c) -> (b, c, a), in place like `Swap`.
+    Rot,
+    // (array, index) -> array[index]
+    ArrayGet,
+    // (array, index, value), without a result.
+    ArraySet,
+    // (len) -> a new array of that length, with unspecified contents.
+    Alloc,
+    // (string), without a result.
+    PrintStr,
+    // (value), printed in decimal, without a result.
+    PrintInt,
+    // () -> a new string, with the next input line (without the newline).
+    ReadLine,
+    // () -> the number on the next input line, in decimal.
+    ReadInt,
+}
+
+impl Routine {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Swap => "std_swap",
+            Self::Rot => "std_rot",
+            Self::ArrayGet => "std_array_get",
+            Self::ArraySet => "std_array_set",
+            Self::Alloc => "std_alloc",
+            Self::PrintStr => "std_print_str",
+            Self::PrintInt => "std_print_int",
+            Self::ReadLine => "std_read_line",
+            Self::ReadInt => "std_read_int",
+        }
+    }
+}
+
+// The routines, and whatever they need, as a relocatable object.
+pub fn library(routines: &[Routine]) -> Object {
+    let mut w = ProgramWriter::new();
+    for &routine in routines {
+        w.include(routine);
+    }
+    // Every label is defined and unique, as the routines go in once
+    w.finish_object().unwrap()
+}
+
+impl ProgramWriter {
+    // Adds the routine to the program, unless it's already there. Starts a
+    // function, so it can't go in the middle of another one.
+    pub fn include(&mut self, routine: Routine) {
+        let name = routine.name();
+        if self.address_of(name).is_some() {
+            return;
+        }
+
+        match routine {
+            Routine::Swap => {
+                self.function(name, 2, 1);
+                self.add(self.arg(0), Param::Imm(0), self.local(0));
+                self.add(self.arg(1), Param::Imm(0), self.arg(0));
+                self.add(self.local(0), Param::Imm(0), self.arg(1));
+                self.ret();
+            },
+            Routine::Rot => {
+                self.function(name, 3, 1);
+                self.add(self.arg(0), Param::Imm(0), self.local(0));
+                self.add(self.arg(1), Param::Imm(0), self.arg(0));
+                self.add(self.arg(2), Param::Imm(0), self.arg(1));
+                self.add(self.local(0), Param::Imm(0), self.arg(2));
+                self.ret();
+            },
+            Routine::ArrayGet => {
+                self.function(name, 2, 2);
+                self.element_addr(name);
+                self.load(self.local(1), self.result(), &format!("{name}_elem"));
+                self.ret();
+            },
+            Routine::ArraySet => {
+                self.function(name, 3, 2);
+                self.element_addr(name);
+                self.store(self.local(1), self.arg(2), &format!("{name}_elem"));
+                self.ret();
+            },
+            Routine::Alloc => {
+                self.function(name, 1, 1);
+                self.less_than(self.arg(0), Param::Imm(0), self.local(0));
+                self.jump_if_true(self.local(0), Param::label(BOUNDS_ERROR));
+                self.add(Param::var(HEAP_TOP), Param::Imm(0), self.result());
+                self.store(Param::var(HEAP_TOP), self.arg(0), &format!("{name}_len"));
+                self.add(Param::var(HEAP_TOP), self.arg(0), Param::var(HEAP_TOP));
+                self.add(Param::var(HEAP_TOP), Param::Imm(1), Param::var(HEAP_TOP));
+                self.ret();
+            },
+            Routine::PrintStr => {
+                // Locals: the end of the string, the next character and a flag
+                self.function(name, 1, 3);
+                self.load(self.arg(0), self.local(0), &format!("{name}_len"));
+                self.add(self.local(0), self.arg(0), self.local(0));
+                self.add(self.local(0), Param::Imm(1), self.local(0));
+                self.add(self.arg(0), Param::Imm(1), self.local(1));
+                self.label(&format!("{name}_loop"));
+                self.equals(self.local(1), self.local(0), self.local(2));
+                self.jump_if_true(self.local(2), Param::label(&format!("{name}_end")));
+                let patch = format!("{name}_char");
+                self.add(self.local(1), Param::Imm(0), Param::var(&patch));
+                self.label_at(&patch, self.here() + 1);
+                self.output(Param::Pos(0));
+                self.add(self.local(1), Param::Imm(1), self.local(1));
+                self.jump(Param::label(&format!("{name}_loop")));
+                self.label(&format!("{name}_end"));
+                self.ret();
+            },
+            Routine::PrintInt => self.print_int(name),
+            Routine::ReadLine => {
+                // Locals: the start of the string, the next cell, the last
+                // character and a scratch cell
+                self.function(name, 0, 4);
+                self.add(Param::var(HEAP_TOP), Param::Imm(0), self.local(0));
+                self.add(self.local(0), Param::Imm(1), self.local(1));
+                self.label(&format!("{name}_loop"));
+                self.input(self.local(2));
+                self.equals(self.local(2), Param::Imm(b'\n' as Int), self.local(3));
+                self.jump_if_true(self.local(3), Param::label(&format!("{name}_end")));
+                self.store(self.local(1), self.local(2), &format!("{name}_char"));
+                self.add(self.local(1), Param::Imm(1), self.local(1));
+                self.jump(Param::label(&format!("{name}_loop")));
+
+                self.label(&format!("{name}_end"));
+                self.mul(self.local(0), Param::Imm(-1), self.local(3));
+                self.add(self.local(1), self.local(3), self.local(3));
+                self.add(self.local(3), Param::Imm(-1), self.local(3));
+                self.store(self.local(0), self.local(3), &format!("{name}_len"));
+                self.add(self.local(1), Param::Imm(0), Param::var(HEAP_TOP));
+                self.add(self.local(0), Param::Imm(0), self.result());
+                self.ret();
+            },
+            Routine::ReadInt => {
+                // Locals: the value, the last character, whether it's
+                // negative and a scratch cell
+                self.function(name, 0, 4);
+                self.add(Param::Imm(0), Param::Imm(0), self.local(0));
+                self.input(self.local(1));
+                self.equals(self.local(1), Param::Imm(b'-' as Int), self.local(2));
+                self.jump_if_false(self.local(2), Param::label(&format!("{name}_digit")));
+                self.label(&format!("{name}_loop"));
+                self.input(self.local(1));
+                self.label(&format!("{name}_digit"));
+                self.equals(self.local(1), Param::Imm(b'\n' as Int), self.local(3));
+                self.jump_if_true(self.local(3), Param::label(&format!("{name}_end")));
+                self.mul(self.local(0), Param::Imm(10), self.local(0));
+                self.add(self.local(1), Param::Imm(-(b'0' as Int)), self.local(3));
+                self.add(self.local(0), self.local(3), self.local(0));
+                self.jump(Param::label(&format!("{name}_loop")));
+
+                self.label(&format!("{name}_end"));
+                self.jump_if_false(self.local(2), Param::label(&format!("{name}_done")));
+                self.mul(self.local(0), Param::Imm(-1), self.local(0));
+                self.label(&format!("{name}_done"));
+                self.add(self.local(0), Param::Imm(0), self.result());
+                self.ret();
+            },
+        }
+
+        let checks_bounds = matches!(routine, Routine::ArrayGet | Routine::ArraySet | Routine::Alloc);
+        if checks_bounds && self.address_of(BOUNDS_ERROR).is_none() {
+            // Not a valid opcode, so running it stops the program with an error
+            self.data(BOUNDS_ERROR, &[0]);
+        }
+        if matches!(routine, Routine::Alloc | Routine::ReadLine) && self.address_of(HEAP_TOP).is_none() {
+            self.data(HEAP_TOP, &[HEAP_START]);
+        }
+    }
+
+    // Reserves an array of the given length, initially all zeros.
+    pub fn array(&mut self, name: &str, len: Int) {
+        let mut cells = vec![0; len as usize + 1];
+        cells[0] = len;
+        self.data(name, &cells);
+    }
+
+    // A string constant, stored as an array of characters.
+    pub fn string(&mut self, name: &str, text: &str) {
+        let mut cells = vec![text.len() as Int];
+        cells.extend(text.bytes().map(Int::from));
+        self.data(name, &cells);
+    }
+
+    // Copies the cell at the address held by `addr` into `dest`.
+    fn load(&mut self, addr: Param, dest: Param, patch: &str) {
+        self.add(addr, Param::Imm(0), Param::var(patch));
+        self.label_at(patch, self.here() + 1);
+        self.add(Param::Pos(0), Param::Imm(0), dest);
+    }
+
+    // Copies `value` into the cell at the address held by `addr`.
+    fn store(&mut self, addr: Param, value: Param, patch: &str) {
+        self.add(addr, Param::Imm(0), Param::var(patch));
+        self.label_at(patch, self.here() + 3);
+        self.add(value, Param::Imm(0), Param::Pos(0));
+    }
+
+    // Intcode can't divide, so digits are found by subtracting powers of
+    // ten, starting from the highest one below the value.
+    fn print_int(&mut self, name: &str) {
+        let label = |part: &str| format!("{name}_{part}");
+        // Locals: what's left to print, the exponent of the current power of
+        // ten, the power itself, the current digit and a scratch cell
+        self.function(name, 1, 5);
+        let (value, exp, power, digit, tmp) = (self.local(0), self.local(1), self.local(2), self.local(3), self.local(4));
+        self.add(self.arg(0), Param::Imm(0), value.clone());
+        self.less_than(value.clone(), Param::Imm(0), tmp.clone());
+        self.jump_if_false(tmp.clone(), Param::label(&label("positive")));
+        self.output(Param::Imm(b'-' as Int));
+        self.mul(value.clone(), Param::Imm(-1), value.clone());
+
+        // Highest power, stopping at 10^38 as the next one would overflow
+        self.label(&label("positive"));
+        self.add(Param::Imm(0), Param::Imm(0), exp.clone());
+        self.add(Param::Imm(1), Param::Imm(0), power.clone());
+        self.label(&label("grow"));
+        self.less_than(power.clone(), Param::Imm(10_i128.pow(38)), tmp.clone());
+        self.jump_if_false(tmp.clone(), Param::label(&label("digit")));
+        self.mul(power.clone(), Param::Imm(10), tmp.clone());
+        self.less_than(value.clone(), tmp.clone(), digit.clone());
+        self.jump_if_true(digit.clone(), Param::label(&label("digit")));
+        self.add(tmp.clone(), Param::Imm(0), power.clone());
+        self.add(exp.clone(), Param::Imm(1), exp.clone());
+        self.jump(Param::label(&label("grow")));
+
+        self.label(&label("digit"));
+        self.add(Param::Imm(b'0' as Int), Param::Imm(0), digit.clone());
+        self.label(&label("subtract"));
+        self.less_than(value.clone(), power.clone(), tmp.clone());
+        self.jump_if_true(tmp.clone(), Param::label(&label("output")));
+        self.mul(power.clone(), Param::Imm(-1), tmp.clone());
+        self.add(value.clone(), tmp.clone(), value.clone());
+        self.add(digit.clone(), Param::Imm(1), digit.clone());
+        self.jump(Param::label(&label("subtract")));
+        self.label(&label("output"));
+        self.output(digit);
+        self.jump_if_false(exp.clone(), Param::label(&label("end")));
+
+        // Next power down, by multiplying up to it again
+        self.add(exp.clone(), Param::Imm(-1), exp.clone());
+        self.add(Param::Imm(1), Param::Imm(0), power.clone());
+        self.add(exp.clone(), Param::Imm(0), tmp.clone());
+        self.label(&label("power"));
+        self.jump_if_false(tmp.clone(), Param::label(&label("digit")));
+        self.mul(power.clone(), Param::Imm(10), power.clone());
+        self.add(tmp.clone(), Param::Imm(-1), tmp);
+        self.jump(Param::label(&label("power")));
+
+        self.label(&label("end"));
+        self.ret();
+    }
+
+    // Checks the index (second argument) against the length of the array
+    // (first argument), and leaves the element's address in the second local.
+    fn element_addr(&mut self, name: &str) {
+        self.load(self.arg(0), self.local(0), &format!("{name}_len"));
+
+        self.less_than(self.arg(1), Param::Imm(0), self.local(1));
+        self.jump_if_true(self.local(1), Param::label(BOUNDS_ERROR));
+        self.less_than(self.arg(1), self.local(0), self.local(1));
+        self.jump_if_false(self.local(1), Param::label(BOUNDS_ERROR));
+
+        self.add(self.arg(0), self.arg(1), self.local(1));
+        self.add(self.local(1), Param::Imm(1), self.local(1));
+    }
+}
@@ -0,0 +1,188 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{IntcodeComputer, Int};
+use crate::watch::Expr;
+
+// Debugger scripts: rules checked after every instruction, which print
+// values, dump memory, patch it or stop the program when something happens,
+// so automating a debugging session doesn't need recompiling the tool.
+// Scripts have one rule per line, with blank lines and anything after a `#`
+// ignored:
+//
+//     at loop => print [count]
+//     change [50] => dump 100..120
+//     when [count] > 10 => [count] := 0; stop
+//
+// `at <addr>` fires whenever the IP gets to the address, `when <expr>` when
+// the expression becomes true (non-zero), and `change <expr>` when its value
+// changes. Expressions are watch expressions (see `watch`). Actions are
+// separated by `;`:
+//
+//     print <expr>            prints the expression and its value
+//     dump <from>..<to>       prints the memory cells in the range
+//     [<addr>] := <expr>      writes to memory
+//     stop                    stops the program as if at a breakpoint
+//
+// Changes made by the actions themselves don't fire any rule.
+
+// Most cells a single `dump` prints.
+pub const MAX_DUMP: Int = 1_000;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Trigger {
+    At(Expr),
+    When(Expr),
+    Change(Expr),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Action {
+    // Along with its source, to show it.
+    Print(String, Expr),
+    Dump(Expr, Expr),
+    Set(Expr, Expr),
+    Stop,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Rule {
+    trigger: Trigger,
+    actions: Vec<Action>,
+    // Value of the `when` or `change` expression at the last check.
+    last: Option<Int>,
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Script {
+    rules: Vec<Rule>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ScriptError {
+    // 1-based.
+    pub line: usize,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid rule on line {}", self.line)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl Script {
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // Adds the rules of another script after these ones.
+    pub fn extend(&mut self, other: Script) {
+        self.rules.extend(other.rules);
+    }
+
+    // Takes the current values of `when` and `change` expressions as the
+    // starting point, so they only fire on what happens from now on.
+    pub fn prime(&mut self, comp: &IntcodeComputer) {
+        for rule in &mut self.rules {
+            if let Trigger::When(expr) | Trigger::Change(expr) = &rule.trigger {
+                rule.last = expr.eval(comp);
+            }
+        }
+    }
+
+    // Checks every rule, running the actions of the ones that fire. Returns
+    // what they printed, and whether any of them asked to stop.
+    pub fn check(&mut self, comp: &mut IntcodeComputer) -> (Vec<String>, bool) {
+        let mut output = vec![];
+        let mut stop = false;
+
+        for i in 0..self.rules.len() {
+            let rule = &self.rules[i];
+            let fires = match &rule.trigger {
+                Trigger::At(addr) => addr.eval(comp) == Some(comp.ip()),
+                Trigger::When(cond) => is_true(cond.eval(comp)) && !is_true(rule.last),
+                Trigger::Change(expr) => expr.eval(comp) != rule.last,
+            };
+            if !fires {
+                continue;
+            }
+            for action in &rule.actions {
+                match action {
+                    Action::Print(src, expr) => output.push(format!("{src} = {}", show(expr.eval(comp)))),
+                    Action::Dump(from, to) => output.push(dump(comp, from.eval(comp), to.eval(comp))),
+                    Action::Set(addr, value) => if let (Some(addr), Some(value)) = (addr.eval(comp), value.eval(comp)) {
+                        comp.write_at(addr, value);
+                    },
+                    Action::Stop => stop = true,
+                }
+            }
+        }
+
+        self.prime(comp);
+        (output, stop)
+    }
+}
+
+fn is_true(value: Option<Int>) -> bool {
+    value.is_some_and(|v| v != 0)
+}
+
+fn show(value: Option<Int>) -> String {
+    value.map_or("?".to_owned(), |v| v.to_string())
+}
+
+fn dump(comp: &IntcodeComputer, from: Option<Int>, to: Option<Int>) -> String {
+    let (Some(from), Some(to)) = (from, to) else {
+        return format!("{}..{}: ?", show(from), show(to));
+    };
+    let end = to.min(from.saturating_add(MAX_DUMP));
+    let cells: Vec<String> = (from..end).map(|addr| comp.peek_at(addr).to_string()).collect();
+    format!("{from}..{to}: {}", cells.join(" "))
+}
+
+impl FromStr for Script {
+    type Err = ScriptError;
+
+    fn from_str(text: &str) -> Result<Self, ScriptError> {
+        let mut script = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = ScriptError { line: i + 1 };
+            let (trigger, actions) = line.split_once("=>").ok_or(error)?;
+            let (kind, expr) = trigger.trim().split_once(' ').ok_or(error)?;
+            let expr = expr.parse().map_err(|_| error)?;
+            let trigger = match kind {
+                "at" => Trigger::At(expr),
+                "when" => Trigger::When(expr),
+                "change" => Trigger::Change(expr),
+                _ => return Err(error),
+            };
+            let actions = actions.split(';').map(|action| parse_action(action.trim()).ok_or(error)).collect::<Result<_, _>>()?;
+            script.rules.push(Rule { trigger, actions, last: None });
+        }
+        Ok(script)
+    }
+}
+
+fn parse_action(text: &str) -> Option<Action> {
+    if text == "stop" {
+        return Some(Action::Stop);
+    }
+    if let Some(expr) = text.strip_prefix("print ") {
+        return Some(Action::Print(expr.trim().to_owned(), expr.parse().ok()?));
+    }
+    if let Some(range) = text.strip_prefix("dump ") {
+        let (from, to) = range.split_once("..")?;
+        return Some(Action::Dump(from.parse().ok()?, to.parse().ok()?));
+    }
+    let (dest, value) = text.split_once(":=")?;
+    match dest.parse().ok()? {
+        Expr::Mem(addr) => Some(Action::Set(*addr, value.parse().ok()?)),
+        _ => None,
+    }
+}
@@ -0,0 +1,195 @@
+use std::ops::RangeInclusive;
+use std::sync::Mutex;
+use std::thread;
+
+use crate::{IntcodeComputer, IntcodeError, Int, RunResult};
+use crate::parallel::n_threads;
+
+// Searching over arrangements of settings, a recurring pattern when several
+// machines are wired together (e.g. the amplifiers' phase settings).
+
+// All orderings of the items, in lexicographic order of their positions.
+pub fn permutations<T: Clone>(items: &[T]) -> Vec<Vec<T>> {
+    if items.len() <= 1 {
+        return vec![items.to_vec()];
+    }
+
+    let mut perms = vec![];
+    for i in 0..items.len() {
+        let mut rest = items.to_vec();
+        let first = rest.remove(i);
+        for mut perm in permutations(&rest) {
+            perm.insert(0, first.clone());
+            perms.push(perm);
+        }
+    }
+    perms
+}
+
+// Permutation with the highest score. Ties go to the earliest permutation.
+pub fn best_permutation<T: Clone, S: Ord>(items: &[T], score: impl Fn(&[T]) -> S) -> Option<(S, Vec<T>)> {
+    best_of(permutations(items).into_iter().map(|perm| (score(&perm), perm)))
+}
+
+// Same as `best_permutation`, scoring permutations on all available threads.
+pub fn par_best_permutation<T, S>(items: &[T], score: impl Fn(&[T]) -> S + Sync) -> Option<(S, Vec<T>)>
+where T: Clone + Send + Sync, S: Ord + Send {
+    let perms = permutations(items);
+    let chunk_size = perms.len().div_ceil(n_threads()).max(1);
+    let score = &score;
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = perms.chunks(chunk_size).map(|chunk| {
+            scope.spawn(move || best_of(chunk.iter().map(|perm| (score(perm), perm.clone()))))
+        }).collect();
+
+        // Chunks are joined in order, so ties still go to the earliest one
+        best_of(workers.into_iter().filter_map(|w| w.join().unwrap()))
+    })
+}
+
+fn best_of<T, S: Ord>(candidates: impl Iterator<Item = (S, Vec<T>)>) -> Option<(S, Vec<T>)> {
+    candidates.fold(None, |best, (score, perm)| match best {
+        Some((ref best_score, _)) if *best_score >= score => best,
+        _ => Some((score, perm)),
+    })
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Searching for the input that makes a program produce some output. The
+// parameter space is a range of integers, each one mapped to the inputs and
+// memory patches a run should start with; bigger spaces can be encoded in a
+// single integer (e.g. `noun * 100 + verb`).
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Setup {
+    pub inputs: Vec<Int>,
+    pub patches: Vec<(Int, Int)>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Strategy {
+    // Tries every parameter in order.
+    Exhaustive,
+    // Same as `Exhaustive`, on all available threads.
+    Parallel,
+    // Binary search, for predicates that are false up to some parameter and
+    // true from then on.
+    Bisect,
+}
+
+// Steps a cancellable run takes between checks of whether it's still wanted.
+const CANCEL_CHECK: u64 = 10_000;
+
+impl Setup {
+    // Outputs of a copy of the computer run from this setup, or `None` if
+    // it failed or took more than `step_limit` steps.
+    pub(crate) fn outputs(&self, comp: &IntcodeComputer, step_limit: Option<u64>) -> Option<Vec<Int>> {
+        self.outputs_until(comp, step_limit, || false)
+    }
+
+    // Same as `outputs`, giving up with `None` as soon as `cancel` returns
+    // true, which is checked every few thousand steps.
+    pub(crate) fn outputs_until(&self, comp: &IntcodeComputer, step_limit: Option<u64>, cancel: impl Fn() -> bool) -> Option<Vec<Int>> {
+        let mut comp = comp.clone();
+        for &(pos, value) in &self.patches {
+            comp.write_at(pos, value);
+        }
+        comp.input_iter(self.inputs.iter().copied());
+        let end = match (comp.step_limit(), step_limit.map(|limit| comp.steps().saturating_add(limit))) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+
+        let mut outputs = vec![];
+        loop {
+            let pause = comp.steps().saturating_add(CANCEL_CHECK);
+            comp.set_step_limit(Some(end.map_or(pause, |end| end.min(pause))));
+            match comp.try_run() {
+                Ok(RunResult::Output(val)) => outputs.push(val),
+                Ok(RunResult::Finished) => return Some(outputs),
+                Err(IntcodeError::StepLimit { steps }) if Some(steps) != end && !cancel() => {},
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+pub struct InputSearch<'a, F> {
+    comp: &'a IntcodeComputer,
+    setup: F,
+    step_limit: Option<u64>,
+}
+
+impl<'a, F: Fn(Int) -> Setup + Sync> InputSearch<'a, F> {
+    pub fn new(comp: &'a IntcodeComputer, setup: F) -> Self {
+        Self { comp, setup, step_limit: None }
+    }
+
+    // Limits every run, so that parameters making the program loop forever
+    // are simply rejected.
+    pub fn with_step_limit(mut self, limit: u64) -> Self {
+        self.step_limit = Some(limit);
+        self
+    }
+
+    // Outputs of a run with the given parameter, or `None` if it failed.
+    pub fn outputs(&self, param: Int) -> Option<Vec<Int>> {
+        (self.setup)(param).outputs(self.comp, self.step_limit)
+    }
+
+    // Smallest parameter in the range whose run succeeds and satisfies the
+    // predicate. `Bisect` needs that to be monotonic: once a parameter is
+    // accepted, so are all the ones after it. Otherwise it gives some
+    // accepted parameter right after a rejected one, not necessarily the
+    // smallest.
+    pub fn find(&self, range: RangeInclusive<Int>, strategy: Strategy, pred: impl Fn(&[Int]) -> bool + Sync) -> Option<Int> {
+        let accepts = |param| self.outputs(param).is_some_and(|out| pred(&out));
+        match strategy {
+            Strategy::Exhaustive => range.into_iter().find(|&p| accepts(p)),
+            Strategy::Parallel => par_find(range, &accepts),
+            Strategy::Bisect => {
+                let (mut lo, mut hi) = range.into_inner();
+                if lo > hi || !accepts(hi) {
+                    return None;
+                }
+                while lo < hi {
+                    // Rounds down, without overflowing on wide ranges
+                    let mid = (lo & hi) + ((lo ^ hi) >> 1);
+                    if accepts(mid) { hi = mid } else { lo = mid + 1 }
+                }
+                Some(lo)
+            },
+        }
+    }
+}
+
+// Smallest parameter in the range accepted by the predicate, trying them on
+// all available threads.
+pub(crate) fn par_find(range: RangeInclusive<Int>, accepts: &(impl Fn(Int) -> bool + Sync)) -> Option<Int> {
+    let n_threads = n_threads();
+    let best: Mutex<Option<Int>> = Mutex::new(None);
+
+    thread::scope(|scope| {
+        for start in 0..n_threads {
+            let (best, range) = (&best, range.clone());
+            // Threads take interleaved parameters, and stop once they're
+            // past the smallest answer found so far.
+            scope.spawn(move || {
+                for p in range.skip(start).step_by(n_threads) {
+                    if best.lock().unwrap().is_some_and(|b| b < p) {
+                        break;
+                    }
+                    if accepts(p) {
+                        let mut best = best.lock().unwrap();
+                        *best = Some(best.map_or(p, |b| b.min(p)));
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    best.into_inner().unwrap()
+}
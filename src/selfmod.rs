@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::{IntcodeComputer, Int};
+use crate::hash::HashSet;
+
+// Detection of self-modifying code. While enabled, the computer remembers
+// every address it has executed as part of an instruction (opcode or
+// parameter), and flags program writes to any of them. Code that's only
+// overwritten before it ever runs, like a loader filling in a buffer, isn't
+// flagged.
+//
+// Writes are counted by the address written and the instruction writing
+// it, which a report summarizes: which code was overwritten, by which
+// instructions and how many times. Programs that only patch operands keep
+// their instruction boundaries, while rewriting opcodes changes what the
+// code decodes to. The first writes are also logged in order, up to
+// `MAX_LOGGED_WRITES`, as programs patching their operands in a loop write
+// to their code over and over.
+
+pub const MAX_LOGGED_WRITES: usize = 1_000;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum CodeWriteMode {
+    #[default]
+    Off,
+    // Keeps a log of the writes, see `code_writes`.
+    Record,
+    // Also stops `run_until_stop` right after every such write.
+    Break,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct CodeWrite {
+    // Instruction doing the write.
+    pub ip: Int,
+    pub addr: Int,
+}
+
+impl fmt::Display for CodeWrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Code at {} overwritten by the instruction at {}", self.addr, self.ip)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CodeWatch {
+    breaking: bool,
+    executed: HashSet<Int>,
+    opcodes: HashSet<Int>,
+    // Every address written so far.
+    sites: BTreeMap<Int, ModifiedCode>,
+    total: u64,
+    // The first writes, in order.
+    log: Vec<CodeWrite>,
+    // Write that should stop the run, if any.
+    hit: Option<CodeWrite>,
+}
+
+impl CodeWatch {
+    pub fn record_executed(&mut self, ip: Int, n_params: usize) {
+        self.executed.extend((0..=n_params as Int).map(|i| ip + i));
+        self.opcodes.insert(ip);
+    }
+
+    pub fn record_write(&mut self, ip: Int, addr: Int) {
+        if self.executed.contains(&addr) {
+            let write = CodeWrite { ip, addr };
+            let site = self.sites.entry(addr).or_insert_with(|| ModifiedCode { addr, opcode: false, writers: BTreeMap::new() });
+            site.opcode |= self.opcodes.contains(&addr);
+            *site.writers.entry(ip).or_default() += 1;
+            self.total += 1;
+            if self.log.len() < MAX_LOGGED_WRITES {
+                self.log.push(write);
+            }
+            if self.breaking {
+                self.hit = Some(write);
+            }
+        }
+    }
+}
+
+// One address of executed code that was overwritten.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ModifiedCode {
+    pub addr: Int,
+    // Whether it had been executed as an opcode when it was overwritten,
+    // rather than only as a parameter.
+    pub opcode: bool,
+    // Times each instruction wrote to it, by their addresses.
+    pub writers: BTreeMap<Int, u64>,
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct SelfModReport {
+    // Sorted by address.
+    pub sites: Vec<ModifiedCode>,
+    pub writes: u64,
+}
+
+impl SelfModReport {
+    pub fn is_empty(&self) -> bool {
+        self.sites.is_empty()
+    }
+
+    pub fn rewrites_opcodes(&self) -> bool {
+        self.sites.iter().any(|site| site.opcode)
+    }
+}
+
+impl fmt::Display for SelfModReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "No executed code was overwritten");
+        }
+        write!(f, "Writes to executed code: {}", self.writes)?;
+        for site in &self.sites {
+            let kind = if site.opcode { "opcode" } else { "parameter" };
+            let writers: Vec<String> = site.writers.iter().map(|(ip, count)| format!("{ip} x{count}")).collect();
+            write!(f, "\n  {} ({kind}): {}", site.addr, writers.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl IntcodeComputer {
+    // Switching modes keeps what's been recorded so far.
+    pub fn set_code_write_mode(&mut self, mode: CodeWriteMode) {
+        match mode {
+            CodeWriteMode::Off => self.code_watch = None,
+            _ => self.code_watch.get_or_insert_with(Default::default).breaking = mode == CodeWriteMode::Break,
+        }
+    }
+
+    // The first writes to executed code so far (up to `MAX_LOGGED_WRITES`),
+    // in order.
+    pub fn code_writes(&self) -> &[CodeWrite] {
+        self.code_watch.as_ref().map_or(&[], |watch| &watch.log)
+    }
+
+    // Summary of the writes so far. Empty unless writes are being recorded.
+    pub fn self_mod_report(&self) -> SelfModReport {
+        let Some(watch) = &self.code_watch else { return SelfModReport::default() };
+        SelfModReport { sites: watch.sites.values().cloned().collect(), writes: watch.total }
+    }
+
+    pub(crate) fn take_code_write_hit(&mut self) -> Option<CodeWrite> {
+        self.code_watch.as_mut()?.hit.take()
+    }
+}
@@ -0,0 +1,338 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+
+use crate::Int;
+use crate::intcode::Opcodes;
+use crate::hash::HashMap;
+
+// Symbolic execution of programs that are pure arithmetic over their inputs,
+// and solving for the inputs that produce some outputs with an SMT solver.
+//
+// Inputs are symbols and every value computed from them is kept as an
+// expression. Control flow and addresses must stay concrete: a jump on an
+// input-dependent condition, or a write to an input-dependent address, ends
+// the analysis with an error. The outputs are then encoded as an SMT-LIB
+// query, which can be handed to any solver reading it from stdin (e.g.
+// `z3 -in`).
+//
+// Subterms are shared rather than copied, so programs reusing values (e.g.
+// doubling one in a loop) don't grow exponentially large expressions. The
+// query names every subterm used more than once with `define-fun`.
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Expr {
+    Const(Int),
+    // The n-th value read by the program.
+    Input(usize),
+    Add(Rc<Expr>, Rc<Expr>),
+    Mul(Rc<Expr>, Rc<Expr>),
+    // Comparisons evaluate to 1 or 0, like the LT and EQ instructions.
+    Lt(Rc<Expr>, Rc<Expr>),
+    Eq(Rc<Expr>, Rc<Expr>),
+}
+
+impl Expr {
+    pub fn as_const(&self) -> Option<Int> {
+        match self {
+            Self::Const(val) => Some(*val),
+            _ => None,
+        }
+    }
+
+    // Combining constants is folded right away, so concrete parts of the
+    // program never grow expressions.
+    fn binary(opcode: u8, a: Rc<Expr>, b: Rc<Expr>) -> Option<Expr> {
+        if let (Some(x), Some(y)) = (a.as_const(), b.as_const()) {
+            let val = match opcode {
+                Opcodes::ADD => x.checked_add(y)?,
+                Opcodes::MUL => x.checked_mul(y)?,
+                Opcodes::LT => (x < y) as Int,
+                _ => (x == y) as Int,
+            };
+            return Some(Self::Const(val));
+        }
+
+        Some(match opcode {
+            Opcodes::ADD => Self::Add(a, b),
+            Opcodes::MUL => Self::Mul(a, b),
+            Opcodes::LT => Self::Lt(a, b),
+            _ => Self::Eq(a, b),
+        })
+    }
+
+    pub fn operands(&self) -> Option<(&Rc<Expr>, &Rc<Expr>)> {
+        match self {
+            Self::Const(_) | Self::Input(_) => None,
+            Self::Add(a, b) | Self::Mul(a, b) | Self::Lt(a, b) | Self::Eq(a, b) => Some((a, b)),
+        }
+    }
+
+    // Value of the expression for the given inputs.
+    pub fn eval(&self, inputs: &[Int]) -> Option<Int> {
+        self.eval_shared(inputs, &mut HashMap::default())
+    }
+
+    // Shared subterms are only evaluated once.
+    fn eval_shared(&self, inputs: &[Int], known: &mut HashMap<*const Expr, Option<Int>>) -> Option<Int> {
+        let mut eval = |expr: &Rc<Expr>| match known.get(&Rc::as_ptr(expr)) {
+            Some(&val) => val,
+            None => {
+                let val = expr.eval_shared(inputs, known);
+                known.insert(Rc::as_ptr(expr), val);
+                val
+            },
+        };
+        Some(match self {
+            Self::Const(val) => *val,
+            Self::Input(n) => *inputs.get(*n)?,
+            Self::Add(a, b) => eval(a)?.checked_add(eval(b)?)?,
+            Self::Mul(a, b) => eval(a)?.checked_mul(eval(b)?)?,
+            Self::Lt(a, b) => (eval(a)? < eval(b)?) as Int,
+            Self::Eq(a, b) => (eval(a)? == eval(b)?) as Int,
+        })
+    }
+
+    // SMT-LIB term, with the operands written by `operand`.
+    fn term(&self, operand: &mut impl FnMut(&Rc<Expr>) -> String) -> String {
+        match self {
+            Self::Const(val) if *val < 0 => format!("(- {})", val.unsigned_abs()),
+            Self::Const(val) => val.to_string(),
+            Self::Input(n) => format!("in{n}"),
+            Self::Add(a, b) => format!("(+ {} {})", operand(a), operand(b)),
+            Self::Mul(a, b) => format!("(* {} {})", operand(a), operand(b)),
+            Self::Lt(a, b) => format!("(ite (< {} {}) 1 0)", operand(a), operand(b)),
+            Self::Eq(a, b) => format!("(ite (= {} {}) 1 0)", operand(a), operand(b)),
+        }
+    }
+}
+
+// SMT-LIB term, writing shared subterms out in full every time.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.term(&mut |expr| expr.to_string()))
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SymbolicError {
+    // The instruction at the IP isn't known, or depends on the inputs.
+    BadInstruction { ip: Int },
+    SymbolicJump { ip: Int },
+    SymbolicAddress { ip: Int },
+    // The program reads more inputs than the ones being solved for.
+    MissingInput { ip: Int },
+    Overflow { ip: Int },
+    StepLimit { steps: u64 },
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BadInstruction { ip } => write!(f, "Unknown or input-dependent instruction at position {ip}"),
+            Self::SymbolicJump { ip } => write!(f, "Jump depending on the inputs at position {ip}"),
+            Self::SymbolicAddress { ip } => write!(f, "Address depending on the inputs at position {ip}"),
+            Self::MissingInput { ip } => write!(f, "The program reads more inputs than available at position {ip}"),
+            Self::Overflow { ip } => write!(f, "Arithmetic overflow at position {ip}"),
+            Self::StepLimit { steps } => write!(f, "Step limit reached after {steps} steps"),
+        }
+    }
+}
+
+impl std::error::Error for SymbolicError {}
+
+// Runs the program with `n_inputs` symbolic inputs until it halts, returning
+// its outputs as expressions over those inputs.
+pub fn symbolic_outputs(code: &[Int], n_inputs: usize, max_steps: u64) -> Result<Vec<Expr>, SymbolicError> {
+    let mut memory: HashMap<Int, Rc<Expr>> = code.iter().enumerate().map(|(i, &v)| (i as Int, Rc::new(Expr::Const(v)))).collect();
+    let read = |memory: &HashMap<Int, Rc<Expr>>, pos: Int| memory.get(&pos).cloned().unwrap_or_else(|| Rc::new(Expr::Const(0)));
+    let (mut ip, mut rel_base, mut next_input): (Int, Int, usize) = (0, 0, 0);
+    let mut outputs = vec![];
+
+    for _ in 0..max_steps {
+        let bad = SymbolicError::BadInstruction { ip };
+        let overflow = SymbolicError::Overflow { ip };
+        let instr = read(&memory, ip).as_const().ok_or(bad.clone())?;
+        let opcode = (instr % 100) as u8;
+        let n_params = match opcode {
+            Opcodes::ADD | Opcodes::MUL | Opcodes::LT | Opcodes::EQ => 3,
+            Opcodes::JMP | Opcodes::JMN => 2,
+            Opcodes::IN | Opcodes::OUT | Opcodes::RLB => 1,
+            Opcodes::END => return Ok(outputs),
+            _ => return Err(bad),
+        };
+
+        // Address each parameter refers to, or `None` for immediate ones
+        let (mut raws, mut addrs) = ([0; 3], [None; 3]);
+        for i in 0..n_params {
+            let pos = ip.checked_add(1 + i as Int).ok_or(overflow.clone())?;
+            raws[i] = read(&memory, pos).as_const().ok_or(SymbolicError::SymbolicAddress { ip })?;
+            addrs[i] = match instr / 10_i128.pow(i as u32 + 2) % 10 {
+                0 => Some(raws[i]),
+                1 => None,
+                2 => Some(rel_base.checked_add(raws[i]).ok_or(overflow.clone())?),
+                _ => return Err(bad),
+            };
+        }
+        let value = |memory: &HashMap<Int, Rc<Expr>>, i: usize| match addrs[i] {
+            Some(addr) => read(memory, addr),
+            None => Rc::new(Expr::Const(raws[i])),
+        };
+        let concrete = |expr: Rc<Expr>| expr.as_const().ok_or(SymbolicError::SymbolicJump { ip });
+        let target = |i: usize| addrs[i].ok_or(bad.clone());
+
+        let mut next_ip = ip.checked_add(1 + n_params as Int).ok_or(overflow.clone())?;
+        match opcode {
+            Opcodes::ADD | Opcodes::MUL | Opcodes::LT | Opcodes::EQ => {
+                let result = Expr::binary(opcode, value(&memory, 0), value(&memory, 1)).ok_or(overflow)?;
+                memory.insert(target(2)?, Rc::new(result));
+            },
+            Opcodes::IN => {
+                if next_input == n_inputs {
+                    return Err(SymbolicError::MissingInput { ip });
+                }
+                memory.insert(target(0)?, Rc::new(Expr::Input(next_input)));
+                next_input += 1;
+            },
+            Opcodes::OUT => outputs.push(Expr::clone(&value(&memory, 0))),
+            Opcodes::JMP | Opcodes::JMN => {
+                let cond = concrete(value(&memory, 0))?;
+                if (cond != 0) == (opcode == Opcodes::JMP) {
+                    next_ip = concrete(value(&memory, 1))?;
+                }
+            },
+            _ => {
+                let delta = value(&memory, 0).as_const().ok_or(SymbolicError::SymbolicAddress { ip })?;
+                rel_base = rel_base.checked_add(delta).ok_or(overflow)?;
+            },
+        }
+        ip = next_ip;
+    }
+
+    Err(SymbolicError::StepLimit { steps: max_steps })
+}
+
+// SMT-LIB script asking for inputs that make the first outputs equal the
+// targets. The solver's answer is read back with `parse_model`.
+pub fn query(outputs: &[Expr], n_inputs: usize, targets: &[Int]) -> String {
+    let mut script = String::from("(set-logic QF_NIA)\n");
+    for n in 0..n_inputs {
+        script += &format!("(declare-const in{n} Int)\n");
+    }
+
+    let outputs = &outputs[..outputs.len().min(targets.len())];
+    let mut uses = HashMap::default();
+    outputs.iter().for_each(|output| count_uses(output, &mut uses));
+    let mut names = HashMap::default();
+    let terms: Vec<String> = outputs.iter().map(|output| shared_term(output, &uses, &mut names, &mut script)).collect();
+    for (term, &target) in terms.iter().zip(targets) {
+        script += &format!("(assert (= {term} {}))\n", Expr::Const(target));
+    }
+    // Outputs that were never produced can't match anything
+    if targets.len() > outputs.len() {
+        script += "(assert false)\n";
+    }
+    script += "(check-sat)\n";
+    if n_inputs > 0 {
+        let names: Vec<String> = (0..n_inputs).map(|n| format!("in{n}")).collect();
+        script += &format!("(get-value ({}))\n", names.join(" "));
+    }
+    script
+}
+
+// How many times each subterm is used, counting every subterm only once.
+fn count_uses(expr: &Expr, uses: &mut HashMap<*const Expr, usize>) {
+    if let Some((a, b)) = expr.operands() {
+        for operand in [a, b] {
+            let count = uses.entry(Rc::as_ptr(operand)).or_insert(0);
+            *count += 1;
+            if *count == 1 {
+                count_uses(operand, uses);
+            }
+        }
+    }
+}
+
+// Term for the expression, defining subterms used more than once in the
+// script the first time they show up.
+fn shared_term(expr: &Expr, uses: &HashMap<*const Expr, usize>, names: &mut HashMap<*const Expr, String>, script: &mut String) -> String {
+    expr.term(&mut |operand| {
+        let ptr = Rc::as_ptr(operand);
+        if let Some(name) = names.get(&ptr) {
+            return name.clone();
+        }
+        let term = shared_term(operand, uses, names, script);
+        if uses[&ptr] < 2 || operand.operands().is_none() {
+            return term;
+        }
+        let name = format!("t{}", names.len());
+        *script += &format!("(define-fun {name} () Int {term})\n");
+        names.insert(ptr, name.clone());
+        name
+    })
+}
+
+// Reads the solver's reply to `query`: `None` if unsatisfiable, otherwise
+// the value of every input.
+pub fn parse_model(reply: &str, n_inputs: usize) -> Result<Option<Vec<Int>>, SmtError> {
+    let mut lines = reply.trim_start().splitn(2, '\n');
+    match lines.next().map(str::trim) {
+        Some("unsat") => return Ok(None),
+        Some("sat") => {},
+        other => return Err(SmtError::BadReply(other.unwrap_or_default().to_owned())),
+    }
+
+    let model = lines.next().unwrap_or_default().replace('(', " ( ").replace(')', " ) ");
+    let tokens: Vec<&str> = model.split_whitespace().collect();
+    let mut values = vec![None; n_inputs];
+    let mut i = 0;
+    while i < tokens.len() {
+        let index = tokens[i].strip_prefix("in").and_then(|n| n.parse::<usize>().ok()).filter(|&n| n < n_inputs);
+        if let Some(n) = index {
+            // Either `v` or `( - v )`
+            values[n] = match tokens.get(i + 1..i + 5) {
+                Some(["(", "-", v, ")"]) => v.parse::<Int>().ok().map(|v| -v),
+                _ => tokens.get(i + 1).and_then(|v| v.parse().ok()),
+            };
+        }
+        i += 1;
+    }
+
+    values.into_iter().collect::<Option<_>>().map(Some).ok_or_else(|| SmtError::BadReply(reply.to_owned()))
+}
+
+#[derive(Debug)]
+pub enum SmtError {
+    Symbolic(SymbolicError),
+    // Couldn't run the solver or talk to it.
+    Io(io::Error),
+    BadReply(String),
+}
+
+impl fmt::Display for SmtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Symbolic(e) => write!(f, "{e}"),
+            Self::Io(e) => write!(f, "Could not run the solver: {e}"),
+            Self::BadReply(reply) => write!(f, "Unexpected solver reply: {reply}"),
+        }
+    }
+}
+
+impl std::error::Error for SmtError {}
+
+// Solves for inputs making the program produce the target outputs, running
+// `solver` (e.g. `["z3", "-in"]`) with the query on its stdin.
+pub fn solve(code: &[Int], n_inputs: usize, targets: &[Int], max_steps: u64, solver: &[&str]) -> Result<Option<Vec<Int>>, SmtError> {
+    let outputs = symbolic_outputs(code, n_inputs, max_steps).map_err(SmtError::Symbolic)?;
+    let script = query(&outputs, n_inputs, targets);
+
+    let (program, args) = solver.split_first().ok_or_else(|| SmtError::BadReply("no solver given".to_owned()))?;
+    let mut child = Command::new(program).args(args)
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().map_err(SmtError::Io)?;
+    child.stdin.take().expect("stdin is piped").write_all(script.as_bytes()).map_err(SmtError::Io)?;
+    let reply = child.wait_with_output().map_err(SmtError::Io)?;
+
+    parse_model(&String::from_utf8_lossy(&reply.stdout), n_inputs)
+}
@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+use std::mem::size_of;
+use std::sync::Arc;
+
+use crate::{IntcodeComputer, Int};
+use crate::memory::Memory;
+
+// Compact copies of a computer's state, for searches that need to keep
+// thousands of them around. Memory is stored as runs of consecutive cells
+// holding the same value, and cells holding zero aren't stored at all.
+//
+// Snapshots can also be taken relative to an earlier one, storing only the
+// cells that changed since. Restoring them goes through the whole chain of
+// bases transparently.
+//
+// A snapshot only holds the machine state: registers, pending inputs and
+// memory. Restoring it into a computer keeps that computer's configuration
+// (limits, extensions, devices...).
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct Run {
+    start: Int,
+    len: Int,
+    value: Int,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Snapshot {
+    ip: Int,
+    rel_base: Int,
+    is_finished: bool,
+    steps: u64,
+    inputs: Vec<Int>,
+    runs: Vec<Run>,
+    base: Option<Arc<Snapshot>>,
+}
+
+impl Snapshot {
+    // Approximate number of bytes taken by the snapshot, not counting its
+    // base.
+    pub fn size(&self) -> usize {
+        size_of::<Self>() + self.inputs.len() * size_of::<Int>() + self.runs.len() * size_of::<Run>()
+    }
+
+    pub fn base(&self) -> Option<&Arc<Snapshot>> {
+        self.base.as_ref()
+    }
+
+    // Non-zero memory cells of the snapshot, with its bases applied.
+    fn cells(&self) -> BTreeMap<Int, Int> {
+        let mut cells = self.base.as_ref().map(|base| base.cells()).unwrap_or_default();
+        for run in &self.runs {
+            for pos in run.start..run.start + run.len {
+                match run.value {
+                    0 => cells.remove(&pos),
+                    value => cells.insert(pos, value),
+                };
+            }
+        }
+        cells
+    }
+
+    fn apply(&self, memory: &mut Memory) {
+        if let Some(base) = &self.base {
+            base.apply(memory);
+        }
+        for run in &self.runs {
+            for pos in run.start..run.start + run.len {
+                memory.insert(pos, run.value);
+            }
+        }
+    }
+}
+
+impl IntcodeComputer {
+    pub fn snapshot(&self) -> Snapshot {
+        let runs = encode(self.memory().filter(|&(_, v)| v != 0));
+        self.snapshot_with(runs, None)
+    }
+
+    // Snapshot storing only the memory cells that differ from the base.
+    pub fn snapshot_since(&self, base: &Arc<Snapshot>) -> Snapshot {
+        let base_cells = base.cells();
+        let mut changed: Vec<(Int, Int)> = self.memory()
+            .filter(|&(pos, v)| base_cells.get(&pos).copied().unwrap_or(0) != v)
+            .collect();
+        // Cells that went back to zero
+        changed.extend(base_cells.keys().filter(|&&pos| self[pos] == 0).map(|&pos| (pos, 0)));
+        changed.sort_unstable();
+        changed.dedup();
+
+        self.snapshot_with(encode(changed.into_iter()), Some(base.clone()))
+    }
+
+    // Puts the computer back in the state of the snapshot.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.memory.reset(&[]);
+        snapshot.apply(&mut self.memory);
+        self.ip = snapshot.ip;
+        self.rel_base = snapshot.rel_base;
+        self.is_finished = snapshot.is_finished;
+        self.steps = snapshot.steps;
+        self.input_queue = snapshot.inputs.iter().copied().collect();
+        self.reset_cycles();
+    }
+
+    fn snapshot_with(&self, runs: Vec<Run>, base: Option<Arc<Snapshot>>) -> Snapshot {
+        Snapshot {
+            ip: self.ip,
+            rel_base: self.rel_base,
+            is_finished: self.is_finished,
+            steps: self.steps,
+            inputs: self.input_queue.iter().copied().collect(),
+            runs,
+            base,
+        }
+    }
+}
+
+// Runs of the cells, which must be sorted by address.
+fn encode(cells: impl Iterator<Item = (Int, Int)>) -> Vec<Run> {
+    let mut runs: Vec<Run> = vec![];
+    for (pos, value) in cells {
+        match runs.last_mut() {
+            Some(run) if run.value == value && run.start + run.len == pos => run.len += 1,
+            _ => runs.push(Run { start: pos, len: 1, value }),
+        }
+    }
+    runs
+}
@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::{IntcodeComputer, Int};
+use crate::hash::HashSet;
+
+// Run statistics, for comparing programs or implementations. Collection is
+// off by default; once enabled, every instruction executed is accounted for
+// until it's reset or disabled. Only time spent inside `try_run` (and the
+// functions built on it) and `run_until_stop` counts as elapsed.
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct BranchCount {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+impl BranchCount {
+    pub fn total(&self) -> u64 {
+        self.taken + self.not_taken
+    }
+
+    // Share of the times the jump was taken, from 0 to 1.
+    pub fn taken_ratio(&self) -> f64 {
+        match self.total() {
+            0 => 0.0,
+            total => self.taken as f64 / total as f64,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct RunStats {
+    pub instructions: u64,
+    pub inputs: u64,
+    pub outputs: u64,
+    // Highest address read, written or executed, if any.
+    pub max_addr: Option<Int>,
+    pub addrs_written: usize,
+    pub min_rel_base: Int,
+    pub max_rel_base: Int,
+    pub elapsed: Duration,
+    // Outcomes of every conditional jump executed, by its address.
+    pub branches: BTreeMap<Int, BranchCount>,
+}
+
+impl fmt::Display for RunStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Instructions:      {}", self.instructions)?;
+        writeln!(f, "Inputs:            {}", self.inputs)?;
+        writeln!(f, "Outputs:           {}", self.outputs)?;
+        match self.max_addr {
+            Some(addr) => writeln!(f, "Highest address:   {addr}")?,
+            None => writeln!(f, "Highest address:   -")?,
+        }
+        writeln!(f, "Addresses written: {}", self.addrs_written)?;
+        writeln!(f, "Relative base:     {} to {}", self.min_rel_base, self.max_rel_base)?;
+        write!(f, "Elapsed:           {:?}", self.elapsed)?;
+        if !self.branches.is_empty() {
+            write!(f, "\nBranches:")?;
+        }
+        for (ip, count) in &self.branches {
+            write!(f, "\n  {ip}: taken {} of {} times ({:.0}%)", count.taken, count.total(), 100.0 * count.taken_ratio())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct StatsCollector {
+    stats: RunStats,
+    written: HashSet<Int>,
+}
+
+impl StatsCollector {
+    fn new(rel_base: Int) -> Self {
+        let stats = RunStats { min_rel_base: rel_base, max_rel_base: rel_base, ..Default::default() };
+        Self { stats, written: HashSet::default() }
+    }
+
+    pub fn record_branch(&mut self, ip: Int, taken: bool) {
+        let count = self.stats.branches.entry(ip).or_default();
+        match taken {
+            true => count.taken += 1,
+            false => count.not_taken += 1,
+        }
+    }
+
+    pub fn record_write(&mut self, addr: Int) {
+        if self.written.insert(addr) {
+            self.stats.addrs_written += 1;
+        }
+    }
+
+    // Accounts for an executed instruction, given the highest address it
+    // touched and the relative base after it.
+    pub fn record_instruction(&mut self, input: bool, output: bool, max_addr: Int, rel_base: Int) {
+        let stats = &mut self.stats;
+        stats.instructions += 1;
+        stats.inputs += input as u64;
+        stats.outputs += output as u64;
+        stats.max_addr = stats.max_addr.max(Some(max_addr));
+        stats.min_rel_base = stats.min_rel_base.min(rel_base);
+        stats.max_rel_base = stats.max_rel_base.max(rel_base);
+    }
+}
+
+impl IntcodeComputer {
+    // Enabling keeps the statistics collected so far, if any.
+    pub fn set_collect_stats(&mut self, enabled: bool) {
+        match enabled {
+            true => _ = self.stats.get_or_insert_with(|| Box::new(StatsCollector::new(self.rel_base))),
+            false => self.stats = None,
+        }
+    }
+
+    pub fn stats(&self) -> Option<RunStats> {
+        self.stats.as_ref().map(|collector| collector.stats.clone())
+    }
+
+    // Returns the statistics so far and starts collecting from scratch.
+    pub fn take_stats(&mut self) -> Option<RunStats> {
+        let collector = self.stats.as_mut()?;
+        let stats = collector.stats.clone();
+        **collector = StatsCollector::new(self.rel_base);
+        Some(stats)
+    }
+
+    // Runs `f`, adding the time it takes to the statistics.
+    pub(crate) fn timed<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let start = self.stats.is_some().then(Instant::now);
+        let result = f(self);
+        if let (Some(start), Some(collector)) = (start, &mut self.stats) {
+            collector.stats.elapsed += start.elapsed();
+        }
+        result
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::{IntcodeComputer, IntcodeError, Int};
+use crate::hash::HashMap;
+
+// Names for memory addresses, both code labels and variables. Once attached
+// to a computer, they're used wherever it shows addresses: its disassembly,
+// traces, watch expressions (where a name stands for its address) and error
+// descriptions. Copies of a computer share its symbols.
+//
+// Symbol files have one `name = address` definition per line. Blank lines
+// and anything after a `#` are ignored.
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct SymbolTable {
+    names: BTreeMap<Int, String>,
+    addrs: HashMap<String, Int>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Names the address, replacing its previous name. A name can only refer
+    // to one address, so it's moved if already in use.
+    pub fn insert(&mut self, addr: Int, name: &str) {
+        if let Some(old_addr) = self.addrs.insert(name.to_owned(), addr) {
+            self.names.remove(&old_addr);
+        }
+        if let Some(old_name) = self.names.insert(addr, name.to_owned()) {
+            if old_name != name {
+                self.addrs.remove(&old_name);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        if let Some(addr) = self.addrs.remove(name) {
+            self.names.remove(&addr);
+        }
+    }
+
+    pub fn name_of(&self, addr: Int) -> Option<&str> {
+        self.names.get(&addr).map(String::as_str)
+    }
+
+    pub fn addr_of(&self, name: &str) -> Option<Int> {
+        self.addrs.get(name).copied()
+    }
+
+    // The address relative to the closest symbol at or before it, as in
+    // `loop+3`. Meant for code addresses.
+    pub fn locate(&self, addr: Int) -> Option<String> {
+        let (&base, name) = self.names.range(..=addr).next_back()?;
+        Some(match addr - base {
+            0 => name.clone(),
+            offset => format!("{name}+{offset}"),
+        })
+    }
+
+    // Sorted by address.
+    pub fn iter(&self) -> impl Iterator<Item = (Int, &str)> {
+        self.names.iter().map(|(&addr, name)| (addr, name.as_str()))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SymbolError {
+    // 1-based.
+    pub line: usize,
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid symbol definition on line {}", self.line)
+    }
+}
+
+impl std::error::Error for SymbolError {}
+
+impl FromStr for SymbolTable {
+    type Err = SymbolError;
+
+    fn from_str(text: &str) -> Result<Self, SymbolError> {
+        let mut table = Self::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = SymbolError { line: i + 1 };
+            let (name, addr) = line.split_once('=').ok_or(error)?;
+            let name = name.trim();
+            if !is_identifier(name) {
+                return Err(error);
+            }
+            table.insert(addr.trim().parse().map_err(|_| error)?, name);
+        }
+        Ok(table)
+    }
+}
+
+// Names must be usable in watch expressions.
+pub(crate) fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !matches!(name, "ip" | "rb" | "mem")
+}
+
+impl IntcodeComputer {
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = Some(Arc::new(symbols));
+    }
+
+    pub fn clear_symbols(&mut self) {
+        self.symbols = None;
+    }
+
+    pub fn symbols(&self) -> Option<&SymbolTable> {
+        self.symbols.as_deref()
+    }
+
+    // The error's message, saying where in the program it happened if the
+    // symbols allow it.
+    pub fn describe_error(&self, error: &IntcodeError) -> String {
+        let location = error.ip().zip(self.symbols()).and_then(|(ip, symbols)| symbols.locate(ip));
+        match location {
+            Some(location) => format!("{error} ({location})"),
+            None => error.to_string(),
+        }
+    }
+}
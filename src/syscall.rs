@@ -0,0 +1,55 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{Effect, IntcodeComputer, IntcodeError, Int};
+
+// Opt-in trap to the host. The SYS instruction takes one parameter, the
+// address of an argument block laid out as:
+//
+//     [number, n_args, arg_1, ..., arg_n, result]
+//
+// The host's handler is called with the syscall number and arguments, and
+// whatever it returns is stored in the result cell, as a write by the SYS
+// instruction (so observers and stats see it). Handlers also get the
+// computer, so services like reading a file can fill buffers in memory.
+
+pub const SYS: u8 = 20;
+
+// Upper bound on `n_args`, so a corrupt block can't make the computer read
+// huge amounts of memory.
+pub const MAX_SYSCALL_ARGS: Int = 16;
+
+pub trait SyscallHandler: Send {
+    fn call(&mut self, comp: &mut IntcodeComputer, number: Int, args: &[Int]) -> Result<Int, IntcodeError>;
+}
+
+impl<F: FnMut(&mut IntcodeComputer, Int, &[Int]) -> Result<Int, IntcodeError> + Send> SyscallHandler for F {
+    fn call(&mut self, comp: &mut IntcodeComputer, number: Int, args: &[Int]) -> Result<Int, IntcodeError> {
+        self(comp, number, args)
+    }
+}
+
+impl IntcodeComputer {
+    // Enables the SYS instruction. Copies of the computer made afterwards
+    // share the same handler.
+    pub fn set_syscall_handler(&mut self, handler: impl SyscallHandler + 'static) {
+        let handler = Arc::new(Mutex::new(handler));
+
+        self.register_opcode(SYS, 1, move |comp, ops| {
+            let block = ops[0].value;
+            let ip = comp.ip();
+            let overflow = || IntcodeError::Overflow { ip };
+            let n_args = comp.read_at(block.checked_add(1).ok_or_else(overflow)?);
+            if !(0..=MAX_SYSCALL_ARGS).contains(&n_args) {
+                return Err(IntcodeError::Handler { ip, reason: "invalid syscall argument count" });
+            }
+
+            // The result cell is the last one, so the others can't overflow
+            let result_addr = block.checked_add(2 + n_args).ok_or_else(overflow)?;
+            let number = comp.read_at(block);
+            let args: Vec<Int> = (0..n_args).map(|i| comp.read_at(block + 2 + i)).collect();
+            let result = handler.lock().unwrap().call(comp, number, &args)?;
+            comp.write_mem(result_addr, result);
+            Ok(Effect::Next)
+        });
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+
+use crate::{Event, IntcodeComputer, IntcodeError, Int, RunResult};
+use crate::intcode::Opcodes;
+use crate::hash::HashMap;
+
+// Taint tracking: runs a computer while keeping, for every memory cell and
+// every output, the set of inputs its value was computed from. Inputs are
+// identified by the order in which the program read them.
+//
+// Only data flow is tracked. A value chosen by a jump on a tainted condition,
+// or read through a tainted relative base, doesn't inherit that taint.
+// Custom instructions could compute anything, so whatever they write is
+// taken to depend on every input read so far.
+
+pub type Taint = BTreeSet<usize>;
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct TaintReport {
+    // Inputs read so far, in order.
+    pub inputs: Vec<Int>,
+    // Every output, with the inputs it depends on.
+    pub outputs: Vec<(Int, Taint)>,
+    // Cells currently holding input-derived values.
+    pub memory: BTreeMap<Int, Taint>,
+}
+
+pub struct TaintTracker {
+    comp: IntcodeComputer,
+    cells: HashMap<Int, Taint>,
+    report: TaintReport,
+    // Cells written by the instruction being run.
+    writes: Arc<Mutex<Vec<Int>>>,
+}
+
+impl TaintTracker {
+    pub fn new(mut comp: IntcodeComputer) -> Self {
+        let writes = Arc::new(Mutex::new(vec![]));
+        let shared = writes.clone();
+        comp.add_observer(move |event: &Event| if let &Event::MemoryWritten { addr, .. } = event {
+            shared.lock().unwrap().push(addr);
+        });
+        Self { comp, cells: HashMap::default(), report: TaintReport::default(), writes }
+    }
+
+    pub fn input(&mut self, value: Int) {
+        self.comp.input(value);
+    }
+
+    pub fn computer(&self) -> &IntcodeComputer {
+        &self.comp
+    }
+
+    // Same as `IntcodeComputer::try_run`, tracking taint along the way.
+    pub fn run(&mut self) -> Result<RunResult, IntcodeError> {
+        while !self.comp.is_finished() {
+            let (opcode, addrs) = self.comp.param_addrs()?;
+            let input = self.comp.next_input();
+            self.writes.lock().unwrap().clear();
+            let ret = self.comp.exec_next()?;
+
+            match opcode {
+                Opcodes::ADD | Opcodes::MUL | Opcodes::LT | Opcodes::EQ => self.binary_op(&addrs),
+                #[cfg(feature = "extensions")]
+                Opcodes::DIV | Opcodes::MOD | Opcodes::FMUL | Opcodes::FDIV => self.binary_op(&addrs),
+                Opcodes::IN => {
+                    self.set_taint(addrs[0], Taint::from([self.report.inputs.len()]));
+                    self.report.inputs.extend(input);
+                },
+                Opcodes::OUT | Opcodes::JMP | Opcodes::JMN | Opcodes::RLB | Opcodes::END => {},
+                _ => {
+                    let taint: Taint = (0..self.report.inputs.len()).collect();
+                    let writes = std::mem::take(&mut *self.writes.lock().unwrap());
+                    for addr in writes {
+                        self.set_taint(addr, taint.clone());
+                    }
+                },
+            }
+
+            if let Some(RunResult::Output(val)) = ret {
+                self.report.outputs.push((val, self.taint_of(addrs[0])));
+                return Ok(RunResult::Output(val));
+            }
+        }
+
+        Ok(RunResult::Finished)
+    }
+
+    // Inputs the value in a cell depends on.
+    pub fn taint_of(&self, addr: Int) -> Taint {
+        self.cells.get(&addr).cloned().unwrap_or_default()
+    }
+
+    pub fn report(&self) -> TaintReport {
+        let memory = self.cells.iter().map(|(&addr, taint)| (addr, taint.clone())).collect();
+        TaintReport { memory, ..self.report.clone() }
+    }
+
+    // Instructions writing a value computed from their first two parameters
+    // to the third one.
+    fn binary_op(&mut self, addrs: &[Int]) {
+        let taint = self.taint_of(addrs[0]).union(&self.taint_of(addrs[1])).copied().collect();
+        self.set_taint(addrs[2], taint);
+    }
+
+    fn set_taint(&mut self, addr: Int, taint: Taint) {
+        if taint.is_empty() {
+            self.cells.remove(&addr);
+        } else {
+            self.cells.insert(addr, taint);
+        }
+    }
+}
+
+// Runs until the program finishes or fails, with the given inputs.
+pub fn trace_taint(comp: IntcodeComputer, inputs: &[Int]) -> Result<TaintReport, IntcodeError> {
+    let mut tracker = TaintTracker::new(comp);
+    for &value in inputs {
+        tracker.input(value);
+    }
+    while tracker.run()? != RunResult::Finished {}
+    Ok(tracker.report())
+}
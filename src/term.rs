@@ -0,0 +1,117 @@
+use std::io::{self, Stdout, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::Int;
+
+// In-place terminal rendering of grid-based outputs, using plain ANSI escape
+// sequences. Day 13 style `(x, y, tile)` triplets update single cells, while
+// day 11 style panel maps are drawn whole. The score triplet `(-1, 0, score)`
+// is shown on the first line, so the grid starts right below it.
+
+const CLEAR: &str = "\x1b[2J\x1b[H";
+const HIDE_CURSOR: &str = "\x1b[?25l";
+const SHOW_CURSOR: &str = "\x1b[?25h";
+
+pub struct Renderer<W: Write = Stdout> {
+    out: W,
+    glyph: fn(Int) -> char,
+    frame_interval: Option<Duration>,
+    last_frame: Option<Instant>,
+    max_row: Int,
+}
+
+// Default glyphs for the arcade tiles (and painted panels: 0 black, 1 white).
+pub fn arcade_glyph(tile: Int) -> char {
+    match tile {
+        0 => ' ',
+        1 => '█',
+        2 => '▒',
+        3 => '▔',
+        4 => '●',
+        _ => '?',
+    }
+}
+
+impl Renderer<Stdout> {
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+}
+
+impl<W: Write> Renderer<W> {
+    pub fn new(out: W) -> Self {
+        Self { out, glyph: arcade_glyph, frame_interval: None, last_frame: None, max_row: 0 }
+    }
+
+    pub fn with_glyphs(mut self, glyph: fn(Int) -> char) -> Self {
+        self.glyph = glyph;
+        self
+    }
+
+    // Caps the number of frames per second shown by `frame`.
+    pub fn with_fps(mut self, fps: u32) -> Self {
+        self.frame_interval = (fps > 0).then(|| Duration::from_secs(1) / fps);
+        self
+    }
+
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.max_row = 0;
+        write!(self.out, "{HIDE_CURSOR}{CLEAR}")
+    }
+
+    pub fn draw_tile(&mut self, x: Int, y: Int, tile: Int) -> io::Result<()> {
+        if (x, y) == (-1, 0) {
+            return write!(self.out, "\x1b[1;1H\x1b[2KScore: {tile}");
+        }
+        if x < 0 || y < 0 {
+            return Ok(());
+        }
+
+        self.max_row = self.max_row.max(y + 1);
+        write!(self.out, "\x1b[{};{}H{}", y + 2, x + 1, (self.glyph)(tile))
+    }
+
+    pub fn draw_triplets(&mut self, outputs: &[Int]) -> io::Result<()> {
+        for chunk in outputs.chunks_exact(3) {
+            self.draw_tile(chunk[0], chunk[1], chunk[2])?;
+        }
+        Ok(())
+    }
+
+    // Draws a whole map of panels, shifted so that its top-left corner is at
+    // the top-left of the screen. Panels missing from the map are blank.
+    pub fn draw_panels(&mut self, panels: impl IntoIterator<Item = ((Int, Int), Int)>) -> io::Result<()> {
+        let panels: Vec<((Int, Int), Int)> = panels.into_iter().collect();
+        let min_x = panels.iter().map(|((x, _), _)| *x).min().unwrap_or_default();
+        let min_y = panels.iter().map(|((_, y), _)| *y).min().unwrap_or_default();
+
+        self.clear()?;
+        for ((x, y), tile) in panels {
+            self.draw_tile(x - min_x, y - min_y, tile)?;
+        }
+        Ok(())
+    }
+
+    // Shows everything drawn since the last frame, waiting if needed to
+    // respect the frame rate.
+    pub fn frame(&mut self) -> io::Result<()> {
+        if let (Some(interval), Some(last)) = (self.frame_interval, self.last_frame) {
+            if let Some(wait) = interval.checked_sub(last.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
+        self.last_frame = Some(Instant::now());
+        self.out.flush()
+    }
+
+    // Leaves the cursor below the drawing, visible again.
+    pub fn finish(&mut self) -> io::Result<()> {
+        write!(self.out, "\x1b[{};1H{SHOW_CURSOR}", self.max_row + 2)?;
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
@@ -1,7 +1,8 @@
 use core::panic;
 use std::fs::read_to_string;
 
-use crate::{IntcodeComputer, Int, RunResult};
+use crate::{AsciiOutput, AsciiStop, NonAsciiOutput, conformance, fuzz, trace, watch, IntcodeBuilder, IntcodeComputer, IntcodeError, Int, Interpreter, RunResult};
+use crate::generate::{ProgramGenerator, Rng};
 
 fn load_input(filename: &str) -> String {
     read_to_string(format!("test_inputs/{filename}")).unwrap()
@@ -24,10 +25,26 @@ fn test_day2() {
 
     // Part 1
     let code = load_input("d2.txt");
-    assert_position_after_running(0, &code.replace("0,0", "12,2"), 3850704);
+    let comp = IntcodeComputer::from(&code);
+    assert_eq!(comp.run_with_noun_verb(12, 2), 3850704);
 
     // Part 2
-    assert_position_after_running(0, &code.replace("0,0", "67,18"), 19690720);
+    assert_eq!(comp.find_noun_verb(19690720), Some((67, 18)));
+    assert_eq!(comp.par_find_noun_verb(19690720), Some((67, 18)));
+    assert_eq!(comp.find_noun_verb(-1), None);
+
+    // Adds the noun and the verb, so there are many answers
+    let adder = IntcodeComputer::from("1101,0,0,0,99");
+    assert_eq!(adder.par_find_noun_verb(5), Some((0, 5)));
+    assert_eq!(adder.par_find_noun_verb(150), Some((51, 99)));
+    assert_eq!(adder.par_find_noun_verb(-1), None);
+
+    // Patching the noun and verb directly in memory
+    let mut comp = IntcodeComputer::from(&code);
+    comp.write_at(1, 12);
+    comp.write_at(2, 2);
+    assert_eq!(comp.run(), RunResult::Finished);
+    assert_eq!(comp.read_at(0), 3850704);
 }
 
 #[test]
@@ -61,11 +78,15 @@ fn test_day5() {
     }
     assert_eq!(comp.run(), RunResult::Finished);
 
+    let outputs = IntcodeComputer::from(&code).run_with_inputs(&[1]);
+    assert_eq!(outputs.split_last(), Some((&14155342, &[0; 9][..])));
+
     // Part 2
     let mut comp = IntcodeComputer::from(&code);
     comp.input(5);
     assert_eq!(comp.run(), RunResult::Output(8684145));
     assert_eq!(comp.run(), RunResult::Finished);
+    assert_eq!(IntcodeComputer::from(&code).run_with_inputs(&[5]), [8684145]);
 }
 
 #[test]
@@ -103,3 +124,2745 @@ fn test_day9() {
     assert_eq!(comp.run(), RunResult::Output(90722));
     assert_eq!(comp.run(), RunResult::Finished);
 }
+
+#[test]
+fn test_conformance() {
+    if let Err(failures) = conformance::run_all(&Interpreter) {
+        let report: Vec<String> = failures.iter().map(|f| f.to_string()).collect();
+        panic!("Conformance failures:\n{}", report.join("\n"));
+    }
+}
+
+#[test]
+fn test_generated_programs() {
+    let generator = ProgramGenerator::default();
+    for program in generator.programs(0xC0FFEE).take(2_000) {
+        let mut comp = IntcodeComputer::new(&program.code);
+        for &val in &program.inputs {
+            comp.input(val);
+        }
+        while comp.run() != RunResult::Finished {}
+    }
+
+    // Same seed, same programs
+    let a: Vec<_> = generator.programs(42).take(10).collect();
+    let b: Vec<_> = generator.programs(42).take(10).collect();
+    assert_eq!(a, b);
+}
+
+#[test]
+fn test_errors() {
+    let run = |code: &str| IntcodeComputer::from(code).try_run();
+    assert_eq!(run("1,0,0,0,42"), Err(IntcodeError::UnknownOpcode { ip: 4, opcode: 42 }));
+    assert_eq!(run("-1"), Err(IntcodeError::UnknownOpcode { ip: 0, opcode: -1 }));
+    assert_eq!(run("301,0,0,0,99"), Err(IntcodeError::UnknownParamMode { ip: 0, mode: 3 }));
+    assert_eq!(run("11101,0,0,0,99"), Err(IntcodeError::ImmediateWrite { ip: 0 }));
+    assert_eq!(run("3,0,99"), Err(IntcodeError::NoInput { ip: 0 }));
+    assert_eq!(run("2,7,7,7,1105,1,0,2"), Err(IntcodeError::Overflow { ip: 0 }));
+
+    // Inputs can be provided after the computer stopped asking for them
+    let mut comp = IntcodeComputer::from("3,0,4,0,99");
+    assert_eq!(comp.try_run(), Err(IntcodeError::NoInput { ip: 0 }));
+    comp.input(7);
+    assert_eq!(comp.try_run(), Ok(RunResult::Output(7)));
+    assert_eq!(comp.try_run(), Ok(RunResult::Finished));
+
+    // Step limits
+    let mut comp = IntcodeComputer::from("1105,1,0");
+    comp.set_step_limit(Some(1_000));
+    assert_eq!(comp.try_run(), Err(IntcodeError::StepLimit { steps: 1_000 }));
+}
+
+#[test]
+fn test_custom_opcodes() {
+    use crate::{Effect, Operand};
+
+    // A native DIV as opcode 30, and an opcode 31 that outputs its parameter
+    // twice by jumping back to itself once
+    let div = |comp: &mut IntcodeComputer, ops: &[Operand]| {
+        if ops[1].value == 0 {
+            return Err(IntcodeError::Handler { ip: comp.ip(), reason: "division by zero" });
+        }
+        comp.write_operand(&ops[2], ops[0].value / ops[1].value)?;
+        Ok(Effect::Next)
+    };
+    let mut comp = IntcodeComputer::from("3,100,1030,100,7,101,4,101,99");
+    comp.register_opcode(30, 3, div);
+    let mut zero = comp.clone();
+    comp.input(50);
+    assert_eq!(comp.run(), RunResult::Output(7));
+
+    // Clones share the handlers, and errors come from them
+    let mut comp = IntcodeComputer::from("3,100,30,100,101,101,99");
+    comp.register_opcode(30, 3, div);
+    comp.input(0);
+    zero.input(3);
+    assert_eq!(zero.try_run(), Ok(RunResult::Output(0)));
+    assert_eq!(comp.try_run(), Err(IntcodeError::Handler { ip: 2, reason: "division by zero" }));
+
+    let mut comp = IntcodeComputer::from("131,5,99");
+    comp.register_opcode(31, 1, |comp, ops| {
+        comp.write_at(10, comp.read_at(10) + 1);
+        Ok(if comp.read_at(10) == 1 { Effect::Jump(0) } else { Effect::Output(ops[0].value) })
+    });
+    assert_eq!(comp.run(), RunResult::Output(5));
+    assert_eq!(comp.read_at(10), 2);
+    assert_eq!(comp.run(), RunResult::Finished);
+
+    // Immediate parameters can't be written to
+    let mut comp = IntcodeComputer::from("132,1,99");
+    comp.register_opcode(32, 1, |comp, ops| comp.write_operand(&ops[0], 0).map(|_| Effect::Halt));
+    assert_eq!(comp.try_run(), Err(IntcodeError::ImmediateWrite { ip: 0 }));
+    assert_eq!(IntcodeComputer::from("33,99").try_run(), Err(IntcodeError::UnknownOpcode { ip: 0, opcode: 33 }));
+}
+
+#[test]
+fn test_syscalls() {
+    // Syscall 1 adds its arguments, syscall 2 fills memory from address 200
+    // with a greeting and returns its length
+    let handler = |comp: &mut IntcodeComputer, number: Int, args: &[Int]| match number {
+        1 => Ok(args.iter().sum()),
+        2 => {
+            for (i, b) in b"hi".iter().enumerate() {
+                comp.write_at(200 + i as Int, *b as Int);
+            }
+            Ok(2)
+        },
+        _ => Err(IntcodeError::Handler { ip: comp.ip(), reason: "unknown syscall" }),
+    };
+
+    // Block at 100: [1, 3, 4, 5, 6, result]; block at 110: [2, 0, result]
+    let mut comp = IntcodeComputer::builder(&[120, 100, 120, 110, 4, 105, 4, 112, 4, 200, 99])
+        .patch(100, 1).patch(101, 3).patch(102, 4).patch(103, 5).patch(104, 6)
+        .patch(110, 2)
+        .build();
+    comp.set_syscall_handler(handler);
+    let mut outputs = vec![];
+    while let RunResult::Output(val) = comp.run() {
+        outputs.push(val);
+    }
+    assert_eq!(outputs, [15, 2, b'h' as Int]);
+
+    let mut comp = IntcodeComputer::builder(&[120, 100, 99]).patch(100, 7).build();
+    comp.set_syscall_handler(handler);
+    assert_eq!(comp.try_run(), Err(IntcodeError::Handler { ip: 0, reason: "unknown syscall" }));
+    let mut comp = IntcodeComputer::builder(&[120, 100, 99]).patch(101, -1).build();
+    comp.set_syscall_handler(handler);
+    assert!(matches!(comp.try_run(), Err(IntcodeError::Handler { .. })));
+    // A block at the end of the address space
+    let mut comp = IntcodeComputer::new(&[120, Int::MAX, 99]);
+    comp.set_syscall_handler(handler);
+    assert_eq!(comp.try_run(), Err(IntcodeError::Overflow { ip: 0 }));
+
+    // Results are written like by any other instruction. The block at 0 puts
+    // the result over a parameter of the first instruction
+    use crate::{CodeWrite, CodeWriteMode, Event};
+    let mut comp = IntcodeComputer::new(&[1101, 0, 0, 20, 120, 0, 99]);
+    comp.set_syscall_handler(|_: &mut IntcodeComputer, _: Int, _: &[Int]| Ok(7));
+    comp.set_code_write_mode(CodeWriteMode::Record);
+    let events = comp.add_observer(Vec::<Event>::new());
+    assert_eq!(comp.run(), RunResult::Finished);
+    assert!(events.lock().unwrap().contains(&Event::MemoryWritten { ip: 4, addr: 2, old: 0, new: 7 }));
+    assert_eq!(comp.code_writes(), [CodeWrite { ip: 4, addr: 2 }]);
+}
+
+#[test]
+fn test_devices() {
+    use std::sync::{Arc, Mutex};
+    use crate::Device;
+
+    #[derive(Default)]
+    struct Keyboard(Vec<Int>);
+    impl Device for Keyboard {
+        fn read(&mut self, _offset: Int) -> Int {
+            if self.0.is_empty() { 0 } else { self.0.remove(0) }
+        }
+        fn write(&mut self, _offset: Int, _value: Int) {}
+    }
+
+    // Remembers every write, with its offset
+    #[derive(Default)]
+    struct Screen(Vec<(Int, Int)>);
+    impl Device for Screen {
+        fn read(&mut self, _offset: Int) -> Int {
+            self.0.len() as Int
+        }
+        fn write(&mut self, offset: Int, value: Int) {
+            self.0.push((offset, value));
+        }
+        fn peek(&self, _offset: Int) -> Int {
+            self.0.len() as Int
+        }
+    }
+
+    // Copies two keys to the screen, then outputs the number of writes
+    let mut comp = IntcodeComputer::from("1001,1000,0,2000,1001,1000,0,2001,4,2005,99");
+    let keyboard = comp.attach_device(1000, 1, Keyboard(vec![72, 73]));
+    let screen = comp.attach_device(2000, 10, Screen::default());
+    assert_eq!(comp.run(), RunResult::Output(2));
+    assert_eq!(screen.lock().unwrap().0, [(0, 72), (1, 73)]);
+    assert!(keyboard.lock().unwrap().0.is_empty());
+
+    // Host accesses go through the devices too, but indexing doesn't
+    comp.write_at(2009, 5);
+    assert_eq!(screen.lock().unwrap().0.last(), Some(&(9, 5)));
+    assert_eq!(comp.read_at(1000), 0);
+    assert_eq!(comp[2009], 0);
+
+    // Observers see what the devices held, without reading them
+    let mut comp = IntcodeComputer::from("1001,1000,0,2000,99");
+    comp[2000] = 5;
+    comp.attach_device(1000, 1, Keyboard(vec![72]));
+    comp.attach_device(2000, 1, Screen(vec![(0, 1)]));
+    let writes = Arc::new(Mutex::new(vec![]));
+    let seen = writes.clone();
+    comp.on_memory_write(move |addr, old, new, _| seen.lock().unwrap().push((addr, old, new)));
+    comp.run();
+    assert_eq!(*writes.lock().unwrap(), [(2000, 1, 72)]);
+    assert_eq!(comp.peek_at(1000), 0);
+    assert_eq!(comp.peek_at(2000), 2);
+}
+
+#[test]
+fn test_rng_device() {
+    use crate::RngDevice;
+
+    // Sets the bound to 6, then outputs three dice rolls
+    let code = "1101,6,0,1001,4,1001,4,1001,4,1001,99";
+    let rolls = |seed| {
+        let mut comp = IntcodeComputer::from(code);
+        comp.attach_device(1000, RngDevice::LEN, RngDevice::new(seed));
+        (0..3).map(|_| match comp.run() {
+            RunResult::Output(val) => val,
+            RunResult::Finished => panic!("Missing roll"),
+        }).collect::<Vec<_>>()
+    };
+    assert_eq!(rolls(42), rolls(42));
+    assert!(rolls(42).iter().all(|r| (0..6).contains(r)));
+    assert!((0..10).any(|seed| rolls(seed) != rolls(42)));
+
+    // Writing to the first address reseeds
+    let mut comp = IntcodeComputer::from("99");
+    comp.attach_device(0, RngDevice::LEN, RngDevice::new(7));
+    let first = comp.read_at(0);
+    comp.read_at(0);
+    comp.write_at(0, 7);
+    assert_eq!(comp.read_at(0), first);
+    assert!(first >= 0);
+}
+
+#[test]
+fn test_clock_device() {
+    use std::time::Duration;
+    use crate::{ClockDevice, FakeClock, SystemClock};
+
+    // Outputs the time elapsed between two readings
+    let code = "1001,1000,0,100,1002,100,-1,100,1,1000,100,100,4,100,99";
+    let mut comp = IntcodeComputer::from(code);
+    let clock = FakeClock { tick: Duration::from_millis(3), ..Default::default() };
+    let device = comp.attach_device(1000, 2, ClockDevice::new(clock));
+    assert_eq!(comp.run(), RunResult::Output(3000));
+
+    device.lock().unwrap().clock_mut().unix_time = Duration::from_secs(1_000_000);
+    assert_eq!(comp.read_at(1001), 1_000_000);
+
+    // The real clock is monotonic
+    let mut comp = IntcodeComputer::from("99");
+    comp.attach_device(0, 2, ClockDevice::new(SystemClock::default()));
+    let first = comp.read_at(0);
+    assert!(comp.read_at(0) >= first);
+    assert!(comp.read_at(1) > 1_600_000_000);
+}
+
+#[test]
+fn test_storage_device() {
+    use crate::StorageDevice;
+
+    let path = std::env::temp_dir().join(format!("intcode-storage-{}", std::process::id()));
+    let outputs = |code: &str, device: StorageDevice| {
+        let mut comp = IntcodeComputer::from(code);
+        comp.attach_device(1000, StorageDevice::LEN, device);
+        let mut outputs = vec![];
+        while let RunResult::Output(val) = comp.run() {
+            outputs.push(val);
+        }
+        outputs
+    };
+
+    // Stores 42 at the start of block 3
+    let store = "1101,3,0,1000,1101,42,0,1002,1101,2,0,1001,4,1001,99";
+    assert_eq!(outputs(store, StorageDevice::open(&path, 4).unwrap()), [0]);
+    // Blocks past the cap are rejected
+    assert_eq!(outputs(store, StorageDevice::open(&path, 3).unwrap()), [-1]);
+
+    // Loads it back, then tries to store it again
+    let load = "1101,3,0,1000,1101,1,0,1001,4,1002,1101,2,0,1001,4,1001,99";
+    assert_eq!(outputs(load, StorageDevice::open_read_only(&path, 4).unwrap()), [42, -1]);
+    assert_eq!(outputs(load, StorageDevice::open(&path, 4).unwrap()), [42, 0]);
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 4 * 64 * 16);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_input_interrupts() {
+    // Spins until the handler (at 10) sets a flag, then outputs the input it got
+    let code = "1001,100,1,100,1006,101,0,4,102,99,3,102,1101,1,0,101,109,50,106,0,200";
+    let mut comp = IntcodeComputer::from(code);
+    comp.set_input_interrupt(10, 200);
+    comp.set_step_limit(Some(100));
+    assert!(matches!(comp.try_run(), Err(IntcodeError::StepLimit { .. })));
+    assert!(!comp.in_interrupt());
+
+    // Saving the registers is seen like any other write
+    let events = comp.add_observer(Vec::<crate::Event>::new());
+    comp.input(7);
+    comp.set_step_limit(Some(101));
+    assert!(matches!(comp.try_run(), Err(IntcodeError::StepLimit { .. })));
+    assert!(comp.in_interrupt());
+    assert!(matches!(comp.read_at(200), 0 | 4));
+    let saved: Vec<Int> = events.lock().unwrap().iter().filter_map(|event| match *event {
+        crate::Event::MemoryWritten { addr, .. } if addr >= 200 => Some(addr),
+        _ => None,
+    }).collect();
+    assert_eq!(saved, [200, 201]);
+
+    comp.set_step_limit(None);
+    assert_eq!(comp.run(), RunResult::Output(7));
+    assert!(!comp.in_interrupt());
+    assert_eq!(comp.relative_base(), 0);
+    assert!(comp.read_at(100) >= 50);
+
+    assert!(std::panic::catch_unwind(|| IntcodeComputer::from("99").set_input_interrupt(0, Int::MAX)).is_err());
+}
+
+#[cfg(feature = "extensions")]
+#[test]
+fn test_div_mod() {
+    // Outputs a / b and a % b
+    let div_mod = |a: Int, b: Int| {
+        let mut comp = IntcodeComputer::from("3,100,3,101,10,100,101,102,4,102,11,100,101,102,4,102,99");
+        comp.input_iter([a, b]);
+        (comp.try_run(), comp.try_run())
+    };
+    assert_eq!(div_mod(17, 5), (Ok(RunResult::Output(3)), Ok(RunResult::Output(2))));
+    assert_eq!(div_mod(-17, 5), (Ok(RunResult::Output(-3)), Ok(RunResult::Output(-2))));
+    assert_eq!(div_mod(17, 0).0, Err(IntcodeError::DivisionByZero { ip: 4 }));
+    assert_eq!(div_mod(Int::MIN, -1).0, Err(IntcodeError::Overflow { ip: 4 }));
+
+    let traced = trace::Trace::record(&mut IntcodeComputer::from("1110,7,2,5,99"));
+    assert!(traced.to_text().starts_with("0: DIV 1110,7,2,5\n"));
+}
+
+#[cfg(feature = "extensions")]
+#[test]
+fn test_fixed_point() {
+    use crate::FIXED_POINT_BITS;
+
+    // Outputs a * b and a / b, in fixed point
+    let fmul_fdiv = |a: f64, b: f64| {
+        let one = (1 << FIXED_POINT_BITS) as f64;
+        let mut comp = IntcodeComputer::from("3,100,3,101,12,100,101,102,4,102,13,100,101,102,4,102,99");
+        comp.input_iter([(a * one) as Int, (b * one) as Int]);
+        let mut next = || match comp.run() {
+            RunResult::Output(val) => val as f64 / one,
+            RunResult::Finished => panic!("Missing output"),
+        };
+        (next(), next())
+    };
+    assert_eq!(fmul_fdiv(1.5, 2.25), (3.375, 43690.0 / 65536.0));
+    assert_eq!(fmul_fdiv(-1.5, 0.5), (-0.75, -3.0));
+    // Results round down
+    assert_eq!(fmul_fdiv(-1.0, 3.0).1, -21846.0 / 65536.0);
+}
+
+#[test]
+fn test_display() {
+    let mut comp = IntcodeComputer::from("1,9,10,3,2,3,11,0,99,30,40,50");
+    comp.input_iter([5, 6]);
+    assert_eq!(comp.to_string(), "\
+ip: 0, relative base: 0, finished: false
+inputs: [5, 6]
+> 0: ADD [9], [10], [3]
+  4: MUL [3], [11], [0]
+  8: END");
+
+    // Custom opcodes and data after the code
+    let mut comp = IntcodeComputer::from("109,-3,1201,2,7,5,104,1,30,1,2,3");
+    comp.register_opcode(30, 0, |_, _| Ok(crate::Effect::Next));
+    comp.run();
+    assert_eq!(comp.to_string(), "\
+ip: 8, relative base: -3, finished: false
+inputs: []
+> 8: OP30
+  9: ADD [2], [3], [0]
+  13: 0");
+
+    // Devices under the code are peeked at, not read
+    #[derive(Default)]
+    struct Counter(Int);
+    impl crate::Device for Counter {
+        fn read(&mut self, _offset: Int) -> Int {
+            self.0 += 1;
+            self.0
+        }
+        fn write(&mut self, _offset: Int, _value: Int) {}
+    }
+    let mut comp = IntcodeComputer::from("1101,0,0,0,99");
+    let counter = comp.attach_device(1, 2, Counter::default());
+    assert!(comp.to_string().ends_with("> 0: ADD 0, 0, [0]\n  4: END"));
+    assert_eq!(counter.lock().unwrap().0, 0);
+    assert_eq!(comp.run(), RunResult::Finished);
+    assert_eq!(comp[0], 3);
+}
+
+#[test]
+fn test_debug() {
+    let mut comp = IntcodeComputer::from("1,0,0,5,99,0,0,1,2,3");
+    comp.run();
+    comp.input(4);
+    assert_eq!(format!("{comp:?}"), "IntcodeComputer { ip: 5, rel_base: 0, is_finished: true, steps: 2, \
+        input_queue: [4], memory: {0: 1, 3: 5, 4: 99, 5: 2, 7: 1, 8: 2, 9: 3}, .. }");
+    assert_eq!(format!("{:?}", comp.debug_with(2)), "IntcodeComputer { ip: 5, rel_base: 0, is_finished: true, steps: 2, \
+        input_queue: [4], memory: {0: 1, 3: 5, ..}, .. }");
+}
+
+#[test]
+fn test_equality() {
+    // Cells that were never set count as zero
+    let mut a = IntcodeComputer::from("1101,2,3,10,99");
+    let mut b = IntcodeComputer::from("1101,2,3,10,99");
+    b.write_at(11, 0);
+    assert_eq!(a, b);
+
+    a.run();
+    assert_ne!(a, b);
+    b.run();
+    assert_eq!(a, b);
+    b.write_at(10, 6);
+    assert_ne!(a, b);
+    b.write_at(10, 5);
+
+    // Pending inputs are part of the state
+    a.input(1);
+    assert_ne!(a, b);
+    b.input(1);
+    assert_eq!(a, b.clone());
+}
+
+#[test]
+fn test_stop_reasons() {
+    use crate::StopReason;
+
+    // Doubles every input, forever
+    let mut comp = IntcodeComputer::from("3,100,1002,100,2,100,4,100,1105,1,0");
+    comp.add_breakpoint(6);
+    comp.input(4);
+    assert_eq!(comp.run_until_stop(), StopReason::Breakpoint { ip: 6 });
+    assert_eq!(comp.run_until_stop(), StopReason::Output(8));
+    assert_eq!(comp.run_until_stop(), StopReason::NeedsInput { ip: 0 });
+    assert_eq!(comp.run_until_stop().to_string(), "Waiting for input at 0");
+
+    comp.remove_breakpoint(6);
+    comp.input(5);
+    assert_eq!(comp.run_until_stop(), StopReason::Output(10));
+    comp.set_step_limit(Some(comp.steps() + 1));
+    assert_eq!(comp.run_until_stop().to_string(), "Step limit reached after 8 steps");
+
+    // Breaking on every I/O instruction
+    let mut comp = IntcodeComputer::from("3,100,1002,100,2,100,4,100,1105,1,0");
+    comp.add_opcode_breakpoint(3);
+    comp.add_opcode_breakpoint(4);
+    comp.input(1);
+    assert_eq!(comp.run_until_stop(), StopReason::Breakpoint { ip: 6 });
+    assert_eq!(comp.run_until_stop(), StopReason::Output(2));
+    assert_eq!(comp.run_until_stop(), StopReason::Breakpoint { ip: 0 });
+    comp.remove_opcode_breakpoint(4);
+    comp.input(2);
+    assert_eq!(comp.run_until_stop(), StopReason::Output(4));
+    assert_eq!(comp.run_until_stop(), StopReason::Breakpoint { ip: 0 });
+
+    let mut comp = IntcodeComputer::from("1101,0,0,4,99");
+    assert_eq!(comp.run_until_stop(), StopReason::Error(IntcodeError::UnknownOpcode { ip: 4, opcode: 0 }));
+    let mut comp = IntcodeComputer::from("99");
+    assert_eq!(comp.run_until_stop(), StopReason::Finished);
+}
+
+#[test]
+fn test_buffered_output() {
+    // Outputs three triplets, asking for input after the second one
+    let code = "104,1,104,2,104,3,104,4,104,5,104,6,3,100,4,100,104,8,104,9,99";
+    let mut comp = IntcodeBuilder::from(code).buffered_output(true).build();
+    assert_eq!(comp.try_run(), Err(IntcodeError::NoInput { ip: 12 }));
+    assert_eq!(comp.drain_outputs(), [1, 2, 3, 4, 5, 6]);
+    assert_eq!(comp.drain_outputs(), []);
+
+    comp.input(7);
+    assert_eq!(comp.run(), RunResult::Finished);
+    assert_eq!(comp.drain_outputs(), [7, 8, 9]);
+
+    // Back to one output per run
+    let mut comp = IntcodeComputer::from(code);
+    comp.set_buffered_output(true);
+    comp.set_step_limit(Some(1));
+    assert!(comp.try_run().is_err());
+    comp.set_step_limit(None);
+    comp.set_buffered_output(false);
+    assert_eq!(comp.run(), RunResult::Output(2));
+    assert_eq!(comp.drain_outputs(), []);
+}
+
+#[test]
+fn test_output_chunks() {
+    // Outputs a triplet, half of another one before asking for input, and
+    // a lone value
+    let mut comp = IntcodeComputer::from("104,1,104,2,104,3,104,4,3,100,4,100,104,6,104,7,99");
+    assert_eq!(comp.run_until_n_outputs(), Ok(Some([1, 2, 3])));
+    assert_eq!(comp.run_until_n_outputs::<3>(), Err(IntcodeError::NoInput { ip: 8 }));
+    comp.input(5);
+    assert_eq!(comp.run_until_n_outputs(), Ok(Some([4, 5, 6])));
+    assert_eq!(comp.run_until_n_outputs::<3>(), Ok(None));
+}
+
+#[test]
+fn test_machine_pool() {
+    use crate::intcode::parse_code;
+    use crate::pool::MachinePool;
+
+    let program = parse_code("3,100,1002,100,2,101,4,101,99");
+    let pool = MachinePool::new();
+    let results: Vec<_> = (0..10).map(|i| {
+        let mut comp = pool.get(&program);
+        comp.input(i);
+        comp.run()
+    }).collect();
+    assert_eq!(results, (0..10).map(|i| RunResult::Output(2 * i)).collect::<Vec<_>>());
+    assert_eq!(pool.available(), 1);
+
+    // Reused machines start from scratch
+    let mut comp = pool.get(&[4, 101, 99]);
+    assert_eq!(pool.available(), 0);
+    assert_eq!((comp.memory_len(), comp.pending_inputs(), comp.steps()), (3, 0, 0));
+    assert_eq!(comp.run(), RunResult::Output(0));
+    assert_eq!(comp.into_inner().run(), RunResult::Finished);
+    assert_eq!(pool.available(), 0);
+}
+
+#[test]
+fn test_parsing() {
+    use crate::intcode::parse_code;
+
+    assert_eq!(parse_code("1,-2, +3 ,0\n"), [1, -2, 3, 0]);
+    assert_eq!(parse_code(&format!("{},{}", Int::MIN, Int::MAX)), [Int::MIN, Int::MAX]);
+    for bad in ["", "1,,2", "1,2,", "1-2", "-", "3.5", &format!("{}0", Int::MAX)] {
+        assert!(std::panic::catch_unwind(|| parse_code(bad)).is_err(), "{bad:?} was accepted");
+    }
+}
+
+#[test]
+fn test_from_reader() {
+    use std::io::{BufReader, Cursor};
+
+    // A tiny buffer makes values span several reads
+    let reader = BufReader::with_capacity(3, Cursor::new("1101,100,\n-1,4\n\n,0\n"));
+    let mut comp = IntcodeComputer::from_reader(reader).unwrap();
+    assert_eq!(comp.memory_len(), 5);
+    assert_eq!(comp.run(), RunResult::Finished);
+    assert_eq!(comp.read_at(4), 99);
+
+    let code = load_input("d5.txt");
+    let comp = IntcodeComputer::from_reader(BufReader::with_capacity(16, code.as_bytes())).unwrap();
+    assert_eq!(comp, IntcodeComputer::from(&code));
+
+    let err = IntcodeComputer::from_reader(Cursor::new("1,2,x3,4")).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_snapshots() {
+    // Fills 1000 cells with 7s, then 1000 with 3s
+    let code = "1101,0,0,100,21101,0,7,1000,109,1,1001,100,1,100,1007,100,1000,101,1005,101,4,\
+        1101,0,0,100,21101,0,3,1000,109,1,1001,100,1,100,1007,100,1000,101,1005,101,25,99";
+    let mut comp = IntcodeComputer::from(code);
+    comp.set_step_limit(Some(3000));
+    assert!(comp.try_run().is_err());
+    comp.input(5);
+
+    let snapshot = comp.snapshot();
+    assert!(snapshot.size() < 4_000);
+    comp.set_step_limit(None);
+    assert_eq!(comp.run(), RunResult::Finished);
+    assert_eq!(comp.read_at(2999), 3);
+
+    let mut restored = IntcodeComputer::from("99");
+    restored.restore(&snapshot);
+    assert_ne!(restored, comp);
+    assert_eq!((restored.steps(), restored.pending_inputs()), (3000, 1));
+    assert_eq!(restored.run(), RunResult::Finished);
+    assert_eq!(restored.read_at(2999), 3);
+    assert_eq!(restored, comp);
+}
+
+#[test]
+fn test_delta_snapshots() {
+    use std::sync::Arc;
+
+    // Counts up at 100, and toggles 101 between 5 and 0
+    let mut comp = IntcodeComputer::from("1001,100,1,100,1008,101,0,102,1002,102,5,101,1105,1,0");
+    let mut checkpoints = vec![Arc::new(comp.snapshot())];
+    for _ in 0..20 {
+        comp.set_step_limit(Some(comp.steps() + 4));
+        assert!(comp.try_run().is_err());
+        let delta = comp.snapshot_since(checkpoints.last().unwrap());
+        checkpoints.push(Arc::new(delta));
+    }
+    assert!(checkpoints[1..].iter().all(|c| c.size() < checkpoints[0].size()));
+    assert_eq!(comp.read_at(101), 0);
+
+    // Any checkpoint can be restored, going back in time
+    let mut restored = IntcodeComputer::from("99");
+    for (i, checkpoint) in checkpoints.iter().enumerate().rev() {
+        restored.restore(checkpoint);
+        assert_eq!(restored.read_at(100), i as Int);
+        assert_eq!(restored.read_at(101), if i % 2 == 1 { 5 } else { 0 });
+    }
+    restored.restore(checkpoints.last().unwrap());
+    assert_eq!(restored, comp);
+}
+
+#[test]
+fn test_bench_workloads() {
+    use crate::bench;
+
+    for workload in bench::suite(20) {
+        let outputs = workload.computer().run_with_inputs(&[]);
+        assert_eq!(outputs, workload.expected, "{}", workload.name);
+    }
+    assert_eq!(bench::arithmetic_loop(0).computer().run_with_inputs(&[]), [0]);
+    assert_eq!(bench::deep_recursion(0).computer().run_with_inputs(&[]), [0]);
+    assert_eq!(bench::heavy_io(0).computer().run_with_inputs(&[]), []);
+}
+
+#[test]
+fn test_observers() {
+    use crate::Event;
+
+    let mut comp = IntcodeComputer::from("3,9,1001,9,1,9,4,9,99");
+    let events = comp.add_observer(Vec::new());
+    comp.input(10);
+    assert_eq!(comp.run(), RunResult::Output(11));
+    assert_eq!(comp.run(), RunResult::Finished);
+    assert_eq!(*events.lock().unwrap(), [
+        Event::InputConsumed { ip: 0, value: 10 },
+        Event::MemoryWritten { ip: 0, addr: 9, old: 0, new: 10 },
+        Event::InstructionExecuted { ip: 0, opcode: 3 },
+        Event::MemoryWritten { ip: 2, addr: 9, old: 10, new: 11 },
+        Event::InstructionExecuted { ip: 2, opcode: 1 },
+        Event::OutputProduced { ip: 6, value: 11 },
+        Event::InstructionExecuted { ip: 6, opcode: 4 },
+        Event::InstructionExecuted { ip: 8, opcode: 99 },
+        Event::Halted { ip: 8 },
+    ]);
+
+    // Closures work too. Waiting for input isn't an error worth reporting
+    let mut comp = IntcodeComputer::from("3,0,1101,0,0,-1");
+    let (tx, rx) = std::sync::mpsc::channel();
+    comp.add_observer(move |event: &Event| if let Event::Trapped(e) = event {
+        tx.send(*e).unwrap();
+    });
+    assert!(comp.try_run().is_err());
+    comp.input(1);
+    comp.set_strict(true);
+    assert!(comp.try_run().is_err());
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), [IntcodeError::NegativeAddress { ip: 2, addr: -1 }]);
+}
+
+#[test]
+fn test_instruction_hooks() {
+    use std::sync::{Arc, Mutex};
+    use crate::HookAction;
+
+    // Outputs 1, 2 and 3, unless told otherwise
+    let mut comp = IntcodeComputer::from("104,1,104,2,104,3,99");
+    let executed = Arc::new(Mutex::new(vec![]));
+    let log = executed.clone();
+    comp.set_pre_hook(|_, instr| match instr.operands.first() {
+        Some(op) if op.value == 2 => HookAction::Skip,
+        Some(op) if op.value == 3 => HookAction::Abort,
+        _ => HookAction::Continue,
+    });
+    comp.set_post_hook(move |comp, instr| log.lock().unwrap().push((instr.mnemonic(), comp.ip())));
+
+    assert_eq!(comp.try_run(), Ok(RunResult::Output(1)));
+    assert_eq!(comp.try_run(), Err(IntcodeError::Aborted { ip: 4 }));
+    assert_eq!(*executed.lock().unwrap(), [("OUT", 2)]);
+
+    comp.clear_hooks();
+    assert_eq!(comp.run(), RunResult::Output(3));
+    assert_eq!(comp.run(), RunResult::Finished);
+    assert_eq!(executed.lock().unwrap().len(), 1);
+
+    // Hooks don't read devices, even through destinations
+    struct Keyboard(Vec<Int>);
+    impl crate::Device for Keyboard {
+        fn read(&mut self, _offset: Int) -> Int {
+            self.0.pop().unwrap_or_default()
+        }
+        fn write(&mut self, _offset: Int, _value: Int) {}
+    }
+    let mut comp = IntcodeComputer::from("1001,1000,0,1001,4,1001,99");
+    let keyboard = comp.attach_device(1000, 2, Keyboard(vec![8, 7]));
+    comp.set_pre_hook(|_, _| HookAction::Continue);
+    assert_eq!(comp.run(), RunResult::Output(8));
+    assert!(keyboard.lock().unwrap().0.is_empty());
+
+    // Skipped instructions count towards the step limit
+    let mut comp = IntcodeComputer::new(&[99; 20]);
+    comp.set_pre_hook(|_, _| HookAction::Skip);
+    comp.set_step_limit(Some(10));
+    assert_eq!(comp.try_run(), Err(IntcodeError::StepLimit { steps: 10 }));
+}
+
+#[test]
+fn test_memory_write_callback() {
+    use std::sync::mpsc;
+
+    // Who writes to address 20?
+    let mut comp = IntcodeComputer::from("1101,2,3,20,1001,20,5,20,1101,1,1,21,99");
+    let (tx, rx) = mpsc::channel();
+    comp.on_memory_write(move |addr, old, new, ip| if addr == 20 {
+        tx.send((ip, old, new)).unwrap();
+    });
+    comp.write_at(20, 1);
+    assert_eq!(comp.run(), RunResult::Finished);
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), [(0, 1, 5), (4, 5, 10)]);
+}
+
+#[test]
+fn test_input_request_handler() {
+    use crate::InputDecision;
+
+    // Echoes inputs until it reads a 0
+    let code = "3,100,4,100,1005,100,0,99";
+    let mut comp = IntcodeComputer::from(code);
+    let mut pending = vec![4, 5];
+    comp.set_input_request_handler(move || match pending.pop() {
+        Some(val) => InputDecision::Provide(val),
+        None => InputDecision::Block,
+    });
+    comp.input(3);
+    assert_eq!((comp.run(), comp.run(), comp.run()), (RunResult::Output(3), RunResult::Output(5), RunResult::Output(4)));
+    assert_eq!(comp.try_run(), Err(IntcodeError::NoInput { ip: 0 }));
+
+    // The handler is only asked when the queue is empty
+    comp.input(0);
+    assert_eq!(comp.run(), RunResult::Output(0));
+    assert_eq!(comp.run(), RunResult::Finished);
+
+    let mut comp = IntcodeComputer::from(code);
+    comp.set_input_request_handler(|| InputDecision::DefaultValue);
+    comp.set_default_input(-1);
+    assert_eq!(comp.run(), RunResult::Output(-1));
+    comp.clear_input_request_handler();
+    assert!(comp.try_run().is_err());
+}
+
+#[test]
+fn test_output_callback() {
+    use std::sync::mpsc;
+
+    // A quine, outputting itself in a single run
+    let code = [109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+    let mut comp = IntcodeComputer::new(&code);
+    let (tx, rx) = mpsc::channel();
+    comp.set_output_callback(move |val| tx.send(val).unwrap());
+    comp.set_buffered_output(true);
+    assert_eq!(comp.run(), RunResult::Finished);
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), code);
+    assert_eq!(comp.drain_outputs(), []);
+
+    let mut comp = IntcodeComputer::from("104,1,104,2,99");
+    comp.set_output_callback(|_| {});
+    comp.set_step_limit(Some(1));
+    assert!(comp.try_run().is_err());
+    comp.set_step_limit(None);
+    comp.clear_output_callback();
+    assert_eq!(comp.run(), RunResult::Output(2));
+}
+
+#[test]
+fn test_blocking_input() {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // Doubles every input until it reads a 0
+    let code = "3,100,1002,100,2,100,4,100,1005,100,0,99";
+    let mut comp = IntcodeComputer::from(code);
+    let input = comp.attach_blocking_input(None);
+    let machine = thread::spawn(move || comp.run_with_inputs(&[]));
+    for val in [1, 2, 0] {
+        thread::sleep(Duration::from_millis(5));
+        input.push(val);
+    }
+    assert_eq!(machine.join().unwrap(), [2, 4, 0]);
+
+    let mut comp = IntcodeComputer::from(code);
+    let input = comp.attach_blocking_input(Some(Duration::from_millis(20)));
+    let start = Instant::now();
+    assert_eq!(comp.try_run(), Err(IntcodeError::NoInput { ip: 0 }));
+    assert!(start.elapsed() >= Duration::from_millis(20));
+
+    // Closing lets the machine drain what's left, then stop waiting
+    input.push(3);
+    input.close();
+    assert_eq!(comp.run(), RunResult::Output(6));
+    let start = Instant::now();
+    assert_eq!(comp.try_run(), Err(IntcodeError::NoInput { ip: 0 }));
+    assert!(start.elapsed() < Duration::from_millis(20));
+
+    // Copies waiting on the same input don't wait for each other
+    let mut comp = IntcodeComputer::from(code);
+    comp.attach_blocking_input(Some(Duration::from_millis(500)));
+    let mut copy = comp.clone();
+    let machine = thread::spawn(move || comp.try_run());
+    thread::sleep(Duration::from_millis(50));
+    let start = Instant::now();
+    assert_eq!(copy.try_run(), Err(IntcodeError::NoInput { ip: 0 }));
+    assert!(start.elapsed() < Duration::from_millis(800));
+    assert_eq!(machine.join().unwrap(), Err(IntcodeError::NoInput { ip: 0 }));
+}
+
+#[test]
+fn test_shared_input_handle() {
+    use std::thread;
+
+    // Adds up 40 inputs
+    let code = "1101,0,40,100,3,101,1,101,102,102,1001,100,-1,100,1005,100,4,4,102,99";
+    let mut comp = IntcodeComputer::from(code);
+    let input = comp.input_handle();
+    assert_eq!(comp.try_run(), Err(IntcodeError::NoInput { ip: 4 }));
+
+    let producers: Vec<_> = (0..4).map(|n| {
+        let input = input.clone();
+        thread::spawn(move || (0..10).for_each(|i| input.push(n * 10 + i)))
+    }).collect();
+    producers.into_iter().for_each(|p| p.join().unwrap());
+
+    assert_eq!(comp.run(), RunResult::Output((0..40).sum()));
+    assert_eq!(comp.run(), RunResult::Finished);
+}
+
+#[test]
+fn test_run_stats() {
+    use crate::RunStats;
+
+    let mut comp = IntcodeComputer::from("109,5,21101,2,3,20,204,20,109,-7,3,30,99");
+    assert_eq!(comp.stats(), None);
+    comp.set_collect_stats(true);
+    comp.input(7);
+    assert_eq!(comp.run(), RunResult::Output(5));
+    assert_eq!(comp.run(), RunResult::Finished);
+
+    let stats = comp.take_stats().unwrap();
+    assert_eq!(stats, RunStats {
+        instructions: 6,
+        inputs: 1,
+        outputs: 1,
+        max_addr: Some(30),
+        addrs_written: 2,
+        min_rel_base: -2,
+        max_rel_base: 5,
+        elapsed: stats.elapsed,
+        branches: Default::default(),
+    });
+    assert!(stats.to_string().contains("Relative base:     -2 to 5"));
+    assert_eq!(comp.stats(), Some(RunStats { min_rel_base: -2, max_rel_base: -2, ..Default::default() }));
+}
+
+#[test]
+fn test_cycle_detection() {
+    let mut comp = IntcodeComputer::from("1101,0,0,20,1105,1,0");
+    comp.set_cycle_detection(Some(1));
+    assert_eq!(comp.try_run(), Err(IntcodeError::InfiniteLoop { ip: 4 }));
+
+    // Loops are still found after a long run without one
+    let mut comp = IntcodeComputer::from("1101,0,100,20,1001,20,-1,20,1005,20,4,1105,1,11");
+    comp.set_cycle_detection(Some(1));
+    assert_eq!(comp.try_run(), Err(IntcodeError::InfiniteLoop { ip: 11 }));
+    assert!(comp.steps() < 2 * 301);
+
+    // Loops that get somewhere, print something or wait for input are fine
+    let mut comp = IntcodeComputer::from("1101,0,100,20,1001,20,-1,20,1005,20,4,99");
+    comp.set_cycle_detection(Some(3));
+    assert_eq!(comp.try_run(), Ok(RunResult::Finished));
+
+    let mut comp = IntcodeComputer::from("104,1,1105,1,0");
+    comp.set_cycle_detection(Some(1));
+    for _ in 0..10 {
+        assert_eq!(comp.try_run(), Ok(RunResult::Output(1)));
+    }
+
+    let mut comp = IntcodeComputer::from("3,0,1105,1,0");
+    comp.set_cycle_detection(Some(1));
+    assert_eq!(comp.try_run(), Err(IntcodeError::NoInput { ip: 0 }));
+    assert_eq!(comp.try_run(), Err(IntcodeError::NoInput { ip: 0 }));
+    comp.input(3);
+    assert_eq!(comp.try_run(), Err(IntcodeError::NoInput { ip: 0 }));
+
+    // Going back to an earlier state isn't a loop. The last check before
+    // the step limit is the state after 31 steps, which both runs go through
+    let mut comp = IntcodeComputer::from("1001,100,1,100,1105,1,0");
+    comp.set_cycle_detection(Some(1));
+    comp.set_step_limit(Some(32));
+    let snapshot = comp.snapshot();
+    assert_eq!(comp.try_run(), Err(IntcodeError::StepLimit { steps: 32 }));
+    comp.restore(&snapshot);
+    assert_eq!(comp.try_run(), Err(IntcodeError::StepLimit { steps: 32 }));
+    comp.write_at(100, 15);
+    comp.set_step_limit(Some(40));
+    assert_eq!(comp.try_run(), Err(IntcodeError::StepLimit { steps: 40 }));
+}
+
+#[test]
+fn test_code_write_detection() {
+    use crate::{CodeWrite, CodeWriteMode, StopReason};
+
+    // Writes an ADD at 12 before running it, which then turns the jump at 4
+    // into a halt after it has run
+    let code = "1101,1,1100,12,1105,1,12,0,0,0,0,0,0,0,99,4,1105,1,4";
+    let mut comp = IntcodeComputer::from(code);
+    comp.set_code_write_mode(CodeWriteMode::Break);
+    assert_eq!(comp.run_until_stop(), StopReason::CodeWrite { ip: 12, addr: 4 });
+    assert_eq!(comp.ip(), 16);
+    assert_eq!(comp.run_until_stop(), StopReason::Finished);
+    assert_eq!(comp.code_writes(), [CodeWrite { ip: 12, addr: 4 }]);
+    assert_eq!(comp.code_writes()[0].to_string(), "Code at 4 overwritten by the instruction at 12");
+
+    let mut comp = IntcodeComputer::from(code);
+    comp.set_code_write_mode(CodeWriteMode::Record);
+    assert_eq!(comp.run_until_stop(), StopReason::Finished);
+    assert_eq!(comp.code_writes().len(), 1);
+}
+
+#[test]
+fn test_self_modification_report() {
+    use std::collections::BTreeMap;
+    use crate::{CodeWriteMode, ModifiedCode};
+
+    // Outputs 14 to 16 by bumping the address in the OUT instruction
+    let mut comp = IntcodeComputer::from("4,14,1001,1,1,1,1007,1,17,18,1005,18,0,99,7,8,9");
+    assert!(comp.self_mod_report().is_empty());
+    comp.set_code_write_mode(CodeWriteMode::Record);
+    assert_eq!(comp.run_with_inputs(&[]), [7, 8, 9]);
+    let report = comp.self_mod_report();
+    assert_eq!(report.writes, 3);
+    assert_eq!(report.sites, [ModifiedCode { addr: 1, opcode: false, writers: BTreeMap::from([(2, 3)]) }]);
+    assert!(!report.rewrites_opcodes());
+    assert_eq!(report.to_string(), "Writes to executed code: 3\n  1 (parameter): 2 x3");
+
+    let mut comp = IntcodeComputer::from("1101,1,1100,12,1105,1,12,0,0,0,0,0,0,0,99,4,1105,1,4");
+    comp.set_code_write_mode(CodeWriteMode::Record);
+    comp.run();
+    assert!(comp.self_mod_report().rewrites_opcodes());
+    assert_eq!(comp.self_mod_report().to_string(), "Writes to executed code: 1\n  4 (opcode): 12 x1");
+
+    // Counts to 1500 in a parameter of the comparison. Every write but the
+    // first is counted, and only the first ones are logged
+    let mut comp = IntcodeComputer::from("1001,5,1,5,1107,0,1500,15,1005,15,0,99");
+    comp.set_code_write_mode(CodeWriteMode::Record);
+    assert_eq!(comp.run(), RunResult::Finished);
+    assert_eq!(comp.self_mod_report().writes, 1_499);
+    assert_eq!(comp.self_mod_report().sites[0].writers, BTreeMap::from([(0, 1_499)]));
+    assert_eq!(comp.code_writes().len(), crate::MAX_LOGGED_WRITES);
+
+    // Patches a parameter, then jumps to it. It's only executed as an opcode
+    // after the write, which is still parameter patching
+    let mut comp = IntcodeComputer::from("1105,1,3,1101,0,99,2,1105,1,2");
+    comp.set_code_write_mode(CodeWriteMode::Record);
+    assert_eq!(comp.run(), RunResult::Finished);
+    assert_eq!(comp.self_mod_report().sites, [ModifiedCode { addr: 2, opcode: false, writers: BTreeMap::from([(3, 1)]) }]);
+}
+
+#[test]
+fn test_memory_search() {
+    let mut comp = IntcodeComputer::from("1,2,3,1,2,3,1,2");
+    comp.write_at(100, 2);
+    assert_eq!(comp.find_value(2), [1, 4, 7, 100]);
+    assert_eq!(comp.find_value(0), []);
+    assert_eq!(comp.find_sequence(&[1, 2, 3]), [0, 3]);
+    assert_eq!(comp.find_sequence(&[2, 0]), [7, 100]);
+    assert_eq!(comp.find_sequence(&[]), []);
+}
+
+#[test]
+fn test_fuzz_harness() {
+    assert_eq!(fuzz::decode(&[]), fuzz::FuzzInput { code: vec![], inputs: vec![] });
+    assert_eq!(fuzz::decode(&[1, 3, 0, 4, 0, 99, 0, 0xff, 0xff]),
+               fuzz::FuzzInput { code: vec![3, 4, 99], inputs: vec![-1] });
+    assert_eq!(fuzz::run(&[1, 3, 0, 0, 0, 4, 0, 0, 0, 99, 0, 0xff, 0xff]).outputs, vec![-1]);
+
+    // Nothing thrown at the harness may panic
+    let mut rng = Rng::new(1234);
+    for _ in 0..5_000 {
+        let len = rng.range(0, 64) as usize;
+        let data: Vec<u8> = (0..len).map(|_| rng.next_u64() as u8).collect();
+        fuzz::run(&data);
+    }
+}
+
+#[test]
+fn test_golden_trace() {
+    let code = "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,\
+        1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99";
+    let mut comp = IntcodeComputer::from(code);
+    comp.input(9);
+    trace::assert_golden("test_inputs/d5_compare.trace", &mut comp);
+
+    // Mismatches point at the first differing line
+    assert!(trace::compare("0: END 99\nhalt\n", "0: END 99\nhalt\n").is_ok());
+    match trace::compare("0: END 99\nhalt\n", "0: END 99\n") {
+        Err(trace::GoldenError::Mismatch { line, expected, actual }) => {
+            assert_eq!((line, expected.as_deref(), actual), (2, Some("halt"), None));
+        },
+        other => panic!("Unexpected comparison result: {other:?}"),
+    }
+}
+
+#[test]
+fn test_watch_expressions() {
+    use crate::watch::{Expr, ParseError, WatchList};
+
+    let expr: Expr = "[rb + 1] * 2 >= mem[ip] - -3".parse().unwrap();
+    assert_eq!(expr.to_string(), "(([(rb + 1)] * 2) >= ([ip] - -3))");
+    assert_eq!(expr.to_string().parse::<Expr>(), Ok(expr.clone()));
+    let comp = IntcodeComputer::from("5,4");
+    assert_eq!(expr.eval(&comp), Some(1));
+    assert_eq!("[1] / [7]".parse::<Expr>().unwrap().eval(&comp), None);
+    assert_eq!("(1 + ".parse::<Expr>(), Err(ParseError { pos: 5, expected: "a value" }));
+    assert_eq!("1 2".parse::<Expr>(), Err(ParseError { pos: 2, expected: "end of expression" }));
+
+    // Counts down from 3, watching the counter and whether it reached 1
+    let mut comp = IntcodeComputer::from("1101,0,3,20,1001,20,-1,20,1005,20,4,99");
+    let mut watches = WatchList::new();
+    watches.add("count", "[20]").unwrap();
+    watches.add("last", "[20] == 1").unwrap();
+    let trace = trace::Trace::record_watching(&mut comp, &watches).to_text();
+    let watch_lines: Vec<_> = trace.lines().filter(|line| line.starts_with('=')).collect();
+    assert_eq!(watch_lines, ["= count: 0", "= last: 0", "= count: 3", "= count: 2", "= count: 1",
+                             "= last: 1", "= count: 0", "= last: 0"]);
+    assert!(trace.ends_with("4: ADD 1001,20,-1,20\n= count: 0\n= last: 0\n8: JMP 1005,20,4\n11: END 99\nhalt\n"));
+}
+
+#[test]
+fn test_debug_session() {
+    use crate::debug::{DebugSession, SessionEvent};
+    use crate::{Event, StopReason};
+
+    // Doubles an input into address 20 and prints it
+    let mut session = DebugSession::new(IntcodeComputer::from("3,20,1002,20,2,20,4,20,99"));
+    let events = session.subscribe();
+    session.watch("value", "[20]").unwrap();
+    session.add_breakpoint(6);
+
+    assert_eq!(session.step(), Some(StopReason::NeedsInput { ip: 0 }));
+    session.input(21);
+    assert_eq!(session.step(), None);
+    assert_eq!(session.resume(), StopReason::Breakpoint { ip: 6 });
+    assert_eq!(session.read_range(19..22), [0, 42, 0]);
+    assert_eq!(session.eval("[20] / 2 == 21"), Ok(Some(1)));
+    session.write(20, 7);
+    assert_eq!(session.resume(), StopReason::Output(7));
+    assert_eq!(session.resume(), StopReason::Finished);
+
+    let events: Vec<_> = events.try_iter().collect();
+    assert_eq!(events[..4], [
+        SessionEvent::WatchChanged { name: "value".to_owned(), value: Some(0) },
+        SessionEvent::Stopped(StopReason::NeedsInput { ip: 0 }),
+        SessionEvent::Machine(Event::InputConsumed { ip: 0, value: 21 }),
+        SessionEvent::Machine(Event::MemoryWritten { ip: 0, addr: 20, old: 0, new: 21 }),
+    ]);
+    let watched: Vec<_> = events.iter().filter_map(|e| match e {
+        SessionEvent::WatchChanged { value, .. } => *value,
+        _ => None,
+    }).collect();
+    assert_eq!(watched, [0, 21, 42, 7]);
+
+    let comp = session.into_inner();
+    assert!(comp.observers.is_empty());
+}
+
+#[test]
+fn test_debug_scripts() {
+    use crate::debug::{DebugSession, SessionEvent};
+    use crate::script::{Script, ScriptError};
+    use crate::StopReason;
+
+    // Counts address 50 up from 0, copying it to 51, and outputs it when it gets to 5
+    let code = "1001,50,1,50,1001,50,0,51,1008,50,5,52,1006,52,0,4,50,99";
+    let mut session = DebugSession::new(IntcodeComputer::from(code));
+    let events = session.subscribe();
+    session.load_script("
+        # Reports and patches the counter
+        change [50] => print [50] * 10
+        at 15 => dump 50..53; [51] := 100
+        when [51] == 3 => [50] := 4; stop
+    ").unwrap();
+
+    assert_eq!(session.resume(), StopReason::Breakpoint { ip: 8 });
+    assert_eq!(session.read_range(50..52), [4, 3]);
+    assert_eq!(session.resume(), StopReason::Output(5));
+    assert_eq!(session.read(51), 100);
+    assert_eq!(session.resume(), StopReason::Finished);
+
+    let printed: Vec<String> = events.try_iter().filter_map(|e| match e {
+        SessionEvent::ScriptOutput(line) => Some(line),
+        _ => None,
+    }).collect();
+    assert_eq!(printed, ["[50] * 10 = 10", "[50] * 10 = 20", "[50] * 10 = 30", "[50] * 10 = 50", "50..53: 5 5 1"]);
+
+    assert_eq!("at 1 => print".parse::<Script>(), Err(ScriptError { line: 1 }));
+    assert_eq!("\nat 1 => 5 := 1".parse::<Script>(), Err(ScriptError { line: 2 }));
+    assert_eq!("sometimes 1 => stop".parse::<Script>(), Err(ScriptError { line: 1 }));
+    assert!("at 1 => stop; [2] := [3]; dump 0..ip".parse::<Script>().is_ok());
+}
+
+#[test]
+fn test_symbol_table() {
+    use crate::{SymbolError, SymbolTable};
+
+    let symbols: SymbolTable = "# Counts down\nstart = 0\nloop = 4\n\ncount = 20  # the counter".parse().unwrap();
+    assert_eq!(symbols.addr_of("loop"), Some(4));
+    assert_eq!(symbols.name_of(20), Some("count"));
+    assert_eq!(symbols.locate(10), Some("loop+6".to_owned()));
+    assert_eq!("x = 1\n2 = y".parse::<SymbolTable>(), Err(SymbolError { line: 2 }));
+    assert_eq!("ip = 1".parse::<SymbolTable>(), Err(SymbolError { line: 1 }));
+
+    let mut comp = IntcodeComputer::from("1101,0,2,20,1001,20,-1,20,1005,20,4,99");
+    comp.set_symbols(symbols);
+    assert!(comp.to_string().contains("\n  start:\n> 0: ADD 0, 2, [count]\n  loop:\n  4: ADD [count], -1, [count]"));
+    assert_eq!("[count] + count".parse::<watch::Expr>().unwrap().eval(&comp), Some(20));
+
+    let trace = trace::Trace::record(&mut comp.clone()).to_text();
+    assert!(trace.starts_with("start:\n0: ADD 1101,0,2,20\nloop:\n4: ADD 1001,20,-1,20\n8: JMP 1005,20,4\nloop:\n"));
+
+    comp.write_at(11, 0);
+    let e = comp.try_run().unwrap_err();
+    assert_eq!(comp.describe_error(&e), "Unexpected opcode at 11: 0 (loop+7)");
+}
+
+#[test]
+fn test_format_program() {
+    use std::num::NonZeroUsize;
+    use crate::format::{format_program, FormatError};
+    use crate::intcode::parse_code;
+
+    let code = "1101,+100,-1,4\n\n, 99,0005\n";
+    let three = NonZeroUsize::new(3).unwrap();
+    let formatted = format_program(code, three).unwrap();
+    assert_eq!(formatted, "1101,  100,   -1,\n   4,   99,    5\n");
+    assert_eq!(parse_code(&formatted), [1101, 100, -1, 4, 99, 5]);
+    assert_eq!(format_program(&formatted, three).unwrap(), formatted);
+    assert_eq!(format_program("1,x,3", three), Err(FormatError { index: 1, token: "x".to_owned() }));
+}
+
+#[test]
+fn test_program_diff() {
+    use crate::diff::{diff_programs, DiffOp};
+    use crate::intcode::parse_code;
+
+    let old = parse_code("3,20,1002,20,2,20,4,20,99,7");
+    let new = parse_code("3,20,1001,20,1,20,1002,20,3,20,4,20,99,8");
+    let diff = diff_programs(&old, &new);
+    let kinds: Vec<_> = diff.ops.iter().map(|op| match op {
+        DiffOp::Same { .. } => '=',
+        DiffOp::Changed { .. } => '~',
+        DiffOp::Deleted(_) => '-',
+        DiffOp::Inserted(_) => '+',
+    }).collect();
+    assert_eq!(kinds, ['=', '+', '~', '=', '=', '-', '+']);
+    assert_eq!(diff.to_string(), "+ 2: ADD 1001,20,1,20\n\
+                                  ~ 2: MUL 1002,20,2,20\n  6: MUL 1002,20,3,20\n\
+                                  - 9: DATA 7\n\
+                                  + 13: DATA 8\n");
+    assert!(diff_programs(&old, &old).is_empty());
+
+    // Long programs: one change in the middle of a shared prefix and suffix
+    let long: Vec<Int> = (0..20_000).flat_map(|i| [1101, i, 0, 0]).collect();
+    let mut edited = long.clone();
+    edited[40_001] = -1;
+    let diff = diff_programs(&long, &edited);
+    assert_eq!(diff.ops.iter().filter(|op| !matches!(op, DiffOp::Same { .. })).count(), 1);
+    assert!(matches!(&diff.ops[10_000], DiffOp::Changed { old, .. } if old.addr == 40_000));
+
+    // Scattered edits, too far apart to strip and too big for a single table
+    let old: Vec<Int> = (0..600).flat_map(|i| [1101, i, 0, 0]).collect();
+    let new: Vec<Int> = (0..600).filter(|i| i % 7 != 3)
+        .flat_map(|i| match i % 11 {
+            5 => vec![1101, i, 0, 0, 104, i],
+            _ => vec![1101, i, 0, 0],
+        })
+        .collect();
+    let diff = diff_programs(&old, &new);
+    let same = diff.ops.iter().filter(|op| matches!(op, DiffOp::Same { .. })).count();
+    assert_eq!(same, (0..600).filter(|i| i % 7 != 3).count());
+    let old_addrs: Vec<Int> = diff.ops.iter().filter_map(|op| match op {
+        DiffOp::Same { old, .. } | DiffOp::Changed { old, .. } | DiffOp::Deleted(old) => Some(old.addr),
+        DiffOp::Inserted(_) => None,
+    }).collect();
+    assert_eq!(old_addrs, (0..600).map(|i| i * 4).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_program_header() {
+    use std::io::{Cursor, ErrorKind};
+    use crate::{HeaderError, ProgramHeader};
+    use crate::format::format_program;
+
+    // Starts past a dead END
+    let code = [99, 104, 7, 99];
+    let mut header = ProgramHeader { name: Some("seven".to_owned()), entry: Some(1), ..Default::default() };
+    header.extra.push(("author".to_owned(), "me".to_owned()));
+    header.seal(&code);
+    let text = format!("{header}99,104,7,\n99");
+
+    let mut comp = IntcodeComputer::from(&text);
+    assert_eq!(comp.header(), Some(&header));
+    assert_eq!(comp.ip(), 1);
+    assert_eq!(comp.run(), RunResult::Output(7));
+    let comp = IntcodeComputer::from_reader(Cursor::new(&text)).unwrap();
+    assert_eq!(comp.header(), Some(&header));
+
+    let corrupted = text.replace("104,7", "104,8");
+    let e = IntcodeComputer::from_reader(Cursor::new(&corrupted)).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidData);
+    let mismatch = HeaderError::ChecksumMismatch {
+        expected: header.checksum.unwrap(),
+        actual: ProgramHeader::checksum_of(&[99, 104, 8, 99]),
+    };
+    assert_eq!(header.verify(&[99, 104, 8, 99]), Err(mismatch));
+
+    let formatted = format_program(&text, std::num::NonZeroUsize::new(2).unwrap()).unwrap();
+    assert!(formatted.starts_with("#! name: seven\n#! entry: 1\n"));
+    assert_eq!(IntcodeComputer::from(&formatted), IntcodeComputer::from(&text));
+}
+
+#[test]
+fn test_obfuscator() {
+    use crate::intcode::parse_code;
+    use crate::RewriteError;
+    use crate::obfuscate::Obfuscator;
+
+    // Counts down from the input
+    let code = parse_code("3,20,1006,20,14,4,20,1001,20,-1,20,1105,1,2,99");
+    let obfuscator = Obfuscator { padding: 50, ..Default::default() };
+    let mut rng = Rng::new(7);
+    for _ in 0..20 {
+        let scrambled = obfuscator.obfuscate(&code, &mut rng).unwrap();
+        assert_ne!(scrambled, code);
+        assert_eq!(IntcodeComputer::new(&scrambled).run_with_inputs(&[3]), [3, 2, 1]);
+        assert_eq!(IntcodeComputer::new(&scrambled).run_with_inputs(&[0]), []);
+    }
+
+    let mut accepted = 0;
+    for program in ProgramGenerator::default().programs(99).take(500) {
+        if let Ok(scrambled) = obfuscator.obfuscate(&program.code, &mut rng) {
+            let expected = IntcodeComputer::new(&program.code).run_with_inputs(&program.inputs);
+            assert_eq!(IntcodeComputer::new(&scrambled).run_with_inputs(&program.inputs), expected);
+            accepted += 1;
+        }
+    }
+    assert!(accepted > 400);
+
+    let obfuscate = |code: &str| obfuscator.obfuscate(&parse_code(code), &mut Rng::new(0));
+    assert_eq!(obfuscate("4,0,99"), Err(RewriteError::CodeAccess { ip: 0 }));
+    assert_eq!(obfuscate("204,0,99"), Err(RewriteError::CodeAccess { ip: 0 }));
+    // The relative base depends on the input
+    assert_eq!(obfuscate("3,11,1006,11,7,109,1,204,10,99,0,0"), Err(RewriteError::RelativeParam { ip: 7 }));
+    assert_eq!(obfuscate("105,1,4,99,5,99"), Err(RewriteError::IndirectJump { ip: 0 }));
+    assert_eq!(obfuscate("3,3,0"), Err(RewriteError::Incomplete));
+}
+
+#[test]
+fn test_calling_convention() {
+    use crate::abi::{LabelError, Param, ProgramWriter};
+
+    let mut w = ProgramWriter::new();
+    w.init_stack();
+    w.input(Param::var("n"));
+    w.push(Param::Imm(0));
+    w.push(Param::var("n"));
+    w.call("fact");
+    w.drop(1);
+    w.pop(Param::var("result"));
+    w.output(Param::var("result"));
+    w.halt();
+
+    // fact(n) = n == 0 ? 1 : n * fact(n - 1)
+    w.function("fact", 1, 1);
+    w.jump_if_true(w.arg(0), Param::label("recurse"));
+    w.add(Param::Imm(1), Param::Imm(0), w.result());
+    w.ret();
+    w.label("recurse");
+    w.add(w.arg(0), Param::Imm(-1), w.local(0));
+    w.push(Param::Imm(0));
+    w.push(w.local(0));
+    w.call("fact");
+    w.drop(1);
+    w.pop(w.local(0));
+    w.mul(w.arg(0), w.local(0), w.result());
+    w.ret();
+
+    w.data("n", &[0]);
+    w.data("result", &[0]);
+    let symbols = w.symbols();
+    let code = w.finish().unwrap();
+    assert_eq!(symbols.name_of(36), Some("fact"));
+    for (n, expected) in [(0, 1), (5, 120), (10, 3_628_800)] {
+        assert_eq!(IntcodeComputer::new(&code).run_with_inputs(&[n]), [expected]);
+    }
+
+    let mut w = ProgramWriter::new();
+    w.call("missing");
+    assert_eq!(w.finish(), Err(LabelError::Undefined("missing".to_owned())));
+    let mut w = ProgramWriter::new();
+    w.label("twice");
+    w.label("twice");
+    assert_eq!(w.finish(), Err(LabelError::Duplicate("twice".to_owned())));
+}
+
+#[test]
+fn test_routines() {
+    use crate::abi::{Param, ProgramWriter};
+    use crate::routines::Routine;
+
+    // Stores the inputs at the given indices, then outputs the array
+    // backwards and the first two values swapped
+    let mut w = ProgramWriter::new();
+    w.init_stack();
+    w.label("store");
+    w.input(Param::var("index"));
+    w.less_than(Param::var("index"), Param::Imm(0), Param::var("done"));
+    w.jump_if_true(Param::var("done"), Param::label("print"));
+    w.push(Param::label("values"));
+    w.push(Param::var("index"));
+    w.input(Param::var("value"));
+    w.push(Param::var("value"));
+    w.call(Routine::ArraySet.name());
+    w.drop(3);
+    w.jump(Param::label("store"));
+
+    w.label("print");
+    for i in (0..3).rev() {
+        w.push(Param::Imm(0));
+        w.push(Param::label("values"));
+        w.push(Param::Imm(i));
+        w.call(Routine::ArrayGet.name());
+        w.drop(2);
+        w.pop(Param::var("value"));
+        w.output(Param::var("value"));
+    }
+    w.push(Param::Imm(1));
+    w.push(Param::Imm(2));
+    w.call(Routine::Swap.name());
+    w.pop(Param::var("value"));
+    w.output(Param::var("value"));
+    w.pop(Param::var("value"));
+    w.output(Param::var("value"));
+    w.halt();
+
+    w.include(Routine::ArraySet);
+    w.include(Routine::ArrayGet);
+    w.include(Routine::ArrayGet);
+    w.include(Routine::Swap);
+    w.data("index", &[0]);
+    w.data("done", &[0]);
+    w.data("value", &[0]);
+    w.array("values", 3);
+    let symbols = w.symbols();
+    let code = w.finish().unwrap();
+
+    let outputs = IntcodeComputer::new(&code).run_with_inputs(&[0, 10, 2, 30, 1, 20, -1]);
+    assert_eq!(outputs, [30, 20, 10, 1, 2]);
+
+    let mut comp = IntcodeComputer::new(&code);
+    comp.input_iter([3, 40]);
+    comp.set_symbols(symbols);
+    let e = comp.try_run().unwrap_err();
+    assert!(comp.describe_error(&e).ends_with("(std_bounds_error)"));
+}
+
+#[test]
+fn test_runtime_routines() {
+    use crate::abi::{Param, ProgramWriter};
+    use crate::routines::{Routine, HEAP_START};
+
+    // Greets the name read, then reads a number and prints it plus one, and
+    // the last element of an array allocated with that length
+    let mut w = ProgramWriter::new();
+    w.init_stack();
+    w.push(Param::label("hello"));
+    w.call(Routine::PrintStr.name());
+    w.drop(1);
+    w.push(Param::Imm(0));
+    w.call(Routine::ReadLine.name());
+    w.call(Routine::PrintStr.name());
+    w.drop(1);
+
+    w.push(Param::Imm(0));
+    w.call(Routine::ReadInt.name());
+    w.pop(Param::var("n"));
+    w.add(Param::var("n"), Param::Imm(1), Param::var("n1"));
+    w.push(Param::var("n1"));
+    w.call(Routine::PrintInt.name());
+    w.drop(1);
+
+    w.push(Param::Imm(0));
+    w.push(Param::var("n"));
+    w.call(Routine::Alloc.name());
+    w.drop(1);
+    w.pop(Param::var("array"));
+    w.add(Param::var("n"), Param::Imm(-1), Param::var("n1"));
+    w.push(Param::var("array"));
+    w.push(Param::var("n1"));
+    w.push(Param::Imm(-42));
+    w.call(Routine::ArraySet.name());
+    w.drop(3);
+    w.push(Param::Imm(0));
+    w.push(Param::var("array"));
+    w.push(Param::var("n1"));
+    w.call(Routine::ArrayGet.name());
+    w.drop(2);
+    w.call(Routine::PrintInt.name());
+    w.drop(1);
+    w.halt();
+
+    for routine in [Routine::PrintStr, Routine::ReadLine, Routine::ReadInt, Routine::PrintInt, Routine::Alloc, Routine::ArraySet, Routine::ArrayGet] {
+        w.include(routine);
+    }
+    w.string("hello", "Hello, ");
+    for var in ["n", "n1", "array"] {
+        w.data(var, &[0]);
+    }
+    let code = w.finish().unwrap();
+
+    let run = |inputs: &str| {
+        let mut comp = IntcodeComputer::new(&code);
+        comp.input_str(inputs);
+        let out = comp.run_ascii();
+        (out.text, comp.read_at(HEAP_START))
+    };
+    assert_eq!(run("Ada\n1009\n"), ("Hello, Ada1010-42".to_owned(), 3));
+    assert_eq!(run("\n-20\n").0, "Hello, -19");
+    assert_eq!(run("x\n0\n").0, "Hello, x1");
+
+    // Numbers of every size
+    let mut w = ProgramWriter::new();
+    w.init_stack();
+    for value in [0, 7, 10, 1_000_000, -99, Int::MAX, Int::MIN + 1] {
+        w.push(Param::Imm(value));
+        w.call(Routine::PrintInt.name());
+        w.drop(1);
+        w.output(Param::Imm(b' ' as Int));
+    }
+    w.halt();
+    w.include(Routine::PrintInt);
+    let text = IntcodeComputer::new(&w.finish().unwrap()).run_ascii().text;
+    assert_eq!(text, format!("0 7 10 1000000 -99 {} {} ", Int::MAX, Int::MIN + 1));
+}
+
+#[test]
+fn test_relocatable_objects() {
+    use crate::abi::{link, LabelError, Param, ProgramWriter};
+    use crate::routines::{library, Routine};
+
+    // Prints a number with a routine from a library built on its own
+    let mut w = ProgramWriter::new();
+    w.init_stack();
+    w.push(Param::Imm(42));
+    w.call(Routine::PrintInt.name());
+    w.drop(1);
+    w.halt();
+    let program = w.finish_object().unwrap();
+    let lib = library(&[Routine::Swap, Routine::PrintInt]);
+    assert_eq!(program.imports, [(16, "std_print_int".to_owned())]);
+    assert_eq!(lib.exports["std_swap"], 0);
+
+    let code = link(&[program.clone(), lib.clone()]).unwrap();
+    assert_eq!(IntcodeComputer::new(&code).run_ascii().text, "42");
+
+    // The library can go anywhere
+    let base = 5_000;
+    let end = base + lib.len() as Int;
+    let mut code = program.load_at(0, end, |name| lib.exports.get(name).map(|addr| base + addr)).unwrap();
+    code.resize(base as usize, 0);
+    code.extend(lib.load_at(base, end, |_| None).unwrap());
+    assert_eq!(IntcodeComputer::new(&code).run_ascii().text, "42");
+
+    assert_eq!(link(std::slice::from_ref(&program)), Err(LabelError::Undefined("std_print_int".to_owned())));
+    assert_eq!(link(&[program, lib.clone(), lib]), Err(LabelError::Duplicate("std_print_int".to_owned())));
+}
+
+#[test]
+fn test_profile_guided_layout() {
+    use crate::abi::{Param, ProgramWriter};
+    use crate::pgo::{optimize, Profile};
+
+    // Outputs twice every input below 10 and the rest as they are, until a 0
+    let mut w = ProgramWriter::new();
+    w.label("loop");
+    w.input(Param::var("x"));
+    w.jump_if_false(Param::var("x"), Param::label("end"));
+    w.less_than(Param::var("x"), Param::Imm(10), Param::var("small"));
+    w.jump_if_false(Param::var("small"), Param::label("big"));
+    w.mul(Param::var("x"), Param::Imm(2), Param::var("y"));
+    w.jump(Param::label("join"));
+    w.label("big");
+    w.add(Param::var("x"), Param::Imm(0), Param::var("y"));
+    w.label("join");
+    w.output(Param::var("y"));
+    w.jump(Param::label("loop"));
+    w.label("end");
+    w.halt();
+    for var in ["x", "small", "y"] {
+        w.data(var, &[0]);
+    }
+    let code = w.finish().unwrap();
+
+    let profile = Profile::collect(&code, &[vec![1, 2, 3, 4, 50, 0], vec![5, 6, 0]], 1_000);
+    assert_eq!(profile.count(0), 9);
+    assert_eq!(profile.edge(2, 5), 7);
+    let optimized = optimize(&code, &profile).unwrap();
+
+    let steps = |code: &[Int], inputs: &[Int]| {
+        let mut comp = IntcodeComputer::new(code);
+        comp.set_collect_stats(true);
+        let outputs = comp.run_with_inputs(inputs);
+        (outputs, comp.stats().unwrap().instructions)
+    };
+    // Same results everywhere, faster on the common path
+    for inputs in [&[3, 0][..], &[20, 0], &[1, 2, 3, 40, 0], &[0]] {
+        assert_eq!(steps(&optimized, inputs).0, steps(&code, inputs).0);
+    }
+    assert!(steps(&optimized, &[1, 2, 3, 0]).1 < steps(&code, &[1, 2, 3, 0]).1);
+
+    // Echoes inputs until a 0, through a zero test and a copy that can be
+    // merged with the instructions using them
+    let mut w = ProgramWriter::new();
+    w.label("loop");
+    w.input(Param::var("x"));
+    w.equals(Param::var("x"), Param::Imm(0), Param::var("zero"));
+    w.jump_if_true(Param::var("zero"), Param::label("end"));
+    w.add(Param::Imm(0), Param::var("x"), Param::var("copy"));
+    w.output(Param::var("copy"));
+    w.jump(Param::label("loop"));
+    w.label("end");
+    w.halt();
+    for var in ["x", "zero", "copy"] {
+        w.data(var, &[0]);
+    }
+    let code = w.finish().unwrap();
+    let optimized = optimize(&code, &Profile::collect(&code, &[vec![1, 0]], 1_000)).unwrap();
+    assert_eq!(steps(&optimized, &[4, 5, 0]), (vec![4, 5], 11));
+    assert_eq!(steps(&code, &[4, 5, 0]), (vec![4, 5], 16));
+
+    for program in ProgramGenerator::default().programs(5).take(300) {
+        let profile = Profile::collect(&program.code, std::slice::from_ref(&program.inputs), 1_000);
+        if let Ok(optimized) = optimize(&program.code, &profile) {
+            let expected = IntcodeComputer::new(&program.code).run_with_inputs(&program.inputs);
+            assert_eq!(IntcodeComputer::new(&optimized).run_with_inputs(&program.inputs), expected);
+        }
+    }
+}
+
+#[test]
+fn test_branch_stats() {
+    use crate::BranchCount;
+    use crate::abi::{Param, ProgramWriter};
+    use crate::pgo::Profile;
+
+    // Counts down from 3, skipping 2
+    let mut w = ProgramWriter::new();
+    w.add(Param::Imm(3), Param::Imm(0), Param::var("n"));
+    w.label("loop");
+    w.jump_if_false(Param::var("n"), Param::label("end"));
+    w.equals(Param::var("n"), Param::Imm(2), Param::var("skip"));
+    w.jump_if_true(Param::var("skip"), Param::label("next"));
+    w.output(Param::var("n"));
+    w.label("next");
+    w.add(Param::var("n"), Param::Imm(-1), Param::var("n"));
+    w.jump(Param::label("loop"));
+    w.label("end");
+    w.halt();
+    w.data("n", &[0]);
+    w.data("skip", &[0]);
+    let code = w.finish().unwrap();
+
+    let mut comp = IntcodeComputer::new(&code);
+    comp.set_collect_stats(true);
+    assert_eq!(comp.run_with_inputs(&[]), [3, 1]);
+    let stats = comp.stats().unwrap();
+    assert_eq!(stats.branches[&4], BranchCount { taken: 1, not_taken: 3 });
+    assert_eq!(stats.branches[&11], BranchCount { taken: 1, not_taken: 2 });
+    assert_eq!(stats.branches[&20], BranchCount { taken: 3, not_taken: 0 });
+    assert_eq!(stats.branches[&11].taken_ratio(), 1.0 / 3.0);
+    assert!(stats.to_string().ends_with("Branches:\n  4: taken 1 of 4 times (25%)\n  \
+                                        11: taken 1 of 3 times (33%)\n  20: taken 3 of 3 times (100%)"));
+
+    let profile = Profile::collect(&code, &[vec![], vec![]], 1_000);
+    assert_eq!(profile.branches[&4], BranchCount { taken: 2, not_taken: 6 });
+}
+
+#[test]
+fn test_range_analysis() {
+    use crate::analysis::{analyze, Branch, Range};
+    use crate::intcode::parse_code;
+
+    // Counts to 10 at address 100, then always jumps over a dead END to echo an input
+    let code = parse_code("1101,0,0,100,1001,100,1,100,1007,100,10,101,1005,101,4,1105,1,19,99,3,102,4,102,99");
+    let analysis = analyze(&code);
+    assert!(analysis.is_complete());
+    assert_eq!(analysis.reachable.iter().copied().collect::<Vec<_>>(), [0, 4, 8, 12, 15, 19, 21, 23]);
+    assert_eq!(analysis.branches.iter().map(|(&ip, &b)| (ip, b)).collect::<Vec<_>>(),
+               [(12, Branch::Both), (15, Branch::AlwaysTaken)]);
+    assert_eq!(analysis.ranges[&100], Range { lo: 0, hi: Int::MAX });
+    assert_eq!(analysis.ranges[&101], Range { lo: 0, hi: 1 });
+    assert_eq!(analysis.ranges[&102], Range::ANY);
+    assert_eq!(analysis.ranges[&101].to_string(), "[0, 1]");
+
+    // Comparisons on known values decide the branch
+    let analysis = analyze(&parse_code("1107,5,3,10,1006,10,9,104,0,99"));
+    assert!(analysis.is_complete());
+    assert_eq!(analysis.branches[&4], Branch::AlwaysTaken);
+    assert!(!analysis.reachable.contains(&7));
+
+    // An input overwriting the next instruction can't be followed
+    let analysis = analyze(&parse_code("3,2,0"));
+    assert!(!analysis.is_complete());
+    assert_eq!(analysis.unknown.iter().copied().collect::<Vec<_>>(), [2]);
+
+    // Overflowing the relative base or an address stops the analysis there
+    let analysis = analyze(&parse_code("109,170141183460469231731687303715884105727,109,1,99"));
+    assert_eq!(analysis.unknown.iter().copied().collect::<Vec<_>>(), [2]);
+    let analysis = analyze(&parse_code("109,170141183460469231731687303715884105727,204,1,99"));
+    assert_eq!(analysis.unknown.iter().copied().collect::<Vec<_>>(), [2]);
+}
+
+#[test]
+fn test_halting_analysis() {
+    use crate::analysis::{will_halt_within, HaltVerdict};
+    use crate::intcode::parse_code;
+
+    // Echoes an input
+    let code = parse_code("3,0,4,0,99");
+    assert_eq!(will_halt_within(&code, &[5], 10), HaltVerdict::Halts { steps: 3 });
+    assert_eq!(will_halt_within(&code, &[], 10), HaltVerdict::Fails(IntcodeError::NoInput { ip: 0 }));
+
+    // Spins in place
+    assert_eq!(will_halt_within(&parse_code("1105,1,0"), &[], 1_000), HaltVerdict::Loops);
+
+    // Counts up forever, never repeating a state, but END is dead code
+    let code = parse_code("1001,100,1,100,4,100,1105,1,0,99");
+    assert_eq!(will_halt_within(&code, &[], 1_000), HaltVerdict::Loops);
+
+    // Counts down from a big number, so it halts eventually
+    let code = parse_code("1101,0,1000000,100,1001,100,-1,100,1005,100,4,99");
+    assert_eq!(will_halt_within(&code, &[], 1_000), HaltVerdict::Unknown);
+
+    // Overflows the relative base
+    let code = parse_code("109,170141183460469231731687303715884105727,109,1,99");
+    assert_eq!(will_halt_within(&code, &[], 10), HaltVerdict::Fails(IntcodeError::Overflow { ip: 2 }));
+}
+
+#[test]
+fn test_taint_tracking() {
+    use crate::taint::{trace_taint, Taint, TaintTracker};
+
+    // Reads a, b and c, outputs a + b, the constant 7, then c * 2, and
+    // finally overwrites a's cell with a constant
+    let code = "3,100,3,101,3,102,1,100,101,103,4,103,104,7,1002,102,2,104,4,104,1101,1,1,100,99";
+    let report = trace_taint(IntcodeComputer::from(code), &[1, 2, 3]).unwrap();
+    assert_eq!(report.inputs, [1, 2, 3]);
+    assert_eq!(report.outputs, [(3, Taint::from([0, 1])), (7, Taint::new()), (6, Taint::from([2]))]);
+    assert_eq!(report.memory.keys().copied().collect::<Vec<_>>(), [101, 102, 103, 104]);
+
+    // Running out of input can be resumed
+    let mut tracker = TaintTracker::new(IntcodeComputer::from(code));
+    tracker.input(5);
+    assert_eq!(tracker.run(), Err(IntcodeError::NoInput { ip: 2 }));
+    assert_eq!(tracker.taint_of(100), Taint::from([0]));
+    tracker.input(6);
+    tracker.input(0);
+    assert_eq!(tracker.run(), Ok(RunResult::Output(11)));
+
+    // A custom instruction writing its first parameter plus one to the third
+    // one. Its result depends on every input so far, and replaces the
+    // destination's taint
+    let mut comp = IntcodeComputer::from("3,100,3,101,1101,0,0,102,30,100,101,102,4,102,99");
+    comp.register_opcode(30, 3, |comp, ops| {
+        comp.write_operand(&ops[2], ops[0].value + 1)?;
+        Ok(crate::Effect::Next)
+    });
+    let report = trace_taint(comp, &[1, 2]).unwrap();
+    assert_eq!(report.outputs, [(2, Taint::from([0, 1]))]);
+
+    #[cfg(feature = "extensions")]
+    {
+        let report = trace_taint(IntcodeComputer::from("3,100,1010,100,2,101,4,101,99"), &[9]).unwrap();
+        assert_eq!(report.outputs, [(4, Taint::from([0]))]);
+    }
+}
+
+#[test]
+fn test_state_accessors() {
+    let mut comp = IntcodeComputer::from("3,0,109,-5,4,0,99");
+    comp.input(1);
+    comp.input(2);
+    assert_eq!((comp.ip(), comp.relative_base(), comp.pending_inputs()), (0, 0, 2));
+
+    assert_eq!(comp.run(), RunResult::Output(1));
+    assert_eq!((comp.ip(), comp.relative_base(), comp.pending_inputs()), (6, -5, 1));
+    assert!(!comp.is_finished());
+
+    assert_eq!(comp.run(), RunResult::Finished);
+    assert!(comp.is_finished());
+}
+
+#[test]
+fn test_memory_view() {
+    let mut comp = IntcodeComputer::from("1101,2,3,10,99");
+    assert_eq!(comp.memory_len(), 5);
+    assert_eq!(comp.max_addr(), Some(4));
+
+    comp.write_at(-3, 7);
+    assert_eq!(comp.run(), RunResult::Finished);
+    let cells: Vec<(Int, Int)> = comp.memory().collect();
+    assert_eq!(cells, vec![(-3, 7), (0, 1101), (1, 2), (2, 3), (3, 10), (4, 99), (10, 5)]);
+    assert_eq!(comp.memory_len(), 7);
+    assert_eq!(comp.max_addr(), Some(10));
+
+    assert_eq!(IntcodeComputer::new(&[]).max_addr(), None);
+}
+
+#[test]
+fn test_paged_memory() {
+    use crate::memory::{DENSE_CELLS, MAX_PAGES, PAGE_SIZE};
+
+    // Writes on both sides of a page boundary, with the code filling both
+    // pages
+    let edge = PAGE_SIZE as Int;
+    let mut code = vec![1101, 2, 3, edge - 1, 1101, 4, 5, edge, 99];
+    code.resize(2 * PAGE_SIZE, 0);
+    let mut comp = IntcodeComputer::new(&code);
+    assert_eq!(comp.run(), RunResult::Finished);
+    assert_eq!(comp.memory.owned_pages(), 2);
+
+    // Copies share pages until they write to them
+    let mut fork = comp.clone();
+    assert_eq!(fork.memory.owned_pages(), 0);
+    fork[edge + 1] += 1;
+    assert_eq!(fork.memory.owned_pages(), 1);
+    assert_eq!(comp.read_at(edge + 1), 0);
+
+    // Scattered writes don't allocate pages, until there are enough of them
+    // in one page
+    let page = 10 * edge;
+    for i in 0..DENSE_CELLS as Int - 1 {
+        fork.write_at(page + 2 * i, i + 1);
+    }
+    assert_eq!(fork.memory.owned_pages(), 1);
+    fork.write_at(page + 1, 100);
+    assert_eq!(fork.memory.owned_pages(), 2);
+    assert_eq!((fork.read_at(page), fork.read_at(page + 1), fork.read_at(page + 2)), (1, 100, 2));
+
+    // Past the paged range
+    let far = (MAX_PAGES * PAGE_SIZE) as Int;
+    fork.write_at(far, 8);
+    fork.write_at(-1, 7);
+    assert_eq!((fork.read_at(edge - 1), fork.read_at(edge), fork.read_at(far)), (5, 9, 8));
+    assert_eq!(fork.read_at(edge - 2), 0);
+    assert_eq!(fork.memory_len(), 2 * PAGE_SIZE + DENSE_CELLS + 2);
+    let mut cells: Vec<(Int, Int)> = fork.memory().filter(|(pos, _)| (edge - 1..=edge + 1).contains(pos)).collect();
+    cells.sort_unstable();
+    assert_eq!(cells, [(edge - 1, 5), (edge, 9), (edge + 1, 1)]);
+    assert_eq!(fork.memory().filter(|&(pos, _)| pos == far || pos < 0).count(), 2);
+}
+
+#[test]
+fn test_indexing() {
+    let mut comp = IntcodeComputer::from("1,0,0,0,99");
+    assert_eq!(comp[4], 99);
+    assert_eq!(comp[1_000], 0);
+
+    comp[1] = 4;
+    comp[2] += 4;
+    assert_eq!(comp.run(), RunResult::Finished);
+    assert_eq!(comp[0], 99 * 2);
+}
+
+#[test]
+fn test_builder() {
+    let code = load_input("d2.txt");
+    let builder = IntcodeBuilder::from(&code).noun_verb(67, 18);
+    for _ in 0..2 {
+        let mut comp = builder.build();
+        assert_eq!(comp.run(), RunResult::Finished);
+        assert_eq!(comp.read_at(0), 19690720);
+    }
+
+    let mut comp = IntcodeComputer::builder(&[3, 0, 3, 1, 4, 1, 99]).input(5).inputs([6, 7]).build();
+    assert_eq!(comp.run(), RunResult::Output(6));
+    assert_eq!(comp.pending_inputs(), 1);
+
+    let mut comp = IntcodeBuilder::from("1105,1,0").step_limit(10).build();
+    assert_eq!(comp.try_run(), Err(IntcodeError::StepLimit { steps: 10 }));
+
+    // Negative addresses are only rejected in strict mode
+    let builder = IntcodeBuilder::from("4,-1,99");
+    assert_eq!(builder.build().try_run(), Ok(RunResult::Output(0)));
+    assert_eq!(builder.strict(true).build().try_run(), Err(IntcodeError::NegativeAddress { ip: 0, addr: -1 }));
+
+    // Attachments are shared by every computer built. Adds the input to the
+    // device's value, twice
+    use std::sync::{Arc, Mutex};
+    use crate::{Device, Event, InputDecision};
+    struct Cell(Int);
+    impl Device for Cell {
+        fn read(&mut self, _offset: Int) -> Int {
+            self.0
+        }
+        fn write(&mut self, _offset: Int, value: Int) {
+            self.0 = value;
+        }
+    }
+    let outputs = Arc::new(Mutex::new(vec![]));
+    let shared = outputs.clone();
+    let builder = IntcodeBuilder::from("3,20,1,20,100,100,4,100,99")
+        .device(100, 1, Cell(1))
+        .input_handler(|| InputDecision::Provide(2))
+        .on_output(move |val| shared.lock().unwrap().push(val))
+        .observer(Vec::<Event>::new());
+    assert!(format!("{builder:?}").starts_with("IntcodeBuilder { code: [3, 20,"));
+    for _ in 0..2 {
+        assert_eq!(builder.build().run(), RunResult::Finished);
+    }
+    assert_eq!(*outputs.lock().unwrap(), [3, 5]);
+}
+
+#[test]
+fn test_bulk_input() {
+    let code = "3,0,3,1,3,2,3,3,1,0,1,0,1,2,3,2,1,0,2,0,4,0,99";
+    let mut comp = IntcodeComputer::from(code);
+    comp.input_iter(vec![1, 2]);
+    comp.extend([3, 4]);
+    assert_eq!(comp.pending_inputs(), 4);
+    assert_eq!(comp.run(), RunResult::Output(10));
+
+    let mut comp = IntcodeComputer::from(code);
+    comp.extend((1..=4).map(|x| x * 10));
+    assert_eq!(comp.run(), RunResult::Output(100));
+}
+
+#[test]
+fn test_ascii_input() {
+    // Echoes 3 values
+    let code = "3,0,4,0,3,0,4,0,3,0,4,0,99";
+    let mut comp = IntcodeComputer::from(code);
+    comp.input_line("hi");
+    for c in "hi\n".bytes() {
+        assert_eq!(comp.run(), RunResult::Output(c as Int));
+    }
+
+    let mut comp = IntcodeComputer::from(code);
+    comp.input_str("abc");
+    assert_eq!(comp.pending_inputs(), 3);
+    assert_eq!(comp.run(), RunResult::Output(97));
+}
+
+#[test]
+fn test_ascii_output() {
+    // Prints "Hi\n", echoes one input and then prints a large number
+    let code = "104,72,104,105,104,10,3,0,4,0,104,1234,99";
+    let mut comp = IntcodeComputer::from(code);
+
+    let out = comp.run_ascii();
+    assert_eq!(out, AsciiOutput { text: "Hi\n".to_owned(), stop: AsciiStop::NeedsInput });
+
+    comp.input_str("!");
+    assert_eq!(comp.run_ascii(), AsciiOutput { text: "!".to_owned(), stop: AsciiStop::Value(1234) });
+    assert_eq!(comp.run_ascii(), AsciiOutput { text: String::new(), stop: AsciiStop::Finished });
+
+    // Prints "?" and hits an unknown opcode
+    let mut comp = IntcodeComputer::from("104,63,42");
+    let stop = AsciiStop::Error(IntcodeError::UnknownOpcode { ip: 2, opcode: 42 });
+    assert_eq!(comp.run_ascii(), AsciiOutput { text: "?".to_owned(), stop });
+}
+
+#[test]
+fn test_ascii_streams() {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    // Echoes every input until it reads a 0, then outputs a large number
+    let code = "3,100,1006,100,11,4,100,1105,1,0,99,104,4321,99";
+    let mut comp = IntcodeComputer::from(code);
+    writeln!(comp.ascii_writer(), "first line").unwrap();
+    comp.ascii_writer().write_all(b"second\n").unwrap();
+
+    let lines: Vec<String> = BufReader::new(comp.ascii_reader()).lines().map(Result::unwrap).collect();
+    assert_eq!(lines, vec!["first line", "second"]);
+
+    comp.ascii_writer().write_all(b"ab\0").unwrap();
+    let mut reader = comp.ascii_reader();
+    let mut buf = [0; 16];
+    assert_eq!(reader.read(&mut buf).unwrap(), 2);
+    assert_eq!(&buf[..2], b"ab");
+    let err = reader.read(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(err.get_ref().unwrap().downcast_ref(), Some(&NonAsciiOutput(4321)));
+    assert_eq!(reader.read(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn test_interactive() {
+    // Prompts, echoes the answer in uppercase-ish (minus 32) and prints a number
+    let code = "104,63,3,100,1008,100,10,101,1005,101,20,1001,100,-32,100,4,100,1105,1,2,104,10,104,777,99";
+    let mut comp = IntcodeComputer::from(code);
+    let mut output = vec![];
+    comp.run_interactive_with("abc\r\n".as_bytes(), &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "?ABC\n777\n");
+
+    // Ends when input runs out
+    let mut comp = IntcodeComputer::from(code);
+    let mut output = vec![];
+    comp.run_interactive_with("".as_bytes(), &mut output).unwrap();
+    assert_eq!(output, b"?");
+    assert!(!comp.is_finished());
+
+    // Counts the characters read at address 101
+    let mut comp = IntcodeComputer::from("3,100,1001,101,1,101,1105,1,0");
+    let mut output = vec![];
+    let session = "ab\n!save s\ncd\n!undo\n!undo\n!undo\nefg\n!restore s\n!restore t\n!jump\n";
+    comp.run_interactive_with(session.as_bytes(), &mut output).unwrap();
+    assert_eq!(comp.read_at(101), 3);
+    assert_eq!(String::from_utf8(output).unwrap(), "Saved 's'.\nUndone.\nUndone.\nNothing to undo.\n\
+        Restored 's'.\nNo savepoint named 't'.\nUnknown command: !jump\n");
+
+    // Failures end the session with an error, after the output so far
+    let mut comp = IntcodeComputer::from("104,63,42");
+    let mut output = vec![];
+    let err = comp.run_interactive_with("".as_bytes(), &mut output).unwrap_err();
+    assert_eq!(err.get_ref().unwrap().downcast_ref(), Some(&IntcodeError::UnknownOpcode { ip: 2, opcode: 42 }));
+    assert_eq!(output, b"?");
+}
+
+#[cfg(feature = "term")]
+#[test]
+fn test_term_renderer() {
+    use crate::term::Renderer;
+
+    let mut renderer = Renderer::new(vec![]).with_fps(1_000);
+    renderer.draw_triplets(&[1, 2, 4, -1, 0, 12, 5]).unwrap();
+    renderer.frame().unwrap();
+    renderer.frame().unwrap();
+    let out = String::from_utf8(renderer.into_inner()).unwrap();
+    assert_eq!(out, "\x1b[4;2H●\x1b[1;1H\x1b[2KScore: 12");
+
+    let mut renderer = Renderer::new(vec![]).with_glyphs(|c| if c == 1 { '#' } else { '.' });
+    renderer.draw_panels([((-1, -1), 1), ((0, -1), 0)]).unwrap();
+    let out = String::from_utf8(renderer.into_inner()).unwrap();
+    assert!(out.ends_with("\x1b[2;1H#\x1b[2;2H.") || out.ends_with("\x1b[2;2H.\x1b[2;1H#"));
+}
+
+#[cfg(feature = "smt")]
+#[test]
+fn test_smt_encoding() {
+    use std::rc::Rc;
+    use crate::intcode::parse_code;
+    use crate::smt::{parse_model, query, solve, symbolic_outputs, Expr, SymbolicError};
+
+    // Outputs 3x + y and x < y
+    let code = parse_code("3,100,3,101,1002,100,3,102,1,102,101,102,4,102,7,100,101,103,4,103,99");
+    let outputs = symbolic_outputs(&code, 2, 1_000).unwrap();
+    assert_eq!(outputs.iter().map(|e| e.eval(&[4, 5])).collect::<Vec<_>>(), [Some(17), Some(1)]);
+    assert_eq!(outputs[1], Expr::Lt(Rc::new(Expr::Input(0)), Rc::new(Expr::Input(1))));
+    assert_eq!(query(&outputs, 2, &[-1]), "(set-logic QF_NIA)\n(declare-const in0 Int)\n(declare-const in1 Int)\n\
+        (assert (= (+ (* in0 3) in1) (- 1)))\n(check-sat)\n(get-value (in0 in1))\n");
+
+    assert_eq!(parse_model("sat\n((in0 (- 3))\n (in1 8))\n", 2).unwrap(), Some(vec![-3, 8]));
+    assert_eq!(parse_model("unsat\n", 2).unwrap(), None);
+    assert!(parse_model("sat\n((in0 1))", 2).is_err());
+
+    // Input-dependent control flow can't be encoded
+    let code = parse_code("3,9,1005,9,7,104,0,104,1,99");
+    assert_eq!(symbolic_outputs(&code, 1, 1_000), Err(SymbolicError::SymbolicJump { ip: 2 }));
+    assert_eq!(symbolic_outputs(&code, 0, 1_000), Err(SymbolicError::MissingInput { ip: 0 }));
+    let code = parse_code("109,170141183460469231731687303715884105727,204,1,99");
+    assert_eq!(symbolic_outputs(&code, 0, 1_000), Err(SymbolicError::Overflow { ip: 2 }));
+
+    // Doubles the input 200 times, sharing the subterms
+    let code = parse_code("3,100,1101,0,200,101,1,100,100,100,1001,101,-1,101,1005,101,6,4,100,99");
+    let outputs = symbolic_outputs(&code, 1, 10_000).unwrap();
+    assert_eq!((outputs[0].eval(&[0]), outputs[0].eval(&[1])), (Some(0), None));
+    let script = query(&outputs, 1, &[0]);
+    assert!(script.contains("(define-fun t0 () Int (+ in0 in0))\n(define-fun t1 () Int (+ t0 t0))\n"));
+    assert!(script.len() < 10_000);
+
+    // Any command speaking SMT-LIB over stdin works as a solver
+    let fake_solver = ["sh", "-c", "cat > /dev/null; echo sat; echo '((in0 2))'"];
+    assert_eq!(solve(&parse_code("3,0,4,0,99"), 1, &[2], 100, &fake_solver).unwrap(), Some(vec![2]));
+}
+
+#[test]
+fn test_arcade() {
+    use crate::aoc::arcade::{Arcade, FollowBall, Tile};
+
+    // Draws a wall, the ball, the paddle and a block, reads the joystick and
+    // uses it as the score. Address 0 is patched when inserting quarters.
+    let code = "1,0,0,100,104,0,104,0,104,1,104,2,104,3,104,4,104,1,104,3,104,3,\
+                104,5,104,5,104,2,3,101,104,-1,104,0,4,101,99";
+
+    let mut arcade = Arcade::new(IntcodeComputer::from(code));
+    assert!(arcade.run_frame());
+    assert_eq!(arcade.state().blocks(), 1);
+    assert_eq!(arcade.state().tiles[&(0, 0)], Tile::Wall);
+    assert_eq!((arcade.state().ball, arcade.state().paddle), (Some((2, 3)), Some((1, 3))));
+    assert_eq!(arcade.play(&mut FollowBall), 1);
+    assert!(arcade.is_finished());
+
+    let mut arcade = Arcade::new(IntcodeComputer::from(code));
+    arcade.insert_quarters();
+    let mut frames = 0;
+    assert_eq!(arcade.play_with(&mut |_: &_| -1, |_| frames += 1), -1);
+    assert_eq!(frames, 2);
+}
+
+#[test]
+fn test_painting_robot() {
+    use crate::aoc::painting::{PaintingRobot, WHITE};
+
+    // Replays the outputs from the puzzle example, storing the camera
+    // readings from address 100 onwards
+    let pairs = [(1, 0), (0, 0), (1, 0), (1, 0), (0, 1), (1, 0), (1, 0)];
+    let mut code = vec![];
+    for (i, (color, turn)) in pairs.into_iter().enumerate() {
+        code.extend([3, 100 + i as Int, 104, color, 104, turn]);
+    }
+    code.push(99);
+
+    let mut robot = PaintingRobot::new(IntcodeComputer::new(&code));
+    robot.run();
+    assert_eq!(robot.painted_count(), 6);
+    assert_eq!(robot.position(), (0, -1));
+    assert_eq!(robot.render(), "..#\n..#\n##.");
+
+    // The starting panel isn't painted until the robot paints it
+    let mut robot = PaintingRobot::new(IntcodeComputer::new(&[104, 0, 104, 0, 99])).with_start_color(WHITE);
+    assert_eq!(robot.color_at((0, 0)), WHITE);
+    assert_eq!(robot.painted_count(), 0);
+    assert_eq!(robot.render(), "#");
+    robot.run();
+    assert_eq!((robot.color_at((0, 0)), robot.painted_count()), (0, 1));
+    assert_eq!(robot.render(), "");
+
+    let mut robot = PaintingRobot::new(IntcodeComputer::new(&code)).with_start_color(WHITE);
+    robot.run();
+    assert_eq!(robot.painted_count(), 6);
+}
+
+#[test]
+fn test_ocr() {
+    use crate::aoc::ocr::ocr;
+
+    let grid = "\
+        .##....##.#...#\n\
+        #..#....#.#...#\n\
+        #.......#..#.#.\n\
+        #.......#...#..\n\
+        #..#.#..#...#..\n\
+        .##...##....#..\n";
+    assert_eq!(ocr(grid), "CJY");
+
+    // Blank rows and other pixel styles, unknown letters
+    let grid = "\n█  █  ██ \n█  █ █  █\n████ █   \n█  █ █ ██\n█  █ █  █\n█  █  ███\n\n";
+    assert_eq!(ocr(grid), "HG");
+    assert_eq!(ocr("#\n#\n#\n#\n#\n#"), "?");
+    assert_eq!(ocr("...\n..."), "");
+}
+
+#[test]
+fn test_droid_explorer() {
+    use crate::aoc::droid::DroidExplorer;
+
+    // Looks up the next position in a maze stored at address 300, 5 cells
+    // wide, keeping the droid position at addresses 200 and 201
+    let code = "3,202,1001,200,0,203,1001,201,0,204,1008,202,2,207,1,204,207,204,1008,202,1,207,\
+                1002,207,-1,207,1,204,207,204,1008,202,4,207,1,203,207,203,1008,202,3,207,1002,207,\
+                -1,207,1,203,207,203,1002,204,5,205,1,205,203,205,1001,205,300,205,9,205,1201,0,0,\
+                206,1002,205,-1,207,9,207,4,206,1006,206,0,1001,203,0,200,1001,204,0,201,1105,1,0";
+    let maze = ["#####", "#.#O#", "#...#", "#####"];
+
+    let mut comp = IntcodeComputer::from(code);
+    comp.write_at(200, 1);
+    comp.write_at(201, 1);
+    for (y, row) in maze.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            comp.write_at(300 + 5 * y as Int + x as Int, match c { '#' => 0, '.' => 1, _ => 2 });
+        }
+    }
+
+    let result = DroidExplorer::new(comp).explore();
+    assert_eq!(result.oxygen, Some((2, 0)));
+    assert_eq!(result.shortest_path_to_oxygen(), Some(4));
+    assert_eq!(result.fill_time(), Some(4));
+    assert_eq!(result.render(), " # # \n#D#O#\n#...#\n ### ");
+}
+
+#[test]
+fn test_scaffold() {
+    use crate::aoc::scaffold::{compress, path_string, Scaffold};
+
+    let camera = "\
+        ..#..........\n\
+        ..#..........\n\
+        #######...###\n\
+        #.#...#...#.#\n\
+        #############\n\
+        ..#...#...#..\n\
+        ..#####...^..\n";
+    let scaffold = Scaffold::parse(camera);
+    assert_eq!(scaffold.intersections(), vec![(2, 2), (2, 4), (6, 4), (10, 4)]);
+    assert_eq!(scaffold.alignment_sum(), 76);
+
+    let camera = "\
+        #######...#####\n\
+        #.....#...#...#\n\
+        #.....#...#...#\n\
+        ......#...#...#\n\
+        ......#...###.#\n\
+        ......#.....#.#\n\
+        ^########...#.#\n\
+        ......#.#...#.#\n\
+        ......#########\n\
+        ........#...#..\n\
+        ....#########..\n\
+        ....#...#......\n\
+        ....#...#......\n\
+        ....#...#......\n\
+        ....#####......\n";
+    let path = Scaffold::parse(camera).path();
+    assert_eq!(path_string(&path), "R,8,R,8,R,4,R,4,R,8,L,6,L,2,R,4,R,4,R,8,R,8,R,8,L,6,L,2");
+
+    // Any split is fine as long as it expands back to the path
+    let routines = compress(&path).unwrap();
+    let expanded: Vec<&str> = routines.main.split(',').map(|call| match call {
+        "A" => routines.functions[0].as_str(),
+        "B" => routines.functions[1].as_str(),
+        _ => routines.functions[2].as_str(),
+    }).collect();
+    assert_eq!(expanded.join(","), path_string(&path));
+    assert!(routines.functions.iter().chain([&routines.main]).all(|r| r.len() <= 20));
+
+    // Routines are fed as lines of ASCII input
+    let mut comp = IntcodeComputer::from("99");
+    routines.feed(&mut comp, false);
+    let total = routines.main.len() + routines.functions.iter().map(String::len).sum::<usize>();
+    assert_eq!(comp.pending_inputs(), total + 6);
+}
+
+#[test]
+fn test_beam_scanner() {
+    use crate::aoc::beam::BeamScanner;
+
+    // The beam covers every point where x <= 2y and y <= 2x
+    let code = "3,100,3,101,1002,101,2,102,7,102,100,103,1002,100,2,104,7,104,101,105,1,103,105,106,1008,106,0,107,4,107,99";
+    let comp = IntcodeComputer::from(code);
+    let in_beam = |x: Int, y: Int| x <= 2 * y && y <= 2 * x;
+
+    let mut scanner = BeamScanner::new(comp);
+    assert!(scanner.query(3, 4));
+    assert!(!scanner.query(1, 3));
+    assert_eq!(scanner.row_span(0), Some((0, 0)));
+    assert_eq!(scanner.row_span(7), Some((4, 14)));
+
+    let expected = (0..50).flat_map(|y| (0..50).map(move |x| (x, y))).filter(|&(x, y)| in_beam(x, y)).count();
+    assert_eq!(scanner.count_in(50, 50), expected);
+
+    // Cached queries don't run the program again
+    let queries = scanner.queries();
+    scanner.count_in(50, 50);
+    assert_eq!(scanner.queries(), queries);
+
+    let (x, y) = scanner.fit_square(10);
+    assert!(in_beam(x, y + 9) && in_beam(x + 9, y));
+    assert!(!in_beam(x - 1, y + 9) || !in_beam(x + 8, y));
+    assert_eq!((x, y), (9, 9));
+}
+
+#[test]
+fn test_springscript() {
+    use crate::aoc::springscript::{and, not, or, Mode, Reg, Springscript, SpringscriptError, Writable};
+
+    // Jump if there's a hole in A, B or C and ground in D
+    let script = Springscript::new(&[not(Reg::A, Writable::J), not(Reg::B, Writable::T), or(Reg::T, Writable::J)])
+        .then(not(Reg::C, Writable::T))
+        .then(or(Reg::T, Writable::J))
+        .then(and(Reg::D, Writable::J));
+    assert_eq!(script.to_ascii(Mode::Walk).unwrap(),
+               "NOT A J\nNOT B T\nOR T J\nNOT C T\nOR T J\nAND D J\nWALK\n");
+
+    let mut comp = IntcodeComputer::from("99");
+    script.feed(&mut comp, Mode::Run).unwrap();
+    assert_eq!(comp.pending_inputs(), script.to_ascii(Mode::Run).unwrap().len());
+
+    // Validation
+    let script = script.then(and(Reg::H, Writable::J));
+    assert_eq!(script.to_ascii(Mode::Walk), Err(SpringscriptError::NeedsRunMode(Reg::H)));
+    assert!(script.validate(Mode::Run).is_ok());
+    let script = Springscript::new(&[or(Reg::D, Writable::J); 16]);
+    assert_eq!(script.validate(Mode::Run), Err(SpringscriptError::TooManyInstructions(16)));
+}
+
+#[test]
+fn test_springscript_search() {
+    use crate::aoc::springscript::{alphabet, search, Mode};
+
+    // Only accepts programs starting with a NOT
+    let code = "3,100,1008,100,78,101,1005,101,12,104,33,99,104,1234,99";
+    let comp = IntcodeComputer::from(code);
+    let (script, damage) = search(&comp, Mode::Walk, 2).unwrap();
+    assert_eq!(script.to_ascii(Mode::Walk).unwrap(), "NOT A J\nWALK\n");
+    assert_eq!(damage, 1234);
+
+    assert_eq!(alphabet(Mode::Walk).len(), 36);
+    assert_eq!(alphabet(Mode::Run).len(), 66);
+    assert!(search(&IntcodeComputer::from("104,33,99"), Mode::Walk, 2).is_none());
+}
+
+#[test]
+fn test_amplifier_chain() {
+    use crate::aoc::amplifiers::{max_chain_signal, run_amplifier_chain};
+
+    let examples = [
+        ("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0", [4, 3, 2, 1, 0], 43210),
+        ("3,23,3,24,1002,24,10,24,1002,23,-1,23,101,5,23,23,1,24,23,23,4,23,99,0,0", [0, 1, 2, 3, 4], 54321),
+        ("3,31,3,32,1002,32,10,32,1001,31,-2,31,1007,31,0,33,1002,33,7,33,1,33,31,31,1,32,31,31,4,31,99,0,0,0",
+         [1, 0, 4, 3, 2], 65210),
+    ];
+
+    for (code, phases, signal) in examples {
+        let program = IntcodeComputer::from(code);
+        assert_eq!(run_amplifier_chain(&program, &phases, 0), signal);
+        assert_eq!(max_chain_signal(&program, &[0, 1, 2, 3, 4]), Some((signal, phases.to_vec())));
+        assert_eq!(max_chain_signal(&program, &[]), None);
+    }
+}
+
+#[test]
+fn test_amplifier_feedback_loop() {
+    use crate::aoc::amplifiers::{max_feedback_signal, run_feedback_loop};
+
+    let examples = [
+        ("3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5",
+         [9, 8, 7, 6, 5], 139629729),
+        ("3,52,1001,52,-5,52,3,53,1,52,56,54,1007,54,5,55,1005,55,26,1001,54,-5,54,1105,1,12,1,53,54,53,\
+          1008,54,0,55,1001,55,1,55,2,53,55,53,4,53,1001,56,-1,56,1005,56,6,99,0,0,0,0,10",
+         [9, 7, 8, 5, 6], 18216),
+    ];
+
+    for (code, phases, signal) in examples {
+        let program = IntcodeComputer::from(code);
+        assert_eq!(run_feedback_loop(&program, &phases), signal);
+        assert_eq!(max_feedback_signal(&program, &[5, 6, 7, 8, 9]), Some((signal, phases.to_vec())));
+        assert_eq!(max_feedback_signal(&program, &[]), None);
+    }
+    assert!(std::panic::catch_unwind(|| run_feedback_loop(&IntcodeComputer::from("3,0,4,0,99"), &[])).is_err());
+}
+
+#[test]
+fn test_permutation_search() {
+    use crate::search::{best_permutation, par_best_permutation, permutations};
+
+    assert_eq!(permutations(&[1, 2, 3]), vec![
+        vec![1, 2, 3], vec![1, 3, 2], vec![2, 1, 3], vec![2, 3, 1], vec![3, 1, 2], vec![3, 2, 1],
+    ]);
+    assert_eq!(permutations::<Int>(&[]), vec![vec![]]);
+
+    // Largest number made of the digits, ties go to the first permutation
+    let score = |p: &[Int]| p.iter().fold(0, |acc, d| acc * 10 + d);
+    let digits = [3, 1, 4, 1, 5, 9, 2];
+    assert_eq!(best_permutation(&digits, score), Some((9543211, vec![9, 5, 4, 3, 2, 1, 1])));
+    assert_eq!(par_best_permutation(&digits, score), best_permutation(&digits, score));
+    assert_eq!(best_permutation(&digits, |_| 0).unwrap().1, digits.to_vec());
+    assert_eq!(par_best_permutation(&digits, |_| 0).unwrap().1, digits.to_vec());
+}
+
+#[test]
+fn test_spawn() {
+    // Doubles every input, forever
+    let doubler = IntcodeComputer::from("3,9,1002,9,2,9,4,9,1105,1,0");
+
+    // Two machines chained through their channels
+    let first = doubler.clone().spawn();
+    let second = doubler.spawn();
+    let forward = second.input.clone();
+    for val in [1, 2, 3] {
+        first.input.send(val).unwrap();
+        forward.send(first.output.recv().unwrap()).unwrap();
+    }
+    let outputs: Vec<Int> = (0..3).map(|_| second.output.recv().unwrap()).collect();
+    assert_eq!(outputs, [4, 8, 12]);
+
+    assert_eq!(first.join().err(), Some(IntcodeError::NoInput { ip: 0 }));
+    drop(forward);
+    assert_eq!(second.join().err(), Some(IntcodeError::NoInput { ip: 0 }));
+
+    // Finished machines are handed back
+    let echo = IntcodeComputer::from("3,0,4,0,99").spawn();
+    echo.input.send(42).unwrap();
+    assert_eq!(echo.output.recv(), Ok(42));
+    assert!(echo.join().unwrap().is_finished());
+}
+
+#[test]
+fn test_lockstep() {
+    use crate::executor::{Lockstep, MachineError};
+
+    // A ring of two doublers, seeded with a 1, each stopping after 3 outputs
+    let doubler = IntcodeComputer::from("3,20,1002,20,2,20,4,20,1001,21,1,21,1007,21,3,22,1005,22,0,99");
+    let mut seeded = doubler.clone();
+    seeded.input(1);
+    let mut lockstep = Lockstep::new(vec![seeded, doubler.clone()]);
+    lockstep.connect(0, 1);
+    lockstep.connect(1, 0);
+    let outputs = lockstep.run().unwrap();
+    assert_eq!(outputs, [(0, 2), (1, 4), (0, 8), (1, 16), (0, 32), (1, 64)]);
+    assert!(lockstep.machines().iter().all(IntcodeComputer::is_finished));
+
+    // Bigger slices take fewer rounds, with the same result
+    let mut seeded = doubler.clone();
+    seeded.input(1);
+    let mut sliced = Lockstep::new(vec![seeded, doubler]).with_slice(100);
+    sliced.connect(0, 1);
+    sliced.connect(1, 0);
+    assert_eq!(sliced.run().unwrap(), outputs);
+    assert!(sliced.rounds() < lockstep.rounds());
+
+    let mut failing = Lockstep::new(vec![IntcodeComputer::from("99"), IntcodeComputer::from("42")]);
+    let error = MachineError { machine: 1, error: IntcodeError::UnknownOpcode { ip: 0, opcode: 42 } };
+    assert_eq!(failing.run(), Err(error));
+}
+
+#[test]
+fn test_actor() {
+    use crate::process::{ActorEvent, ActorMessage, IntcodeActor};
+
+    // Adds up its inputs at address 100, outputting the sum after each one,
+    // and stops when given a 0
+    let code = "3,101,1006,101,15,1,100,101,100,4,100,1105,1,0,0,99";
+    let actor = IntcodeActor::start(IntcodeComputer::from(code));
+    actor.push_input(5);
+    assert_eq!(actor.events().recv(), Ok(ActorEvent::Output(5)));
+
+    // Paused actors still take inputs and answer, but don't run
+    assert!(actor.send(ActorMessage::Pause));
+    actor.push_input(7);
+    let state = actor.state().unwrap();
+    assert_eq!(state.read_at(100), 5);
+    assert_eq!(state.pending_inputs(), 1);
+    assert!(actor.send(ActorMessage::Resume));
+    assert_eq!(actor.events().recv(), Ok(ActorEvent::Output(12)));
+
+    actor.push_input(0);
+    assert_eq!(actor.events().recv(), Ok(ActorEvent::Finished));
+    let comp = actor.terminate();
+    assert!(comp.is_finished());
+    assert_eq!(comp.read_at(100), 12);
+
+    // Errors are reported as events
+    let actor = IntcodeActor::start(IntcodeComputer::from("42"));
+    assert_eq!(actor.events().recv(), Ok(ActorEvent::Failed(IntcodeError::UnknownOpcode { ip: 0, opcode: 42 })));
+    actor.terminate();
+}
+
+#[test]
+fn test_run_batch() {
+    use crate::parallel::run_batch;
+
+    // Outputs the square of its input
+    let comp = IntcodeComputer::from("3,9,2,9,9,9,4,9,99,0");
+    let squares = run_batch(&comp, (0..100).map(|x| [x]));
+    assert_eq!(squares, (0..100).map(|x| vec![x * x]).collect::<Vec<_>>());
+    assert!(run_batch(&comp, Vec::<Vec<Int>>::new()).is_empty());
+
+    // Day 19 style grid scan: outputs whether x > y
+    let comp = IntcodeComputer::from("3,11,3,12,7,12,11,13,4,13,99,0,0,0");
+    let points: Vec<[Int; 2]> = (0..5).flat_map(|y| (0..5).map(move |x| [x, y])).collect();
+    let affected: Int = run_batch(&comp, points).iter().map(|out| out[0]).sum();
+    assert_eq!(affected, 10);
+}
+
+#[test]
+fn test_parallel_search() {
+    use crate::parallel::parallel_search;
+    use crate::search::Setup;
+
+    // Outputs the product of addresses 7 and 8
+    let comp = IntcodeComputer::from("2,7,8,9,4,9,99,0,0,0");
+    let candidates: Vec<Setup> = (0..20).flat_map(|a| (0..20).map(move |b| Setup {
+        patches: vec![(7, a), (8, b)],
+        ..Default::default()
+    })).collect();
+    assert_eq!(parallel_search(&comp, &candidates, None, |out| out == [48]), Some((3 * 20 + 16, vec![48])));
+    assert_eq!(parallel_search(&comp, &candidates, None, |out| out == [1_000]), None);
+    assert_eq!(parallel_search(&comp, &[], None, |_| true), None);
+
+    // Outputs its input, or loops forever on 0. Runs that never finish are
+    // given up on once an earlier candidate matches, or after the step limit
+    let comp = IntcodeComputer::from("3,20,1006,20,2,4,20,99");
+    let candidates: Vec<Setup> = [5, 0, 0, 0].map(|input| Setup { inputs: vec![input], ..Default::default() }).to_vec();
+    assert_eq!(parallel_search(&comp, &candidates, None, |_| true), Some((0, vec![5])));
+    assert_eq!(parallel_search(&comp, &candidates[1..], Some(1_000), |_| true), None);
+}
+
+#[test]
+fn test_input_search() {
+    use crate::search::{InputSearch, Setup, Strategy};
+
+    // Outputs the square of its input
+    let comp = IntcodeComputer::from("3,9,2,9,9,9,4,9,99,0");
+    let search = InputSearch::new(&comp, |x| Setup { inputs: vec![x], ..Default::default() });
+    assert_eq!(search.outputs(7), Some(vec![49]));
+    for strategy in [Strategy::Exhaustive, Strategy::Parallel, Strategy::Bisect] {
+        assert_eq!(search.find(0..=1_000, strategy, |out| out[0] >= 1_000), Some(32));
+        assert_eq!(search.find(0..=10, strategy, |out| out[0] >= 1_000), None);
+    }
+    assert_eq!(search.find(-20..=20, Strategy::Parallel, |out| out == [144]), Some(-12));
+
+    // Outputs its input, over the whole range of values
+    let comp = IntcodeComputer::from("3,5,4,5,99,0");
+    let search = InputSearch::new(&comp, |x| Setup { inputs: vec![x], ..Default::default() });
+    assert_eq!(search.find(Int::MIN..=Int::MAX, Strategy::Bisect, |out| out[0] >= -3), Some(-3));
+    assert_eq!(search.find(Int::MIN..=Int::MAX, Strategy::Bisect, |_| true), Some(Int::MIN));
+
+    // Patches, with runs that never finish being rejected
+    let comp = IntcodeComputer::from("1105,0,0,104,1,99");
+    let search = InputSearch::new(&comp, |x| Setup { patches: vec![(1, x)], ..Default::default() }).with_step_limit(100);
+    assert_eq!(search.outputs(1), None);
+    assert_eq!(search.find(0..=5, Strategy::Exhaustive, |out| out.is_empty()), None);
+    assert_eq!(search.find(0..=5, Strategy::Exhaustive, |out| out == [1]), Some(0));
+}
+
+// Computer 1 sends (5, 7) to computer 0, which forwards every packet it
+// receives to the NAT, adding 1 to Y while it's below 10.
+const NETWORK_CODE: &str = "3,100,1005,100,33,3,101,1008,101,-1,103,1005,103,5,3,102,1007,102,10,103,\
+                            1,102,103,102,104,255,4,101,4,102,1105,1,5,104,0,104,5,104,7,3,101,1105,1,39";
+
+#[test]
+fn test_network() {
+    use crate::aoc::network::{Network, Packet};
+
+    let program = IntcodeComputer::from(NETWORK_CODE);
+    let mut network = Network::new(&program, 2);
+    let report = network.tick();
+    assert_eq!(report.sent, vec![Packet { src: 1, dest: 0, x: 5, y: 7 }]);
+    assert!(!report.idle);
+    assert_eq!(network.first_nat_packet(), 8);
+    assert_eq!(network.nat(), Some((5, 8)));
+
+    let mut network = Network::new(&program, 2);
+    assert_eq!(network.run_nat(), 10);
+}
+
+#[test]
+fn test_shared_memory() {
+    use crate::executor::Lockstep;
+
+    // Adds 1 to the shared counter at address 100, three times
+    let adder = IntcodeComputer::from("1001,100,1,100,1001,100,1,100,1001,100,1,100,99");
+    // Waits for the counter to reach 6, then outputs it
+    let watcher = IntcodeComputer::from("1008,100,6,101,1006,101,0,4,100,99");
+
+    let mut lockstep = Lockstep::new(vec![adder.clone(), adder, watcher]);
+    let region = lockstep.share(100, 1, &[0, 1, 2]);
+    assert_eq!(lockstep.run().unwrap(), [(2, 6)]);
+    assert_eq!(lockstep.shared(region), [6]);
+
+    // Within a round, later machines see the writes of earlier ones
+    let mut lockstep = Lockstep::new(vec![IntcodeComputer::from("1101,7,0,100,99"), IntcodeComputer::from("4,100,99")]);
+    lockstep.share(100, 1, &[0, 1]);
+    assert_eq!(lockstep.step().unwrap().outputs, [(1, 7)]);
+
+    // Regions need machines, and addresses that exist
+    let halted = || Lockstep::new(vec![IntcodeComputer::from("99")]);
+    assert!(std::panic::catch_unwind(|| halted().share(100, 1, &[])).is_err());
+    assert!(std::panic::catch_unwind(|| halted().share(Int::MAX, 2, &[0])).is_err());
+}
+
+#[test]
+fn test_scheduler() {
+    use crate::executor::{Router, Scheduler};
+
+    // Day 23 on top of the scheduler
+    struct Packets {
+        nat: Option<(Int, Int)>,
+    }
+
+    impl Router for Packets {
+        fn route(&mut self, _src: usize, outputs: &[Int]) -> Vec<(usize, Int)> {
+            let mut deliveries = vec![];
+            for packet in outputs.chunks(3) {
+                match *packet {
+                    [255, x, y] => self.nat = Some((x, y)),
+                    [dest, x, y] => deliveries.extend([(dest as usize, x), (dest as usize, y)]),
+                    _ => panic!("Incomplete packet"),
+                }
+            }
+            deliveries
+        }
+
+        fn idle_input(&mut self, _machine: usize) -> Option<Int> {
+            Some(-1)
+        }
+    }
+
+    let machines: Vec<IntcodeComputer> = (0..2).map(|addr| {
+        let mut comp = IntcodeComputer::from(NETWORK_CODE);
+        comp.input(addr);
+        comp
+    }).collect();
+    let mut scheduler = Scheduler::new(machines, Packets { nat: None }).with_max_outputs(3);
+    while scheduler.router().nat.is_none() {
+        assert!(scheduler.round().unwrap() > 0);
+    }
+    assert_eq!(scheduler.router().nat, Some((5, 8)));
+
+    // Closures work as routers too: a chain of three doublers
+    let doubler = IntcodeComputer::from("3,9,1002,9,2,9,4,9,99,0");
+    let mut first = doubler.clone();
+    first.input(3);
+    let mut last = None;
+    let mut scheduler = Scheduler::new(vec![first, doubler.clone(), doubler], |src: usize, out: &[Int]| {
+        if src == 2 {
+            last = out.first().copied();
+        }
+        out.iter().map(|&v| (src + 1, v)).collect()
+    });
+    scheduler.run().unwrap();
+    assert!(scheduler.machines().iter().all(IntcodeComputer::is_finished));
+    drop(scheduler);
+    assert_eq!(last, Some(24));
+}
+
+#[test]
+fn test_pipeline() {
+    use crate::executor::{pipe, Pipeline};
+
+    // Outputs every input plus one, until it reads a 0
+    let incr = IntcodeComputer::from("3,20,1006,20,14,1001,20,1,21,4,21,1105,1,0,99");
+    let source = IntcodeComputer::from("104,1,104,2,104,0,99");
+    assert_eq!(pipe(source.clone(), incr.clone()).unwrap(), [2, 3]);
+    assert_eq!(Pipeline::new(source).then(incr.clone()).then(incr).run().unwrap(), [3, 4]);
+
+    // Day 7's feedback loop, declared as a pipeline
+    let code = "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5";
+    let amp = |phase| {
+        let mut amp = IntcodeComputer::from(code);
+        amp.input(phase);
+        amp
+    };
+    let pipeline = Pipeline::new(amp(9)).then(amp(8)).then(amp(7)).then(amp(6)).then(amp(5));
+    let outputs = pipeline.with_feedback().input(0).run().unwrap();
+    assert_eq!(outputs.last(), Some(&139629729));
+}
+
+#[test]
+fn test_topology() {
+    use crate::executor::{Channel, Topology};
+
+    // Day 23's example network, with the NAT as a host device
+    let mut nat = vec![];
+    let mut topology = Topology::new();
+    let machines: Vec<usize> = (0..2).map(|addr| {
+        let mut comp = IntcodeComputer::from(NETWORK_CODE);
+        comp.input(addr);
+        topology.add_machine(comp)
+    }).collect();
+    let nat_node = topology.add_host(|packet: &[Int]| {
+        nat.extend_from_slice(packet);
+        vec![]
+    });
+    topology.connect(machines[1], machines[0], Channel::Packet { address: 0, len: 3 })
+            .connect(machines[0], nat_node, Channel::Packet { address: 255, len: 3 });
+    topology.run().unwrap();
+    assert_eq!(topology.outputs(machines[0]), [255, 5, 8]);
+    assert!(topology.machine(nat_node).is_none());
+    drop(topology);
+    assert_eq!(nat, [5, 8]);
+
+    // A host feeding a machine through a transforming channel, and back
+    let mut topology = Topology::new();
+    let mut pending = vec![1, 2, 3];
+    let source = topology.add_host(move |_: &[Int]| std::mem::take(&mut pending));
+    let echo = topology.add_machine(IntcodeComputer::from("3,7,4,7,1105,1,0,0"));
+    let sink = topology.add_host(|_: &[Int]| vec![]);
+    topology.connect(source, echo, Channel::Map(|v| v * 10)).connect(echo, sink, Channel::Stream);
+    topology.run().unwrap();
+    assert_eq!(topology.outputs(echo), [10, 20, 30]);
+
+    // Packets need an address, and a node splits its output only one way
+    let connect = |first: usize, second: usize| {
+        let mut topology = Topology::new();
+        let src = topology.add_machine(IntcodeComputer::from("99"));
+        let dst = topology.add_machine(IntcodeComputer::from("99"));
+        topology.connect(src, dst, Channel::Packet { address: 0, len: first })
+                .connect(src, dst, Channel::Packet { address: 1, len: second });
+    };
+    assert!(std::panic::catch_unwind(|| connect(0, 0)).is_err());
+    assert!(std::panic::catch_unwind(|| connect(3, 2)).is_err());
+    connect(2, 2);
+}
+
+#[test]
+fn test_network_idle_detection() {
+    use crate::aoc::network::{IdleDetector, Network};
+
+    let mut detector = IdleDetector::new(2, 2);
+    detector.record(0, false, false);
+    detector.record(1, false, false);
+    assert!(!detector.is_idle());
+    detector.record(0, false, false);
+    detector.record(1, false, true);
+    assert!(!detector.is_idle());
+    detector.record(1, false, false);
+    detector.record(1, false, false);
+    assert!(detector.is_idle());
+    detector.record(0, true, false);
+    assert!(!detector.is_idle());
+
+    // The network only becomes idle after enough empty reads
+    let program = IntcodeComputer::from(NETWORK_CODE);
+    let mut network = Network::new(&program, 2).with_idle_threshold(3);
+    let idle_ticks: Vec<bool> = (0..6).map(|_| network.tick().idle).collect();
+    assert_eq!(idle_ticks, vec![false, false, false, false, true, true]);
+    assert_eq!(network.run_nat(), 10);
+}
+
+#[test]
+fn test_network_packet_log() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::aoc::network::{Network, NAT_ADDRESS};
+
+    let program = IntcodeComputer::from(NETWORK_CODE);
+    let mut network = Network::new(&program, 2);
+    let seen = Rc::new(RefCell::new(vec![]));
+    let seen_hook = Rc::clone(&seen);
+    network.on_packet(move |p| seen_hook.borrow_mut().push((p.packet.src, p.packet.dest)));
+    network.enable_log();
+    network.run_nat();
+
+    let log = network.packet_log();
+    assert_eq!(seen.borrow().len(), log.len());
+    assert_eq!(seen.borrow()[..2], [(1, 0), (0, NAT_ADDRESS)]);
+    assert!(log.windows(2).all(|w| w[0].tick <= w[1].tick));
+    assert!(log.iter().any(|p| p.packet.src == NAT_ADDRESS && p.packet.dest == 0));
+
+    let mut dump = vec![];
+    network.dump_log(&mut dump).unwrap();
+    let dump = String::from_utf8(dump).unwrap();
+    assert_eq!(dump.lines().count(), log.len());
+    assert_eq!(dump.lines().next(), Some("0 1 -> 0: 5,7"));
+}
+
+// A tiny ship for the day 25 explorer, in the same format as the puzzle.
+#[derive(Clone)]
+struct MockShip {
+    room: &'static str,
+    floor: Vec<(&'static str, &'static str)>,
+    inventory: Vec<&'static str>,
+}
+
+const SHIP_DOORS: &[(&str, &str, &str)] = &[
+    ("Hull Breach", "north", "Kitchen"), ("Kitchen", "south", "Hull Breach"),
+    ("Hull Breach", "east", "Lab"), ("Lab", "west", "Hull Breach"),
+    ("Lab", "north", "Security Checkpoint"), ("Security Checkpoint", "south", "Lab"),
+    ("Security Checkpoint", "east", "Pressure-Sensitive Floor"),
+];
+
+impl MockShip {
+    fn describe(&self, room: &str) -> String {
+        let mut text = format!("\n\n\n== {room} ==\nA room.\n\nDoors here lead:\n");
+        for (_, door, _) in SHIP_DOORS.iter().filter(|d| d.0 == room) {
+            text += &format!("- {door}\n");
+        }
+        let items: Vec<_> = self.floor.iter().filter(|i| i.0 == room).collect();
+        if !items.is_empty() {
+            text += "\nItems here:\n";
+            for (_, item) in items {
+                text += &format!("- {item}\n");
+            }
+        }
+        text + "\nCommand?\n"
+    }
+}
+
+impl crate::aoc::adventure::TextGame for MockShip {
+    fn start(&mut self) -> Option<String> {
+        Some(self.describe(self.room))
+    }
+
+    fn send(&mut self, command: &str) -> Option<String> {
+        if let Some(item) = command.strip_prefix("take ") {
+            let pos = self.floor.iter().position(|&(r, i)| r == self.room && i == item)?;
+            let (_, item) = self.floor.remove(pos);
+            match item {
+                "infinite loop" => return None,
+                "molten lava" => return Some("You melt!\n".to_owned()),
+                _ => self.inventory.push(item),
+            }
+        } else if let Some(item) = command.strip_prefix("drop ") {
+            let pos = self.inventory.iter().position(|&i| i == item)?;
+            self.floor.push((self.room, self.inventory.remove(pos)));
+        } else if command == "inv" {
+            return Some("Items in your inventory:\n\nCommand?\n".to_owned());
+        } else {
+            let &(_, _, next) = SHIP_DOORS.iter().find(|d| d.0 == self.room && d.1 == command)?;
+            if next != "Pressure-Sensitive Floor" {
+                self.room = next;
+                return Some(self.describe(next));
+            }
+            let mut held = self.inventory.clone();
+            held.sort_unstable();
+            if held == ["coin", "mug"] {
+                return Some(format!("== {next} ==\nYou should be able to get in by typing 1234 on the keypad.\n"));
+            }
+            return Some(format!("== {next} ==\nAlert! Droids are heavier than the detected value!\n{}", self.describe(self.room)));
+        }
+        Some("Ok.\n\nCommand?\n".to_owned())
+    }
+}
+
+#[test]
+fn test_adventure() {
+    use crate::aoc::adventure::{parse_room, AutoExplorer};
+
+    let ship = MockShip {
+        room: "Hull Breach",
+        floor: vec![("Kitchen", "mug"), ("Kitchen", "molten lava"), ("Lab", "infinite loop"),
+                    ("Lab", "wreath"), ("Security Checkpoint", "coin")],
+        inventory: vec![],
+    };
+
+    let room = parse_room(&ship.describe("Kitchen")).unwrap();
+    assert_eq!(room.name, "Kitchen");
+    assert_eq!(room.description, "A room.");
+    assert_eq!(room.doors, ["south"]);
+    assert_eq!(room.items, ["mug", "molten lava"]);
+
+    // Without a blacklist, dangerous items are detected by trying them out
+    let mut explorer = AutoExplorer::new(ship.clone()).with_blacklist::<&str>(&[]);
+    assert_eq!(explorer.solve().as_deref(), Some("1234"));
+    assert_eq!(explorer.inventory(), ["mug", "wreath", "coin"]);
+
+    let mut explorer = AutoExplorer::new(ship).with_blacklist(&["mug"]);
+    assert_eq!(explorer.solve(), None);
+}
@@ -0,0 +1,202 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::{IntcodeComputer, IntcodeError, Int, RunResult};
+use crate::intcode::Opcodes;
+use crate::watch::WatchList;
+
+// Execution traces with a canonical, stable text form, meant to be stored as
+// golden files so that changes to the interpreter can't silently alter the
+// semantics of known programs.
+//
+// The format has one line per event:
+//
+//     <ip>: <MNEMONIC> <raw instruction words, comma separated>
+//     < <value consumed by IN>
+//     > <value produced by OUT>
+//     halt
+//     error: <description>
+//
+// For computers with symbols, instructions at a label are preceded by it:
+//
+//     <label>:
+//
+// Traces recorded with watch expressions also have a line for the initial
+// value of every watch, and another one whenever it changes after a step:
+//
+//     = <name>: <value, or ? if it can't be evaluated>
+
+// Set this environment variable to (re)write golden files instead of comparing.
+pub const BLESS_VAR: &str = "INTCODE_BLESS";
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TraceEvent {
+    Exec { ip: Int, opcode: u8, words: Vec<Int> },
+    Input(Int),
+    Output(Int),
+    Halt,
+    Error(IntcodeError),
+    Watch { name: String, value: Option<Int> },
+    Label(String),
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Trace {
+    pub events: Vec<TraceEvent>,
+}
+
+impl Trace {
+    // Runs the computer until it finishes or fails, recording every step.
+    // Running out of input ends the trace with an error line, so a step
+    // limit is the only thing needed to trace non-terminating programs.
+    pub fn record(comp: &mut IntcodeComputer) -> Self {
+        Self::record_watching(comp, &WatchList::new())
+    }
+
+    // Same as `record`, also tracking the values of the watches.
+    pub fn record_watching(comp: &mut IntcodeComputer, watches: &WatchList) -> Self {
+        let mut events = vec![];
+        let mut values = watches.sample(comp);
+        events.extend(values.iter().map(|(name, value)| TraceEvent::Watch { name: name.clone(), value: *value }));
+
+        loop {
+            let ip = comp.ip();
+            let (opcode, n_params) = match comp.peek_instruction() {
+                Ok(instr) => instr,
+                Err(e) => {
+                    events.push(TraceEvent::Error(e));
+                    break;
+                },
+            };
+            if let Some(label) = comp.symbols().and_then(|s| s.name_of(ip)) {
+                events.push(TraceEvent::Label(label.to_owned()));
+            }
+            let words = (0..=n_params as Int).map(|i| comp.read_at(ip + i)).collect();
+            let input = comp.next_input();
+
+            match comp.exec_next() {
+                Err(e) => {
+                    events.push(TraceEvent::Error(e));
+                    break;
+                },
+                Ok(ret) => {
+                    events.push(TraceEvent::Exec { ip, opcode, words });
+                    match (opcode, ret, input) {
+                        (Opcodes::IN, _, Some(val)) => events.push(TraceEvent::Input(val)),
+                        (_, Some(RunResult::Output(val)), _) => events.push(TraceEvent::Output(val)),
+                        _ => {},
+                    }
+
+                    let new_values = watches.sample(comp);
+                    for ((name, value), (_, old)) in new_values.iter().zip(&values) {
+                        if value != old {
+                            events.push(TraceEvent::Watch { name: name.clone(), value: *value });
+                        }
+                    }
+                    values = new_values;
+
+                    if opcode == Opcodes::END {
+                        events.push(TraceEvent::Halt);
+                        break;
+                    }
+                },
+            }
+        }
+
+        Self { events }
+    }
+
+    pub fn to_text(&self) -> String {
+        self.to_string()
+    }
+
+    // Compares against a golden file, or writes it if blessing is enabled.
+    pub fn check_golden(&self, path: impl AsRef<Path>) -> Result<(), GoldenError> {
+        let path = path.as_ref();
+        let actual = self.to_text();
+
+        if std::env::var_os(BLESS_VAR).is_some() {
+            return fs::write(path, actual).map_err(GoldenError::Io);
+        }
+
+        let expected = fs::read_to_string(path).map_err(GoldenError::Io)?;
+        compare(&expected, &actual)
+    }
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Exec { ip, opcode, words } => {
+                let words: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+                write!(f, "{ip}: {} {}", Opcodes::mnemonic(*opcode), words.join(","))
+            },
+            Self::Input(val) => write!(f, "< {val}"),
+            Self::Output(val) => write!(f, "> {val}"),
+            Self::Halt => write!(f, "halt"),
+            Self::Error(e) => write!(f, "error: {e}"),
+            Self::Watch { name, value: Some(val) } => write!(f, "= {name}: {val}"),
+            Self::Watch { name, value: None } => write!(f, "= {name}: ?"),
+            Self::Label(label) => write!(f, "{label}:"),
+        }
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for event in &self.events {
+            writeln!(f, "{event}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Io(io::Error),
+    // First differing line (1-based) and its contents on each side.
+    Mismatch { line: usize, expected: Option<String>, actual: Option<String> },
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Could not access golden file: {e}"),
+            Self::Mismatch { line, expected, actual } => {
+                let show = |s: &Option<String>| s.clone().unwrap_or_else(|| "<end of trace>".to_owned());
+                write!(f, "Trace differs from golden file at line {line}:\n  expected: {}\n  actual:   {}\n\
+                           (set {BLESS_VAR}=1 to update the golden file)", show(expected), show(actual))
+            },
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+pub fn compare(expected: &str, actual: &str) -> Result<(), GoldenError> {
+    let mut exp_lines = expected.lines();
+    let mut act_lines = actual.lines();
+
+    for line in 1.. {
+        match (exp_lines.next(), act_lines.next()) {
+            (None, None) => break,
+            (e, a) if e != a => return Err(GoldenError::Mismatch {
+                line,
+                expected: e.map(str::to_owned),
+                actual: a.map(str::to_owned),
+            }),
+            _ => {},
+        }
+    }
+
+    Ok(())
+}
+
+// Panicking version of `check_golden`, for use in tests.
+pub fn assert_golden(path: impl AsRef<Path>, comp: &mut IntcodeComputer) {
+    if let Err(e) = Trace::record(comp).check_golden(path) {
+        panic!("{e}");
+    }
+}
@@ -0,0 +1,283 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{IntcodeComputer, Int};
+use crate::symbols::is_identifier;
+
+// Watch expressions: small named formulas over the state of a computer,
+// evaluated whenever it's convenient for the host (at every stop, or after
+// every step). The syntax is
+//
+//     expr := sum [("==" | "!=" | "<" | "<=" | ">" | ">=") sum]
+//     sum  := prod {("+" | "-") prod}
+//     prod := unary {("*" | "/" | "%") unary}
+//     unary := "-" unary | atom
+//     atom := integer | "ip" | "rb" | symbol | "[" expr "]" | "mem[" expr "]" | "(" expr ")"
+//
+// where `[x]` is the memory cell at `x`, and a symbol is the address with
+// that name in the computer's symbol table. Comparisons give 0 or 1. Memory
+// is read directly, bypassing devices, so watching never disturbs the
+// program.
+// Evaluating gives `None` on overflow, division by zero or unknown symbols.
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BinOp {
+    Add, Sub, Mul, Div, Rem,
+    Eq, Ne, Lt, Le, Gt, Ge,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Expr {
+    Const(Int),
+    Ip,
+    RelBase,
+    Symbol(String),
+    Mem(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ParseError {
+    // Byte offset in the source.
+    pub pos: usize,
+    pub expected: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Expected {} at position {}", self.expected, self.pos)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Expr {
+    pub fn eval(&self, comp: &IntcodeComputer) -> Option<Int> {
+        match self {
+            Self::Const(val) => Some(*val),
+            Self::Ip => Some(comp.ip()),
+            Self::RelBase => Some(comp.relative_base()),
+            Self::Symbol(name) => comp.symbols()?.addr_of(name),
+            Self::Mem(addr) => Some(comp[addr.eval(comp)?]),
+            Self::Neg(e) => e.eval(comp)?.checked_neg(),
+            Self::Binary(op, a, b) => {
+                let (a, b) = (a.eval(comp)?, b.eval(comp)?);
+                match op {
+                    BinOp::Add => a.checked_add(b),
+                    BinOp::Sub => a.checked_sub(b),
+                    BinOp::Mul => a.checked_mul(b),
+                    BinOp::Div => a.checked_div(b),
+                    BinOp::Rem => a.checked_rem(b),
+                    BinOp::Eq => Some((a == b) as Int),
+                    BinOp::Ne => Some((a != b) as Int),
+                    BinOp::Lt => Some((a < b) as Int),
+                    BinOp::Le => Some((a <= b) as Int),
+                    BinOp::Gt => Some((a > b) as Int),
+                    BinOp::Ge => Some((a >= b) as Int),
+                }
+            },
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = ParseError;
+
+    fn from_str(src: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser { src: src.as_bytes(), pos: 0 };
+        let expr = parser.expr()?;
+        parser.skip_spaces();
+        match parser.pos == src.len() {
+            true => Ok(expr),
+            false => Err(parser.error("end of expression")),
+        }
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Rem => "%",
+            Self::Eq => "==",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        })
+    }
+}
+
+// Fully parenthesized, so it parses back to the same expression.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Const(val) if *val < 0 => write!(f, "({val})"),
+            Self::Const(val) => write!(f, "{val}"),
+            Self::Ip => write!(f, "ip"),
+            Self::RelBase => write!(f, "rb"),
+            Self::Symbol(name) => write!(f, "{name}"),
+            Self::Mem(addr) => write!(f, "[{addr}]"),
+            Self::Neg(e) => write!(f, "-{e}"),
+            Self::Binary(op, a, b) => write!(f, "({a} {op} {b})"),
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, expected: &'static str) -> ParseError {
+        ParseError { pos: self.pos, expected }
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.src.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    // Consumes the token if it comes next.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_spaces();
+        let found = self.src[self.pos..].starts_with(token.as_bytes());
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    fn expect(&mut self, token: &'static str) -> Result<(), ParseError> {
+        match self.eat(token) {
+            true => Ok(()),
+            false => Err(self.error(token)),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let lhs = self.sum()?;
+        // Two-character operators go first, so "<=" isn't taken for "<"
+        let ops = [("==", BinOp::Eq), ("!=", BinOp::Ne), ("<=", BinOp::Le), (">=", BinOp::Ge), ("<", BinOp::Lt), (">", BinOp::Gt)];
+        match ops.into_iter().find(|(token, _)| self.eat(token)) {
+            Some((_, op)) => Ok(Expr::Binary(op, Box::new(lhs), Box::new(self.sum()?))),
+            None => Ok(lhs),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.prod()?;
+        loop {
+            let op = match () {
+                _ if self.eat("+") => BinOp::Add,
+                _ if self.eat("-") => BinOp::Sub,
+                _ => return Ok(expr),
+            };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.prod()?));
+        }
+    }
+
+    fn prod(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.unary()?;
+        loop {
+            let op = match () {
+                _ if self.eat("*") => BinOp::Mul,
+                _ if self.eat("/") => BinOp::Div,
+                _ if self.eat("%") => BinOp::Rem,
+                _ => return Ok(expr),
+            };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.eat("-") {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, ParseError> {
+        if self.eat("(") {
+            let expr = self.expr()?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        if self.eat("[") || self.eat("mem[") {
+            let addr = self.expr()?;
+            self.expect("]")?;
+            return Ok(Expr::Mem(Box::new(addr)));
+        }
+
+        let start = self.pos;
+        while self.src.get(self.pos).is_some_and(|&c| c.is_ascii_alphanumeric() || c == b'_') {
+            self.pos += 1;
+        }
+        // Only ASCII was consumed, so this is valid UTF-8
+        let word = std::str::from_utf8(&self.src[start..self.pos]).unwrap();
+        match word {
+            "ip" => return Ok(Expr::Ip),
+            "rb" => return Ok(Expr::RelBase),
+            _ if is_identifier(word) => return Ok(Expr::Symbol(word.to_owned())),
+            _ => self.pos = start,
+        }
+
+        let start = self.pos;
+        while self.src.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+        // Only digits were consumed, so this is valid UTF-8
+        let digits = std::str::from_utf8(&self.src[start..self.pos]).unwrap();
+        digits.parse().map(Expr::Const).map_err(|_| ParseError { pos: start, expected: "a value" })
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Named watch expressions, kept in the order they were added.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct WatchList {
+    watches: Vec<(String, Expr)>,
+}
+
+impl WatchList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds a watch, replacing any other with the same name.
+    pub fn add(&mut self, name: &str, expr: &str) -> Result<(), ParseError> {
+        let expr = expr.parse()?;
+        match self.watches.iter_mut().find(|(n, _)| n == name) {
+            Some(watch) => watch.1 = expr,
+            None => self.watches.push((name.to_owned(), expr)),
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.watches.retain(|(n, _)| n != name);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Expr)> {
+        self.watches.iter().map(|(name, expr)| (name.as_str(), expr))
+    }
+
+    // Current value of every watch.
+    pub fn sample(&self, comp: &IntcodeComputer) -> Vec<(String, Option<Int>)> {
+        self.watches.iter().map(|(name, expr)| (name.clone(), expr.eval(comp))).collect()
+    }
+}